@@ -0,0 +1,194 @@
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use chrono::Utc;
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+
+use tungstenite::{Message, WebSocket};
+
+use serde_json::{from_str, json, Value};
+
+use log::info;
+
+// APIキーを格納する環境変数
+pub const API_KEY_ENV: &str = "BF_API_KEY";
+// APIシークレットを格納する環境変数
+pub const API_SECRET_ENV: &str = "BF_API_SECRET";
+
+// 認証リクエストのID
+const AUTH_REQUEST_ID: u64 = 0;
+
+// 認証リクエストのレスポンスを待つ時間
+pub const AUTH_TIMEOUT: Duration = Duration::from_secs(10);
+
+// 認証処理のエラー
+#[derive(Debug)]
+pub enum AuthError {
+    // 認証情報の読み込みに失敗した
+    Credentials(String),
+    // ソケットの読み書きに失敗した
+    Socket(tungstenite::Error),
+    // サーバーから認証を拒否された
+    Rejected(String),
+    // 待つ時間内にレスポンスを受信できなかった
+    Timeout,
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthError::Credentials(message) => write!(f, "invalid credentials: {}", message),
+            AuthError::Socket(error) => write!(f, "socket error: {}", error),
+            AuthError::Rejected(message) => write!(f, "auth rejected: {}", message),
+            AuthError::Timeout => write!(f, "auth timed out"),
+        }
+    }
+}
+
+impl std::error::Error for AuthError {}
+
+// プライベートチャンネルの認証に用いるAPIキーとシークレット
+#[derive(Clone)]
+pub struct Credentials {
+    api_key: String,
+    api_secret: String,
+}
+
+impl fmt::Debug for Credentials {
+    // シークレットはログに出さない
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Credentials")
+            .field("api_key", &self.api_key)
+            .field("api_secret", &"***")
+            .finish()
+    }
+}
+
+impl Credentials {
+    pub fn new(api_key: &str, api_secret: &str) -> Self {
+        Credentials {
+            api_key: api_key.to_string(),
+            api_secret: api_secret.to_string(),
+        }
+    }
+
+    // 環境変数から認証情報を取得する(未設定の場合はNone)
+    pub fn from_env() -> Option<Self> {
+        match (env::var(API_KEY_ENV), env::var(API_SECRET_ENV)) {
+            (Ok(api_key), Ok(api_secret)) if !api_key.is_empty() && !api_secret.is_empty() => {
+                Some(Credentials::new(&api_key, &api_secret))
+            }
+            _ => None,
+        }
+    }
+
+    // JSONファイル({"api_key": "...", "api_secret": "..."})から認証情報を取得する
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, AuthError> {
+        let text = fs::read_to_string(path.as_ref()).map_err(|error| {
+            AuthError::Credentials(format!("{}: {}", path.as_ref().display(), error))
+        })?;
+        let v: Value = from_str(&text)
+            .map_err(|error| AuthError::Credentials(format!("{}", error)))?;
+        match (v["api_key"].as_str(), v["api_secret"].as_str()) {
+            (Some(api_key), Some(api_secret)) => Ok(Credentials::new(api_key, api_secret)),
+            _ => Err(AuthError::Credentials(String::from(
+                "api_key and api_secret are required",
+            ))),
+        }
+    }
+
+    pub fn get_api_key(&self) -> String {
+        self.api_key.clone()
+    }
+
+    // タイムスタンプとナンスを連結した文字列のHMAC-SHA256署名を16進数で取得する
+    pub fn sign(&self, timestamp: i64, nonce: &str) -> String {
+        sign(&self.api_secret, timestamp, nonce)
+    }
+
+    // authメソッドのJSON-RPCリクエストを生成する
+    pub fn auth_request(&self, id: u64) -> String {
+        let timestamp = Utc::now().timestamp_millis();
+        let nonce: String = thread_rng().sample_iter(&Alphanumeric).take(16).collect();
+        json!({
+            "jsonrpc": "2.0",
+            "method": "auth",
+            "params": {
+                "api_key": self.api_key,
+                "timestamp": timestamp,
+                "nonce": nonce,
+                "signature": self.sign(timestamp, &nonce),
+            },
+            "id": id,
+        })
+        .to_string()
+    }
+}
+
+// シークレットでタイムスタンプ+ナンスに署名する
+pub fn sign(api_secret: &str, timestamp: i64, nonce: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(api_secret.as_bytes())
+        .expect("HMAC can take key of any size");
+    mac.update(format!("{}{}", timestamp, nonce).as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+// ソケットに認証リクエストを送信し、timeoutまでレスポンスを待つ
+// 応答のないサーバーで止まらないよう、ソケットには事前に読み込みのタイムアウトを設定しておく
+pub fn authenticate<S: Read + Write>(
+    socket: &mut WebSocket<S>,
+    credentials: &Credentials,
+    timeout: Duration,
+) -> Result<(), AuthError> {
+    socket
+        .write_message(Message::Text(credentials.auth_request(AUTH_REQUEST_ID)))
+        .map_err(AuthError::Socket)?;
+
+    let deadline = Instant::now() + timeout;
+    loop {
+        let message = match socket.read_message() {
+            Ok(message) => message,
+            Err(tungstenite::Error::Io(error))
+                if error.kind() == io::ErrorKind::WouldBlock || error.kind() == io::ErrorKind::TimedOut =>
+            {
+                if deadline <= Instant::now() {
+                    return Err(AuthError::Timeout);
+                }
+                continue;
+            }
+            Err(error) => return Err(AuthError::Socket(error)),
+        };
+        let text = match message {
+            Message::Text(text) => text,
+            Message::Close(_) => {
+                return Err(AuthError::Rejected(String::from("connection closed")));
+            }
+            _ => continue,
+        };
+
+        let v: Value = match from_str(&text) {
+            Ok(v) => v,
+            Err(_) => continue,
+        };
+
+        // 認証リクエストに対するレスポンス以外は読み飛ばす
+        if v["id"].as_u64() != Some(AUTH_REQUEST_ID) {
+            continue;
+        }
+
+        if v["result"].as_bool() == Some(true) {
+            info!("authenticate: Authenticated {}", credentials.get_api_key());
+            return Ok(());
+        }
+        return Err(AuthError::Rejected(v["error"].to_string()));
+    }
+}
@@ -0,0 +1,107 @@
+use std::sync::OnceLock;
+use std::time::Instant;
+
+use chrono::{DateTime, Duration, TimeZone, Utc};
+
+// プロセス起動後に最初に時刻を取得した時点の単調時計と壁時計
+// 単調時計の経過時間はこの時点を起点として計算する
+fn anchor() -> &'static (Instant, DateTime<Utc>) {
+    static ANCHOR: OnceLock<(Instant, DateTime<Utc>)> = OnceLock::new();
+    ANCHOR.get_or_init(|| (Instant::now(), Utc::now()))
+}
+
+// ソケットからの読み込み直後に取得した受信時刻
+#[derive(Clone, Copy, Debug)]
+pub struct ReceiveTime {
+    // 壁時計の受信時刻(NTPの調整等で前後に飛ぶことがある)
+    pub wall: DateTime<Utc>,
+    // 起点からの単調時計の経過時間(ナノ秒、順序の比較に用いる)
+    monotonic_nanos: u64,
+    // 起点の壁時計に単調時計の経過時間を加えた時刻
+    steady_wall: DateTime<Utc>,
+}
+
+impl ReceiveTime {
+    // 現在時刻を受信時刻として取得する
+    pub fn now() -> Self {
+        let (anchor_instant, anchor_wall) = anchor();
+        let monotonic = Instant::now();
+        let wall = Utc::now();
+        // 起点より前の時刻にならないようにする
+        let elapsed = monotonic.saturating_duration_since(*anchor_instant);
+        ReceiveTime {
+            wall,
+            monotonic_nanos: elapsed.as_nanos() as u64,
+            steady_wall: *anchor_wall + Duration::nanoseconds(elapsed.as_nanos() as i64),
+        }
+    }
+
+    // 記録済みの値(ナノ秒)から受信時刻を復元する
+    pub fn from_nanos(wall_nanos: i64, monotonic_nanos: u64, steady_wall_nanos: i64) -> Self {
+        ReceiveTime {
+            wall: Utc.timestamp_nanos(wall_nanos),
+            monotonic_nanos,
+            steady_wall: Utc.timestamp_nanos(steady_wall_nanos),
+        }
+    }
+
+    // 起点からの単調時計の経過時間(ナノ秒)
+    pub fn monotonic_nanos(&self) -> u64 {
+        self.monotonic_nanos
+    }
+
+    // 起点の壁時計に単調時計の経過時間を加えた時刻
+    // 壁時計が飛んでも前後関係と間隔が崩れないため、遅延の計算に用いる
+    pub fn steady_wall(&self) -> DateTime<Utc> {
+        self.steady_wall
+    }
+
+    // 壁時計の受信時刻(UNIX時間のナノ秒)
+    pub fn wall_nanos(&self) -> i64 {
+        self.wall.timestamp_nanos_opt().unwrap_or_default()
+    }
+
+    // 単調時計に基づく受信時刻(UNIX時間のナノ秒)
+    pub fn steady_wall_nanos(&self) -> i64 {
+        self.steady_wall.timestamp_nanos_opt().unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn monotonic_nanos_never_decrease() {
+        let mut previous = ReceiveTime::now();
+        for _ in 0..1000 {
+            let current = ReceiveTime::now();
+            assert!(previous.monotonic_nanos() <= current.monotonic_nanos());
+            assert!(previous.steady_wall() <= current.steady_wall());
+            previous = current;
+        }
+    }
+
+    #[test]
+    fn derives_steady_wall_from_anchor() {
+        let receive_time = ReceiveTime::now();
+        let (_, anchor_wall) = anchor();
+        // 起点の壁時計に単調時計の経過時間を加えた時刻になる
+        assert_eq!(
+            receive_time.steady_wall(),
+            *anchor_wall + Duration::nanoseconds(receive_time.monotonic_nanos() as i64)
+        );
+        assert_eq!(
+            receive_time.steady_wall_nanos(),
+            anchor_wall.timestamp_nanos_opt().unwrap() + receive_time.monotonic_nanos() as i64
+        );
+    }
+
+    #[test]
+    fn restores_recorded_nanos() {
+        let receive_time = ReceiveTime::from_nanos(1601510400123456789, 42, 1601510400123000000);
+        assert_eq!(receive_time.wall_nanos(), 1601510400123456789);
+        assert_eq!(receive_time.monotonic_nanos(), 42);
+        assert_eq!(receive_time.steady_wall_nanos(), 1601510400123000000);
+    }
+}
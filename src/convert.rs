@@ -0,0 +1,696 @@
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use arrow_array::cast::AsArray;
+use arrow_array::types::{Float64Type, Int64Type, TimestampNanosecondType, TimestampSecondType, UInt64Type};
+use arrow_array::{
+    Array, ArrayRef, ArrowPrimitiveType, Float64Array, Int64Array, PrimitiveArray, RecordBatch, StringArray,
+    TimestampNanosecondArray, TimestampSecondArray, UInt64Array,
+};
+use arrow_ipc::reader::FileReader;
+use arrow_ipc::writer::FileWriter;
+use arrow_schema::{ArrowError, DataType, Field, Schema, SchemaRef, TimeUnit};
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use parquet::arrow::ArrowWriter;
+use parquet::errors::ParquetError;
+use serde_json::{json, Value};
+
+use crate::stream_api::Side;
+use crate::validate::DataKind;
+
+// ファイルの形式
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    // 記録したファイルの形式(空白区切り、見出しなし)
+    Space,
+    // 見出し付きのカンマ区切り
+    Csv,
+    // 1行1オブジェクトのJSON
+    JsonLines,
+    Parquet,
+    // Arrow IPCのファイル形式
+    Arrow,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "space" => Ok(Format::Space),
+            "csv" => Ok(Format::Csv),
+            "jsonl" | "ndjson" => Ok(Format::JsonLines),
+            "parquet" => Ok(Format::Parquet),
+            "arrow" | "ipc" => Ok(Format::Arrow),
+            _ => Err(format!("unknown format: {} (space, csv, jsonl, parquet or arrow)", s)),
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Format::Space => write!(f, "space"),
+            Format::Csv => write!(f, "csv"),
+            Format::JsonLines => write!(f, "jsonl"),
+            Format::Parquet => write!(f, "parquet"),
+            Format::Arrow => write!(f, "arrow"),
+        }
+    }
+}
+
+impl Format {
+    // 拡張子から形式を推定する(.csvは見出し付きのカンマ区切り)
+    pub fn from_extension(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+            "csv" => Some(Format::Csv),
+            "jsonl" | "ndjson" => Some(Format::JsonLines),
+            "parquet" => Some(Format::Parquet),
+            "arrow" | "ipc" | "feather" => Some(Format::Arrow),
+            _ => None,
+        }
+    }
+
+    // 読み込むファイルの形式を推定する
+    // 記録したファイルも拡張子は.csvのため、1行目が見出しでなければ空白区切りとして扱う
+    pub fn detect(path: &Path, kind: DataKind) -> io::Result<Self> {
+        match Format::from_extension(path) {
+            Some(Format::Csv) | None => {
+                let mut first = String::new();
+                BufReader::new(File::open(path)?).read_line(&mut first)?;
+                if first.trim_end() == header(kind).join(",") {
+                    Ok(Format::Csv)
+                } else {
+                    Ok(Format::Space)
+                }
+            }
+            Some(format) => Ok(format),
+        }
+    }
+}
+
+// 変換のエラー
+#[derive(Debug)]
+pub enum ConvertError {
+    Io(io::Error),
+    Arrow(ArrowError),
+    Parquet(ParquetError),
+    // 解析できない行(ファイルと1から始まる行番号)
+    Parse { path: PathBuf, line: usize },
+    // 列がない、または列の型が異なる
+    Column(String),
+}
+
+impl fmt::Display for ConvertError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConvertError::Io(error) => write!(f, "{}", error),
+            ConvertError::Arrow(error) => write!(f, "arrow: {}", error),
+            ConvertError::Parquet(error) => write!(f, "parquet: {}", error),
+            ConvertError::Parse { path, line } => write!(f, "{}:{}: malformed line", path.display(), line),
+            ConvertError::Column(name) => write!(f, "missing or invalid column: {}", name),
+        }
+    }
+}
+
+impl std::error::Error for ConvertError {}
+
+impl From<io::Error> for ConvertError {
+    fn from(error: io::Error) -> Self {
+        ConvertError::Io(error)
+    }
+}
+
+impl From<ArrowError> for ConvertError {
+    fn from(error: ArrowError) -> Self {
+        ConvertError::Arrow(error)
+    }
+}
+
+impl From<ParquetError> for ConvertError {
+    fn from(error: ParquetError) -> Self {
+        ConvertError::Parquet(error)
+    }
+}
+
+// 約定データの1行
+// 記録した時期により受信時刻以降の項目がないため、ない項目はNone
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExecutionRow {
+    // 約定日時(秒)
+    pub exec_time: i64,
+    pub side: Side,
+    pub price: f64,
+    pub size: f64,
+    // 受信時刻(ナノ秒)
+    pub receive_time: Option<i64>,
+    // 単調時計の受信時刻(ナノ秒)
+    pub monotonic_nanos: Option<i64>,
+    pub sequence: Option<u64>,
+    pub id: Option<u64>,
+}
+
+impl ExecutionRow {
+    // 約定日時 売買種別 価格 数量[ 受信時刻 単調時計の受信時刻[ 受信シーケンス番号[ 約定ID]]]
+    pub fn parse(line: &str) -> Option<Self> {
+        let fields: Vec<Option<&str>> = line.split_whitespace().map(Some).collect();
+        if ![4, 6, 7, 8].contains(&fields.len()) {
+            return None;
+        }
+        ExecutionRow::from_values(&fields)
+    }
+
+    // 見出しの順の値から作成する(ない項目はNone)
+    fn from_values(values: &[Option<&str>]) -> Option<Self> {
+        let value = |index: usize| values.get(index).copied().flatten();
+        Some(ExecutionRow {
+            exec_time: value(0)?.parse().ok()?,
+            side: Side::from_code(value(1)?)?,
+            price: value(2)?.parse().ok()?,
+            size: value(3)?.parse().ok()?,
+            receive_time: parse_optional(value(4))?,
+            monotonic_nanos: parse_optional(value(5))?,
+            sequence: parse_optional(value(6))?,
+            id: parse_optional(value(7))?,
+        })
+    }
+
+    // 記録したファイルと同じ形式の1行(ない項目以降は書き込まない)
+    pub fn to_line(&self) -> String {
+        let mut fields = vec![
+            self.exec_time.to_string(),
+            self.side.to_string(),
+            self.price.to_string(),
+            self.size.to_string(),
+        ];
+        let optional = vec![
+            self.receive_time.map(|value| value.to_string()),
+            self.monotonic_nanos.map(|value| value.to_string()),
+            self.sequence.map(|value| value.to_string()),
+            self.id.map(|value| value.to_string()),
+        ];
+        fields.extend(optional.into_iter().map_while(|field| field));
+        fields.join(" ")
+    }
+}
+
+// 遅延データの1行
+#[derive(Clone, Debug, PartialEq)]
+pub struct LatencyRow {
+    // 受信時刻(ナノ秒)
+    pub receive_time: i64,
+    // 遅延(ミリ秒)
+    pub latency_ms: f64,
+    // 単調時計の受信時刻(ナノ秒)
+    pub monotonic_nanos: Option<i64>,
+    pub sequence: Option<u64>,
+}
+
+// 単調時計の受信時刻がない遅延データを3項目以上の形式で書き込む場合の値
+const NO_MONOTONIC: &str = "-";
+
+impl LatencyRow {
+    // 受信時刻(ナノ秒) 遅延(ミリ秒) 単調時計の受信時刻(ナノ秒)[ 受信シーケンス番号]
+    // 受信時刻(ミリ秒) 遅延(ミリ秒) の2項目の形式も読み込む
+    // 単調時計の受信時刻が-の場合は、単調時計の受信時刻がないものとする
+    pub fn parse(line: &str) -> Option<Self> {
+        let fields: Vec<Option<&str>> = line.split_whitespace().map(Some).collect();
+        match fields.len() {
+            2 => Some(LatencyRow {
+                receive_time: fields[0]?.parse::<i64>().ok()?.checked_mul(1_000_000)?,
+                latency_ms: fields[1]?.parse().ok()?,
+                monotonic_nanos: None,
+                sequence: None,
+            }),
+            3 | 4 => LatencyRow::from_values(&fields),
+            _ => None,
+        }
+    }
+
+    // 見出しの順の値から作成する(ない項目はNone)
+    fn from_values(values: &[Option<&str>]) -> Option<Self> {
+        let value = |index: usize| values.get(index).copied().flatten();
+        Some(LatencyRow {
+            receive_time: value(0)?.parse().ok()?,
+            latency_ms: value(1)?.parse().ok()?,
+            monotonic_nanos: parse_optional(value(2).filter(|value| *value != NO_MONOTONIC))?,
+            sequence: parse_optional(value(3))?,
+        })
+    }
+
+    // 記録したファイルと同じ形式の1行
+    // 2項目の形式は受信時刻がミリ秒のため、ミリ秒で表せる場合のみ2項目の形式で書き込み、
+    // それ以外で単調時計の受信時刻がない場合は-を書き込み、受信時刻をナノ秒のまま保つ
+    pub fn to_line(&self) -> String {
+        let mut line = match (self.monotonic_nanos, self.sequence) {
+            (None, None) if self.receive_time % 1_000_000 == 0 => {
+                return format!("{} {:.3}", self.receive_time / 1_000_000, self.latency_ms);
+            }
+            (None, _) => format!("{} {:.3} {}", self.receive_time, self.latency_ms, NO_MONOTONIC),
+            (Some(monotonic_nanos), _) => {
+                format!("{} {:.3} {}", self.receive_time, self.latency_ms, monotonic_nanos)
+            }
+        };
+        if let Some(sequence) = self.sequence {
+            line += &format!(" {}", sequence);
+        }
+        line
+    }
+}
+
+// ない項目はSome(None)、解析できない場合はNone
+fn parse_optional<T: FromStr>(value: Option<&str>) -> Option<Option<T>> {
+    match value {
+        Some(value) => value.parse().ok().map(Some),
+        None => Some(None),
+    }
+}
+
+// 値がない場合はNone
+fn optional<T: ArrowPrimitiveType>(array: &PrimitiveArray<T>, index: usize) -> Option<T::Native> {
+    if array.is_null(index) {
+        None
+    } else {
+        Some(array.value(index))
+    }
+}
+
+// 見出し(Parquet・Arrow IPCの列名、JSON Linesの項目名と同じ)
+pub fn header(kind: DataKind) -> &'static [&'static str] {
+    match kind {
+        DataKind::Executions => &[
+            "exec_time",
+            "side",
+            "price",
+            "size",
+            "receive_time",
+            "monotonic_nanos",
+            "sequence",
+            "id",
+        ],
+        DataKind::Latency => &["receive_time", "latency_ms", "monotonic_nanos", "sequence"],
+    }
+}
+
+// Parquet・Arrow IPCのスキーマ
+// 約定日時と受信時刻はUTCのタイムスタンプ(秒・ナノ秒)、売買種別はB/S/Nの文字列
+pub fn schema(kind: DataKind) -> SchemaRef {
+    let utc = || Some(Arc::from("UTC"));
+    let fields = match kind {
+        DataKind::Executions => vec![
+            Field::new("exec_time", DataType::Timestamp(TimeUnit::Second, utc()), false),
+            Field::new("side", DataType::Utf8, false),
+            Field::new("price", DataType::Float64, false),
+            Field::new("size", DataType::Float64, false),
+            Field::new("receive_time", DataType::Timestamp(TimeUnit::Nanosecond, utc()), true),
+            Field::new("monotonic_nanos", DataType::Int64, true),
+            Field::new("sequence", DataType::UInt64, true),
+            Field::new("id", DataType::UInt64, true),
+        ],
+        DataKind::Latency => vec![
+            Field::new("receive_time", DataType::Timestamp(TimeUnit::Nanosecond, utc()), false),
+            Field::new("latency_ms", DataType::Float64, false),
+            Field::new("monotonic_nanos", DataType::Int64, true),
+            Field::new("sequence", DataType::UInt64, true),
+        ],
+    };
+    Arc::new(Schema::new(fields))
+}
+
+// 読み込んだ約定データ・遅延データ
+#[derive(Clone, Debug, PartialEq)]
+pub enum Table {
+    Executions(Vec<ExecutionRow>),
+    Latency(Vec<LatencyRow>),
+}
+
+impl Table {
+    pub fn new(kind: DataKind) -> Self {
+        match kind {
+            DataKind::Executions => Table::Executions(Vec::new()),
+            DataKind::Latency => Table::Latency(Vec::new()),
+        }
+    }
+
+    pub fn kind(&self) -> DataKind {
+        match self {
+            Table::Executions(_) => DataKind::Executions,
+            Table::Latency(_) => DataKind::Latency,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            Table::Executions(rows) => rows.len(),
+            Table::Latency(rows) => rows.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // 記録したファイルと同じ形式の1行を解析して追加する
+    fn push_line(&mut self, line: &str) -> bool {
+        match self {
+            Table::Executions(rows) => ExecutionRow::parse(line).map(|row| rows.push(row)).is_some(),
+            Table::Latency(rows) => LatencyRow::parse(line).map(|row| rows.push(row)).is_some(),
+        }
+    }
+
+    // 見出しの順の値を解析して追加する
+    fn push_values(&mut self, values: &[Option<&str>]) -> bool {
+        match self {
+            Table::Executions(rows) => ExecutionRow::from_values(values).map(|row| rows.push(row)).is_some(),
+            Table::Latency(rows) => LatencyRow::from_values(values).map(|row| rows.push(row)).is_some(),
+        }
+    }
+
+    // 見出し付きのカンマ区切りの1行(ない項目は空)
+    fn csv_lines(&self) -> Vec<String> {
+        let optional = |value: Option<String>| value.unwrap_or_default();
+        match self {
+            Table::Executions(rows) => rows
+                .iter()
+                .map(|row| {
+                    [
+                        row.exec_time.to_string(),
+                        row.side.to_string(),
+                        row.price.to_string(),
+                        row.size.to_string(),
+                        optional(row.receive_time.map(|value| value.to_string())),
+                        optional(row.monotonic_nanos.map(|value| value.to_string())),
+                        optional(row.sequence.map(|value| value.to_string())),
+                        optional(row.id.map(|value| value.to_string())),
+                    ]
+                    .join(",")
+                })
+                .collect(),
+            Table::Latency(rows) => rows
+                .iter()
+                .map(|row| {
+                    [
+                        row.receive_time.to_string(),
+                        row.latency_ms.to_string(),
+                        optional(row.monotonic_nanos.map(|value| value.to_string())),
+                        optional(row.sequence.map(|value| value.to_string())),
+                    ]
+                    .join(",")
+                })
+                .collect(),
+        }
+    }
+
+    // 見出し付きのカンマ区切りの1行を追加する(columnsは見出しの順の列の位置)
+    fn push_csv(&mut self, columns: &[usize], line: &str) -> bool {
+        let values: Vec<&str> = line.split(',').collect();
+        let values: Vec<Option<&str>> = columns
+            .iter()
+            .map(|index| values.get(*index).copied().filter(|value| !value.is_empty()))
+            .collect();
+        self.push_values(&values)
+    }
+
+    fn json_values(&self) -> Vec<Value> {
+        match self {
+            Table::Executions(rows) => rows
+                .iter()
+                .map(|row| {
+                    json!({
+                        "exec_time": row.exec_time,
+                        "side": row.side.to_string(),
+                        "price": row.price,
+                        "size": row.size,
+                        "receive_time": row.receive_time,
+                        "monotonic_nanos": row.monotonic_nanos,
+                        "sequence": row.sequence,
+                        "id": row.id,
+                    })
+                })
+                .collect(),
+            Table::Latency(rows) => rows
+                .iter()
+                .map(|row| {
+                    json!({
+                        "receive_time": row.receive_time,
+                        "latency_ms": row.latency_ms,
+                        "monotonic_nanos": row.monotonic_nanos,
+                        "sequence": row.sequence,
+                    })
+                })
+                .collect(),
+        }
+    }
+
+    // JSONの1オブジェクトを追加する
+    fn push_json(&mut self, v: &Value) -> bool {
+        let values: Vec<Option<String>> = header(self.kind())
+            .iter()
+            .map(|name| match &v[*name] {
+                Value::String(value) => Some(value.clone()),
+                Value::Null => None,
+                value => Some(value.to_string()),
+            })
+            .collect();
+        let values: Vec<Option<&str>> = values.iter().map(|value| value.as_deref()).collect();
+        self.push_values(&values)
+    }
+
+    // Arrowの列形式に変換する
+    pub fn to_record_batch(&self) -> Result<RecordBatch, ConvertError> {
+        let columns: Vec<ArrayRef> = match self {
+            Table::Executions(rows) => vec![
+                Arc::new(
+                    TimestampSecondArray::from_iter_values(rows.iter().map(|row| row.exec_time)).with_timezone("UTC"),
+                ),
+                Arc::new(StringArray::from_iter_values(
+                    rows.iter().map(|row| row.side.to_string()),
+                )),
+                Arc::new(Float64Array::from_iter_values(rows.iter().map(|row| row.price))),
+                Arc::new(Float64Array::from_iter_values(rows.iter().map(|row| row.size))),
+                Arc::new(
+                    TimestampNanosecondArray::from(rows.iter().map(|row| row.receive_time).collect::<Vec<_>>())
+                        .with_timezone("UTC"),
+                ),
+                Arc::new(Int64Array::from(
+                    rows.iter().map(|row| row.monotonic_nanos).collect::<Vec<_>>(),
+                )),
+                Arc::new(UInt64Array::from(
+                    rows.iter().map(|row| row.sequence).collect::<Vec<_>>(),
+                )),
+                Arc::new(UInt64Array::from(rows.iter().map(|row| row.id).collect::<Vec<_>>())),
+            ],
+            Table::Latency(rows) => vec![
+                Arc::new(
+                    TimestampNanosecondArray::from_iter_values(rows.iter().map(|row| row.receive_time))
+                        .with_timezone("UTC"),
+                ),
+                Arc::new(Float64Array::from_iter_values(rows.iter().map(|row| row.latency_ms))),
+                Arc::new(Int64Array::from(
+                    rows.iter().map(|row| row.monotonic_nanos).collect::<Vec<_>>(),
+                )),
+                Arc::new(UInt64Array::from(
+                    rows.iter().map(|row| row.sequence).collect::<Vec<_>>(),
+                )),
+            ],
+        };
+        Ok(RecordBatch::try_new(schema(self.kind()), columns)?)
+    }
+
+    // Arrowの列形式から行を追加する(列は名前で参照する)
+    pub fn extend_from_record_batch(&mut self, batch: &RecordBatch) -> Result<(), ConvertError> {
+        let column = |name: &str| {
+            batch
+                .column_by_name(name)
+                .ok_or_else(|| ConvertError::Column(name.to_string()))
+        };
+        let invalid = |name: &str| ConvertError::Column(name.to_string());
+        match self {
+            Table::Executions(rows) => {
+                let exec_time = column("exec_time")?
+                    .as_primitive_opt::<TimestampSecondType>()
+                    .ok_or_else(|| invalid("exec_time"))?;
+                let side = column("side")?.as_string_opt::<i32>().ok_or_else(|| invalid("side"))?;
+                let price = column("price")?
+                    .as_primitive_opt::<Float64Type>()
+                    .ok_or_else(|| invalid("price"))?;
+                let size = column("size")?
+                    .as_primitive_opt::<Float64Type>()
+                    .ok_or_else(|| invalid("size"))?;
+                let receive_time = column("receive_time")?
+                    .as_primitive_opt::<TimestampNanosecondType>()
+                    .ok_or_else(|| invalid("receive_time"))?;
+                let monotonic_nanos = column("monotonic_nanos")?
+                    .as_primitive_opt::<Int64Type>()
+                    .ok_or_else(|| invalid("monotonic_nanos"))?;
+                let sequence = column("sequence")?
+                    .as_primitive_opt::<UInt64Type>()
+                    .ok_or_else(|| invalid("sequence"))?;
+                let id = column("id")?
+                    .as_primitive_opt::<UInt64Type>()
+                    .ok_or_else(|| invalid("id"))?;
+                for index in 0..batch.num_rows() {
+                    rows.push(ExecutionRow {
+                        exec_time: exec_time.value(index),
+                        side: Side::from_code(side.value(index)).ok_or_else(|| invalid("side"))?,
+                        price: price.value(index),
+                        size: size.value(index),
+                        receive_time: optional(receive_time, index),
+                        monotonic_nanos: optional(monotonic_nanos, index),
+                        sequence: optional(sequence, index),
+                        id: optional(id, index),
+                    });
+                }
+            }
+            Table::Latency(rows) => {
+                let receive_time = column("receive_time")?
+                    .as_primitive_opt::<TimestampNanosecondType>()
+                    .ok_or_else(|| invalid("receive_time"))?;
+                let latency_ms = column("latency_ms")?
+                    .as_primitive_opt::<Float64Type>()
+                    .ok_or_else(|| invalid("latency_ms"))?;
+                let monotonic_nanos = column("monotonic_nanos")?
+                    .as_primitive_opt::<Int64Type>()
+                    .ok_or_else(|| invalid("monotonic_nanos"))?;
+                let sequence = column("sequence")?
+                    .as_primitive_opt::<UInt64Type>()
+                    .ok_or_else(|| invalid("sequence"))?;
+                for index in 0..batch.num_rows() {
+                    rows.push(LatencyRow {
+                        receive_time: receive_time.value(index),
+                        latency_ms: latency_ms.value(index),
+                        monotonic_nanos: optional(monotonic_nanos, index),
+                        sequence: optional(sequence, index),
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// ファイルを読み込む(形式を指定しない場合は推定する)
+pub fn read_table(path: &Path, kind: DataKind, format: Option<Format>) -> Result<Table, ConvertError> {
+    let format = match format {
+        Some(format) => format,
+        None => Format::detect(path, kind)?,
+    };
+    let mut table = Table::new(kind);
+    let malformed = |line: usize| ConvertError::Parse {
+        path: path.to_path_buf(),
+        line,
+    };
+    match format {
+        Format::Space => {
+            for (index, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+                let line = line?;
+                if !line.trim().is_empty() && !table.push_line(&line) {
+                    return Err(malformed(index + 1));
+                }
+            }
+        }
+        Format::Csv => {
+            let mut lines = BufReader::new(File::open(path)?).lines();
+            let names: Vec<String> = match lines.next() {
+                Some(line) => line?.trim_end().split(',').map(|name| name.to_string()).collect(),
+                None => return Ok(table),
+            };
+            // 見出しの順序に依らず、記録したファイルの項目の順序で読み込む
+            let columns = header(kind)
+                .iter()
+                .map(|name| {
+                    names
+                        .iter()
+                        .position(|column| column == name)
+                        .ok_or_else(|| ConvertError::Column(name.to_string()))
+                })
+                .collect::<Result<Vec<usize>, ConvertError>>()?;
+            for (index, line) in lines.enumerate() {
+                let line = line?;
+                if !line.trim().is_empty() && !table.push_csv(&columns, line.trim_end()) {
+                    return Err(malformed(index + 2));
+                }
+            }
+        }
+        Format::JsonLines => {
+            for (index, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+                let line = line?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let v: Value = serde_json::from_str(&line).map_err(|_| malformed(index + 1))?;
+                if !table.push_json(&v) {
+                    return Err(malformed(index + 1));
+                }
+            }
+        }
+        Format::Parquet => {
+            for batch in ParquetRecordBatchReaderBuilder::try_new(File::open(path)?)?.build()? {
+                table.extend_from_record_batch(&batch?)?;
+            }
+        }
+        Format::Arrow => {
+            for batch in FileReader::try_new(File::open(path)?, None)? {
+                table.extend_from_record_batch(&batch?)?;
+            }
+        }
+    }
+    Ok(table)
+}
+
+// ファイルに書き込む(既存のファイルは置き換える)
+pub fn write_table(path: &Path, table: &Table, format: Format) -> Result<(), ConvertError> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let file = File::create(path)?;
+    match format {
+        Format::Space | Format::Csv | Format::JsonLines => {
+            let mut writer = BufWriter::new(file);
+            let lines: Vec<String> = match (format, table) {
+                (Format::Space, Table::Executions(rows)) => rows.iter().map(|row| row.to_line()).collect(),
+                (Format::Space, Table::Latency(rows)) => rows.iter().map(|row| row.to_line()).collect(),
+                (Format::Csv, _) => {
+                    let mut lines = vec![header(table.kind()).join(",")];
+                    lines.extend(table.csv_lines());
+                    lines
+                }
+                _ => table.json_values().iter().map(|v| v.to_string()).collect(),
+            };
+            for line in lines {
+                writeln!(writer, "{}", line)?;
+            }
+            writer.flush()?;
+        }
+        Format::Parquet => {
+            let batch = table.to_record_batch()?;
+            let mut writer = ArrowWriter::try_new(file, batch.schema(), None)?;
+            writer.write(&batch)?;
+            writer.close()?;
+        }
+        Format::Arrow => {
+            let batch = table.to_record_batch()?;
+            let mut writer = FileWriter::try_new(file, &batch.schema())?;
+            writer.write(&batch)?;
+            writer.finish()?;
+        }
+    }
+    Ok(())
+}
+
+// ファイルの形式を変換し、変換した行数を返す
+pub fn convert(
+    input: &Path,
+    input_format: Option<Format>,
+    output: &Path,
+    output_format: Format,
+    kind: DataKind,
+) -> Result<usize, ConvertError> {
+    let table = read_table(input, kind, input_format)?;
+    write_table(output, &table, output_format)?;
+    Ok(table.len())
+}
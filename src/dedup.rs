@@ -0,0 +1,94 @@
+use std::collections::{BTreeSet, HashSet};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+// 重複の確認に用いる直近の約定IDの既定の件数
+pub const DEFAULT_DEDUP_WINDOW: usize = 100_000;
+
+// 日付毎の約定IDの一覧を読み込む(1行1件)
+pub fn read_ids(path: &Path) -> io::Result<HashSet<u64>> {
+    let mut ids = HashSet::new();
+    match File::open(path) {
+        Ok(file) => {
+            for line in BufReader::new(file).lines() {
+                if let Ok(id) = line?.trim().parse() {
+                    ids.insert(id);
+                }
+            }
+        }
+        Err(error) if error.kind() == io::ErrorKind::NotFound => {}
+        Err(error) => return Err(error),
+    }
+    Ok(ids)
+}
+
+// 約定IDによる約定の重複の検知
+// 直近の約定IDはメモリ上で確認し、それより古い約定IDは日付毎の約定IDの一覧(ファイル)で確認する
+pub struct ExecutionDedup {
+    capacity: usize,
+    // 直近の約定ID(約定IDは約定順に増加する)
+    window: BTreeSet<u64>,
+    // 直近の約定IDに読み込み済みの日付毎の一覧
+    seeded: HashSet<PathBuf>,
+    // 最後に確認した日付毎の一覧
+    loaded: Option<(PathBuf, HashSet<u64>)>,
+}
+
+impl ExecutionDedup {
+    pub fn new(capacity: usize) -> Self {
+        ExecutionDedup {
+            capacity,
+            window: BTreeSet::new(),
+            seeded: HashSet::new(),
+            loaded: None,
+        }
+    }
+
+    // 約定IDが初めてのものであればtrueを返し、記録済みとして扱う
+    // ids_pathは約定の日付の約定IDの一覧で、初めてのものであれば呼び出し側で追記する
+    pub fn insert(&mut self, id: u64, ids_path: &Path) -> io::Result<bool> {
+        // 再起動後も直近の約定IDを確認できるよう、初めての日付の一覧から読み込む
+        if !self.seeded.contains(ids_path) {
+            let ids = read_ids(ids_path)?;
+            let mut ids: Vec<u64> = ids.into_iter().collect();
+            ids.sort_unstable();
+            for id in ids.iter().rev().take(self.capacity) {
+                self.window.insert(*id);
+            }
+            self.evict();
+            self.seeded.insert(ids_path.to_path_buf());
+        }
+
+        if self.window.contains(&id) {
+            return Ok(false);
+        }
+        // 直近の約定IDより古い場合は日付毎の一覧で確認する
+        if self.window.first().is_some_and(|first| id < *first) {
+            let loaded = match self.loaded.take() {
+                Some((path, ids)) if path == ids_path => (path, ids),
+                _ => (ids_path.to_path_buf(), read_ids(ids_path)?),
+            };
+            let (path, mut ids) = loaded;
+            let inserted = ids.insert(id);
+            self.loaded = Some((path, ids));
+            return Ok(inserted);
+        }
+
+        self.window.insert(id);
+        self.evict();
+        if let Some((path, ids)) = &mut self.loaded {
+            if path == ids_path {
+                ids.insert(id);
+            }
+        }
+        Ok(true)
+    }
+
+    // 件数を超えた古い約定IDを外す
+    fn evict(&mut self) {
+        while self.capacity < self.window.len() {
+            self.window.pop_first();
+        }
+    }
+}
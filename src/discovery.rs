@@ -0,0 +1,184 @@
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use serde_json::Value;
+
+use log::{error, info, warn};
+
+use crate::product::{Product, ProductRegistry};
+use crate::rest_api::{get_json, RestError};
+
+// 銘柄の自動取得の既定の間隔
+pub const DEFAULT_DISCOVERY_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+// 登録されていない銘柄の呼値の単位と数量の単位(先物等)
+const DEFAULT_TICK_SIZE: f64 = 1.0;
+const DEFAULT_SIZE_UNIT: f64 = 0.01;
+
+// 取引所が取り扱う銘柄(マーケットの一覧の1件)
+#[derive(Clone, Debug, PartialEq)]
+pub struct Market {
+    pub product_code: String,
+    // 限月に依存しない別名(BTCJPY_MAT1WK等)
+    pub alias: Option<String>,
+    pub market_type: Option<String>,
+}
+
+// 銘柄の自動取得のエラー
+#[derive(Debug)]
+pub enum DiscoveryError {
+    Rest(RestError),
+    // マーケットの一覧の形式が不正
+    InvalidMarkets(String),
+    // マーケットの一覧に存在しない銘柄コード・別名
+    UnknownProducts(Vec<String>),
+}
+
+impl fmt::Display for DiscoveryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DiscoveryError::Rest(error) => write!(f, "markets: {}", error),
+            DiscoveryError::InvalidMarkets(message) => write!(f, "invalid markets: {}", message),
+            DiscoveryError::UnknownProducts(codes) => write!(f, "unknown products: {}", codes.join(", ")),
+        }
+    }
+}
+
+impl std::error::Error for DiscoveryError {}
+
+// マーケットの一覧のURL
+pub fn markets_url(rest_end_point: &str) -> String {
+    format!("{}/v1/markets", rest_end_point.trim_end_matches('/'))
+}
+
+// マーケットの一覧を解析する
+pub fn parse_markets(v: &Value) -> Result<Vec<Market>, DiscoveryError> {
+    let markets = v
+        .as_array()
+        .ok_or_else(|| DiscoveryError::InvalidMarkets(v.to_string()))?;
+    markets
+        .iter()
+        .map(|market| {
+            let product_code = market["product_code"]
+                .as_str()
+                .ok_or_else(|| DiscoveryError::InvalidMarkets(market.to_string()))?;
+            Ok(Market {
+                product_code: product_code.to_string(),
+                alias: market["alias"].as_str().map(|alias| alias.to_string()),
+                market_type: market["market_type"].as_str().map(|market_type| market_type.to_string()),
+            })
+        })
+        .collect()
+}
+
+// マーケットの一覧を取得する
+pub fn fetch_markets(rest_end_point: &str) -> Result<Vec<Market>, DiscoveryError> {
+    let v = get_json(&markets_url(rest_end_point)).map_err(DiscoveryError::Rest)?;
+    parse_markets(&v)
+}
+
+// 銘柄コードまたは別名を、マーケットの一覧に存在する銘柄に解決する
+// 登録されていない銘柄(限月のある先物等)は銘柄コードから通貨ペアを推定する
+pub fn resolve_products<S: AsRef<str>>(
+    requested: &[S],
+    markets: &[Market],
+    registry: &ProductRegistry,
+) -> Result<Vec<Product>, DiscoveryError> {
+    let mut products: Vec<Product> = Vec::new();
+    let mut unknown = Vec::new();
+    for code in requested.iter().map(|code| code.as_ref()) {
+        let market = markets
+            .iter()
+            .find(|market| market.product_code == code || market.alias.as_deref() == Some(code));
+        let product_code = match market {
+            Some(market) => market.product_code.as_str(),
+            None => {
+                unknown.push(code.to_string());
+                continue;
+            }
+        };
+        // 別名と銘柄コードの両方を指定された場合等の重複は除く
+        if products.iter().any(|product| product.get_code() == product_code) {
+            continue;
+        }
+        products.push(match registry.get(product_code) {
+            Some(product) => product.clone(),
+            None => infer_product(product_code),
+        });
+    }
+
+    if unknown.is_empty() {
+        Ok(products)
+    } else {
+        Err(DiscoveryError::UnknownProducts(unknown))
+    }
+}
+
+// 銘柄コードから通貨ペアを推定する(BTCJPY28JUN2024 → BTC/JPY、FX_BTC_JPY → BTC/JPY)
+fn infer_product(product_code: &str) -> Product {
+    let parts: Vec<&str> = product_code.split('_').collect();
+    let (base, quote) = if 2 <= parts.len() {
+        (
+            parts[parts.len() - 2].to_string(),
+            parts[parts.len() - 1].to_string(),
+        )
+    } else {
+        let letters: String = product_code.chars().take_while(|c| c.is_ascii_alphabetic()).collect();
+        let split = letters.len().min(3);
+        (letters[..split].to_string(), letters[split..].chars().take(3).collect())
+    };
+    Product::new(product_code, DEFAULT_TICK_SIZE, DEFAULT_SIZE_UNIT, &base, &quote)
+}
+
+// 一定間隔でマーケットの一覧を取得し、購読する銘柄が変わった場合に通知する
+pub struct ProductDiscovery {
+    update: Arc<Mutex<Option<Vec<Product>>>>,
+}
+
+impl ProductDiscovery {
+    // 別スレッドで定期的な取得を開始する(currentは取得済みの銘柄)
+    pub fn start(
+        rest_end_point: &str,
+        requested: Vec<String>,
+        registry: ProductRegistry,
+        current: Vec<Product>,
+        interval: Duration,
+    ) -> Self {
+        let update = Arc::new(Mutex::new(None));
+        let rest_end_point = rest_end_point.to_string();
+        let thread_update = update.clone();
+
+        thread::spawn(move || {
+            let mut current = current;
+            loop {
+                thread::sleep(interval);
+                let products = fetch_markets(&rest_end_point)
+                    .and_then(|markets| resolve_products(&requested, &markets, &registry));
+                match products {
+                    Ok(products) if products != current => {
+                        info!(
+                            "ProductDiscovery: Products changed. {:?}",
+                            products.iter().map(|product| product.get_code()).collect::<Vec<_>>()
+                        );
+                        current = products.clone();
+                        *thread_update.lock().unwrap() = Some(products);
+                    }
+                    Ok(_) => {}
+                    Err(DiscoveryError::UnknownProducts(codes)) => {
+                        warn!("ProductDiscovery: Unknown products {:?}. Keep subscriptions.", codes)
+                    }
+                    Err(error) => error!("ProductDiscovery: {}", error),
+                }
+            }
+        });
+
+        ProductDiscovery { update }
+    }
+
+    // 前回の確認以降に変わった銘柄を取り出す
+    pub fn take_update(&self) -> Option<Vec<Product>> {
+        self.update.lock().unwrap().take()
+    }
+}
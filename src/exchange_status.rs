@@ -0,0 +1,322 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{self, create_dir_all, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+
+use serde_json::Value;
+
+use log::{error, info, warn};
+
+use crate::logging::event;
+use crate::rest_api::{get_json, RestError};
+use crate::stream_api::Common;
+
+// 取引所の状態を取得する既定の間隔
+pub const DEFAULT_STATUS_INTERVAL: Duration = Duration::from_secs(60);
+
+// 板の稼働状況が通常の場合の状態
+const RUNNING: &str = "RUNNING";
+
+// 再起動後も停止中の銘柄を引き継ぐため、停止中の銘柄の状態を書き込むファイル名
+pub const OPEN_HALTS_FILE_NAME: &str = "open_halts.csv";
+
+// 取引所の稼働状態
+#[derive(Clone, Debug, PartialEq)]
+pub enum ExchangeHealth {
+    Normal,
+    Busy,
+    VeryBusy,
+    SuperBusy,
+    NoOrder,
+    Stop,
+    Unknown(String),
+}
+
+impl ExchangeHealth {
+    fn from_str(s: &str) -> Self {
+        match s {
+            "NORMAL" => ExchangeHealth::Normal,
+            "BUSY" => ExchangeHealth::Busy,
+            "VERY BUSY" => ExchangeHealth::VeryBusy,
+            "SUPER BUSY" => ExchangeHealth::SuperBusy,
+            "NO ORDER" => ExchangeHealth::NoOrder,
+            "STOP" => ExchangeHealth::Stop,
+            s => ExchangeHealth::Unknown(s.to_string()),
+        }
+    }
+}
+
+// ファイルに書き込む際は空白を含めないよう、空白を"_"に置き換える
+impl fmt::Display for ExchangeHealth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExchangeHealth::Normal => write!(f, "NORMAL"),
+            ExchangeHealth::Busy => write!(f, "BUSY"),
+            ExchangeHealth::VeryBusy => write!(f, "VERY_BUSY"),
+            ExchangeHealth::SuperBusy => write!(f, "SUPER_BUSY"),
+            ExchangeHealth::NoOrder => write!(f, "NO_ORDER"),
+            ExchangeHealth::Stop => write!(f, "STOP"),
+            ExchangeHealth::Unknown(s) => write!(f, "{}", s.replace(' ', "_")),
+        }
+    }
+}
+
+// 取引所の状態
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExchangeStatus {
+    // 取得した時刻
+    time: DateTime<Utc>,
+    pub product_code: String,
+    pub health: ExchangeHealth,
+    // 板の稼働状況(RUNNING/CLOSED/STARTING/PREOPEN/CIRCUIT BREAK等)
+    pub state: String,
+}
+
+impl Common for ExchangeStatus {
+    // 取得時刻(ミリ秒) 銘柄 稼働状態 板の稼働状況 停止中か(1/0)
+    fn get_csv(&self) -> String {
+        format!(
+            "{} {} {} {} {}\n",
+            self.time.timestamp_millis(),
+            self.product_code,
+            self.health,
+            self.state.replace(' ', "_"),
+            if self.is_halted() { 1 } else { 0 }
+        )
+    }
+
+    fn data_time(&self) -> DateTime<Utc> {
+        self.time
+    }
+
+    fn channel(&self) -> String {
+        String::from("status")
+    }
+}
+
+impl ExchangeStatus {
+    pub fn new(time: DateTime<Utc>, product_code: &str, health: ExchangeHealth, state: &str) -> Self {
+        ExchangeStatus {
+            time,
+            product_code: product_code.to_string(),
+            health,
+            state: state.to_string(),
+        }
+    }
+
+    // 板の状態のレスポンス({"health": "NORMAL", "state": "RUNNING"})から生成する
+    pub fn from_json(time: DateTime<Utc>, product_code: &str, v: &Value) -> Option<Self> {
+        let health = v["health"].as_str()?;
+        let state = v["state"].as_str().unwrap_or(RUNNING);
+        Some(ExchangeStatus::new(time, product_code, ExchangeHealth::from_str(health), state))
+    }
+
+    // ファイルに書き込んだ1行(get_csvの形式)から復元する
+    pub fn from_csv(line: &str) -> Option<Self> {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() != 5 {
+            return None;
+        }
+        let time = DateTime::from_timestamp_millis(fields[0].parse().ok()?)?;
+        let health = ExchangeHealth::from_str(&fields[2].replace('_', " "));
+        Some(ExchangeStatus::new(time, fields[1], health, &fields[3].replace('_', " ")))
+    }
+
+    // 取引が停止しているか(稼働状態がSTOP、またはメンテナンス等で板が稼働していない)
+    pub fn is_halted(&self) -> bool {
+        self.health == ExchangeHealth::Stop || self.state != RUNNING
+    }
+}
+
+// 取引が停止していた期間
+#[derive(Clone, Debug, PartialEq)]
+pub struct HaltPeriod {
+    pub product_code: String,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    // 停止を検知した際の稼働状態と板の稼働状況
+    pub health: ExchangeHealth,
+    pub state: String,
+}
+
+impl Common for HaltPeriod {
+    // 開始時刻(ミリ秒) 終了時刻(ミリ秒) 銘柄 稼働状態 板の稼働状況
+    fn get_csv(&self) -> String {
+        format!(
+            "{} {} {} {} {}\n",
+            self.start.timestamp_millis(),
+            self.end.timestamp_millis(),
+            self.product_code,
+            self.health,
+            self.state.replace(' ', "_")
+        )
+    }
+
+    // 停止が終わった日付のファイルに書き込む
+    fn data_time(&self) -> DateTime<Utc> {
+        self.end
+    }
+
+    fn channel(&self) -> String {
+        String::from("halt")
+    }
+}
+
+// 銘柄毎に取引の停止を検知し、再開した時点で停止していた期間を返す
+// ファイルを指定した場合は停止中の銘柄を書き込み、再起動後も停止の開始時刻を引き継ぐ
+#[derive(Default)]
+pub struct HaltTracker {
+    halted: HashMap<String, ExchangeStatus>,
+    path: Option<PathBuf>,
+}
+
+impl HaltTracker {
+    pub fn new() -> Self {
+        HaltTracker::default()
+    }
+
+    // 停止中の銘柄を書き込むファイルを指定し、前回の起動時に停止中だった銘柄を読み込む
+    // 停止中に再起動した場合、停止していた期間の終了時刻は再起動後に再開を検知した時刻となる
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let mut halted = HashMap::new();
+        match fs::read_to_string(path) {
+            Ok(content) => {
+                for status in content.lines().filter_map(ExchangeStatus::from_csv) {
+                    warn!(
+                        "HaltTracker: {} was halted at startup since {}. {} {}",
+                        status.product_code, status.time, status.health, status.state
+                    );
+                    halted.insert(status.product_code.clone(), status);
+                }
+            }
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {}
+            Err(error) => return Err(error),
+        }
+        Ok(HaltTracker {
+            halted,
+            path: Some(path.to_path_buf()),
+        })
+    }
+
+    // 停止中の銘柄
+    pub fn halted(&self) -> Vec<ExchangeStatus> {
+        let mut halted: Vec<ExchangeStatus> = self.halted.values().cloned().collect();
+        halted.sort_by(|a, b| a.product_code.cmp(&b.product_code));
+        halted
+    }
+
+    pub fn update(&mut self, status: &ExchangeStatus) -> Option<HaltPeriod> {
+        if status.is_halted() {
+            if !self.halted.contains_key(&status.product_code) {
+                warn!("HaltTracker: {} halted. {} {}", status.product_code, status.health, status.state);
+                self.halted.insert(status.product_code.clone(), status.clone());
+                self.save();
+            }
+            return None;
+        }
+
+        let start = self.halted.remove(&status.product_code)?;
+        info!("HaltTracker: {} resumed.", status.product_code);
+        self.save();
+        Some(HaltPeriod {
+            product_code: status.product_code.clone(),
+            start: start.time,
+            end: status.time,
+            health: start.health,
+            state: start.state,
+        })
+    }
+
+    // 停止中の銘柄をファイルに書き込む(書き込みに失敗した場合もメモリ上の状態で検知を続ける)
+    fn save(&self) {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return,
+        };
+        if let Err(error) = self.write(path) {
+            error!(
+                event = event::WRITE_FAILED, file = "open_halts", error:% = error;
+                "HaltTracker.save: {} {}", path.display(), error
+            );
+        }
+    }
+
+    fn write(&self, path: &Path) -> io::Result<()> {
+        if let Some(parent) = path.parent() {
+            create_dir_all(parent)?;
+        }
+        // 書き込み途中の一覧が残らないよう、一時ファイルに書き込んでから置き換える
+        let temp_path = path.with_extension("csv.tmp");
+        let mut file = File::create(&temp_path)?;
+        for status in self.halted() {
+            file.write_all(status.get_csv().as_bytes())?;
+        }
+        file.sync_all()?;
+        fs::rename(temp_path, path)
+    }
+}
+
+// 銘柄の板の状態のURL
+pub fn board_state_url(rest_end_point: &str, product_code: &str) -> String {
+    format!(
+        "{}/v1/getboardstate?product_code={}",
+        rest_end_point.trim_end_matches('/'),
+        product_code
+    )
+}
+
+// 銘柄の取引所の状態を取得する
+pub fn fetch_status(rest_end_point: &str, product_code: &str) -> Result<ExchangeStatus, RestError> {
+    let v = get_json(&board_state_url(rest_end_point, product_code))?;
+    ExchangeStatus::from_json(Utc::now(), product_code, &v)
+        .ok_or_else(|| RestError::Response(v.to_string()))
+}
+
+// 一定間隔で銘柄毎の取引所の状態を取得する
+pub struct StatusPoller {
+    product_codes: Arc<Mutex<Vec<String>>>,
+    statuses: Arc<Mutex<Vec<ExchangeStatus>>>,
+}
+
+impl StatusPoller {
+    // 別スレッドで定期的な取得を開始する
+    pub fn start(rest_end_point: &str, product_codes: Vec<String>, interval: Duration) -> Self {
+        let product_codes = Arc::new(Mutex::new(product_codes));
+        let statuses = Arc::new(Mutex::new(Vec::new()));
+        let rest_end_point = rest_end_point.to_string();
+
+        let thread_product_codes = product_codes.clone();
+        let thread_statuses = statuses.clone();
+        thread::spawn(move || loop {
+            let product_codes = thread_product_codes.lock().unwrap().clone();
+            for product_code in product_codes.iter() {
+                match fetch_status(&rest_end_point, product_code) {
+                    Ok(status) => thread_statuses.lock().unwrap().push(status),
+                    Err(error) => error!("StatusPoller: {} {}", product_code, error),
+                }
+            }
+            thread::sleep(interval);
+        });
+
+        StatusPoller {
+            product_codes,
+            statuses,
+        }
+    }
+
+    // 状態を取得する銘柄を置き換える
+    pub fn set_product_codes(&self, product_codes: Vec<String>) {
+        *self.product_codes.lock().unwrap() = product_codes;
+    }
+
+    // 前回の確認以降に取得した状態を取り出す
+    pub fn take(&self) -> Vec<ExchangeStatus> {
+        std::mem::take(&mut *self.statuses.lock().unwrap())
+    }
+}
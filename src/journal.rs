@@ -0,0 +1,249 @@
+use std::collections::BTreeSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+
+// fsyncしていないファイルの一覧のファイル名
+pub const UNSYNCED_FILE_NAME: &str = "unsynced.log";
+// 先行書き込みログのファイル名
+pub const JOURNAL_FILE_NAME: &str = "journal.log";
+
+// 先行書き込みログの1件(追記先、追記前のファイルのバイト数、追記する内容)
+#[derive(Clone, Debug, PartialEq)]
+pub struct JournalEntry {
+    pub path: PathBuf,
+    pub offset: u64,
+    pub content: Vec<u8>,
+}
+
+impl JournalEntry {
+    // {追記前のバイト数} {内容のバイト数} {追記先}\n{内容}\n
+    fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writeln!(writer, "{} {} {}", self.offset, self.content.len(), self.path.display())?;
+        writer.write_all(&self.content)?;
+        writer.write_all(b"\n")
+    }
+}
+
+// 先行書き込みログを読み込む(書き込み途中で途切れた最後の1件は含めない)
+pub fn read_journal(path: &Path) -> io::Result<Vec<JournalEntry>> {
+    let mut entries = Vec::new();
+    if !path.exists() {
+        return Ok(entries);
+    }
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut header = String::new();
+    loop {
+        header.clear();
+        if reader.read_line(&mut header)? == 0 || !header.ends_with('\n') {
+            break;
+        }
+        let mut fields = header.trim_end_matches('\n').splitn(3, ' ');
+        let (offset, len, target) = match (fields.next(), fields.next(), fields.next()) {
+            (Some(offset), Some(len), Some(target)) => (offset, len, target),
+            _ => break,
+        };
+        let (offset, len): (u64, usize) = match (offset.parse(), len.parse()) {
+            (Ok(offset), Ok(len)) => (offset, len),
+            _ => break,
+        };
+        let mut content = vec![0; len + 1];
+        if reader.read_exact(&mut content).is_err() || content.pop() != Some(b'\n') {
+            break;
+        }
+        entries.push(JournalEntry {
+            path: PathBuf::from(target),
+            offset,
+            content,
+        });
+    }
+    Ok(entries)
+}
+
+// 最後の改行より後ろ(書き込み途中で途切れた行)を切り詰め、切り詰めたバイト数を返す
+pub fn repair_partial_line(path: &Path) -> io::Result<u64> {
+    let mut file = match OpenOptions::new().read(true).write(true).open(path) {
+        Ok(file) => file,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(error) => return Err(error),
+    };
+    let len = file.metadata()?.len();
+    let mut end = len;
+    let mut buffer = [0; 4096];
+    while 0 < end {
+        let start = end.saturating_sub(buffer.len() as u64);
+        let chunk = &mut buffer[..(end - start) as usize];
+        file.seek(SeekFrom::Start(start))?;
+        file.read_exact(chunk)?;
+        if let Some(index) = chunk.iter().rposition(|byte| *byte == b'\n') {
+            end = start + index as u64 + 1;
+            break;
+        }
+        end = start;
+    }
+    if end < len {
+        file.set_len(end)?;
+        file.sync_all()?;
+    }
+    Ok(len - end)
+}
+
+// 起動時の復旧結果
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Recovery {
+    // 先行書き込みログから書き戻した件数
+    pub replayed: usize,
+    // 途中で切れた行を切り詰めたファイルと切り詰めたバイト数
+    pub repaired: Vec<(PathBuf, u64)>,
+}
+
+// 前回の異常終了時に書き込み途中だったファイルを復旧する
+// 先行書き込みログに残っている追記のうちファイルに反映されていないものを書き戻し、
+// fsyncしていなかったファイルの途中で切れた行を切り詰める
+pub fn recover(dir: &Path) -> io::Result<Recovery> {
+    let mut recovery = Recovery::default();
+    let mut targets = BTreeSet::new();
+
+    for entry in read_journal(&dir.join(JOURNAL_FILE_NAME))? {
+        let len = fs::metadata(&entry.path).map(|metadata| metadata.len()).unwrap_or(0);
+        if entry.offset + (entry.content.len() as u64) <= len {
+            continue;
+        }
+        if let Some(parent) = entry.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = OpenOptions::new().create(true).write(true).truncate(false).open(&entry.path)?;
+        // 途中まで書き込まれていた場合は追記前の位置から書き直す
+        if entry.offset < len {
+            file.set_len(entry.offset)?;
+        }
+        file.seek(SeekFrom::End(0))?;
+        file.write_all(&entry.content)?;
+        recovery.replayed += 1;
+        targets.insert(entry.path);
+    }
+
+    let unsynced_path = dir.join(UNSYNCED_FILE_NAME);
+    if unsynced_path.exists() {
+        for line in BufReader::new(File::open(&unsynced_path)?).lines() {
+            let line = line?;
+            if !line.is_empty() {
+                targets.insert(PathBuf::from(line));
+            }
+        }
+    }
+    for path in targets {
+        let removed = repair_partial_line(&path)?;
+        if 0 < removed {
+            recovery.repaired.push((path.clone(), removed));
+        }
+        sync_file(&path)?;
+    }
+
+    truncate(&dir.join(JOURNAL_FILE_NAME))?;
+    truncate(&unsynced_path)?;
+    Ok(recovery)
+}
+
+// ファイルをfsyncする(存在しない場合は何もしない)
+fn sync_file(path: &Path) -> io::Result<()> {
+    match File::open(path) {
+        Ok(file) => file.sync_all(),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(error) => Err(error),
+    }
+}
+
+fn truncate(path: &Path) -> io::Result<()> {
+    if path.exists() {
+        File::create(path)?.sync_all()?;
+    }
+    Ok(())
+}
+
+// CSVへの追記の耐障害性
+// 追記したファイルは一定間隔でfsyncし、fsyncするまでのファイルを一覧に記録する(起動時の修復の対象)
+// 先行書き込みログを有効にした場合は、追記の前に内容をログに書き込んでfsyncする
+pub struct Durability {
+    sync_interval: Duration,
+    last_sync: Option<DateTime<Utc>>,
+    unsynced: BTreeSet<PathBuf>,
+    unsynced_file: File,
+    journal: Option<File>,
+}
+
+impl Durability {
+    // dirにfsyncしていないファイルの一覧と先行書き込みログを書き込む
+    // sync_intervalが0の場合は追記毎にfsyncする
+    pub fn open(dir: &Path, sync_interval: Duration, journal: bool) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        let open = |file_name: &str| OpenOptions::new().create(true).append(true).open(dir.join(file_name));
+        Ok(Durability {
+            sync_interval,
+            last_sync: None,
+            unsynced: BTreeSet::new(),
+            unsynced_file: open(UNSYNCED_FILE_NAME)?,
+            journal: if journal { Some(open(JOURNAL_FILE_NAME)?) } else { None },
+        })
+    }
+
+    // 追記の前に、追記先を一覧に、追記する内容を先行書き込みログに書き込む
+    pub fn before_append(&mut self, path: &Path, content: &[u8]) -> io::Result<()> {
+        if !self.unsynced.contains(path) {
+            writeln!(self.unsynced_file, "{}", path.display())?;
+            self.unsynced_file.sync_data()?;
+            self.unsynced.insert(path.to_path_buf());
+        }
+        if let Some(journal) = &mut self.journal {
+            let entry = JournalEntry {
+                path: path.to_path_buf(),
+                offset: fs::metadata(path).map(|metadata| metadata.len()).unwrap_or(0),
+                content: content.to_vec(),
+            };
+            entry.write_to(journal)?;
+            journal.sync_data()?;
+        }
+        Ok(())
+    }
+
+    // 追記の後に、追記毎にfsyncする設定であればfsyncする
+    pub fn after_append(&mut self) -> io::Result<()> {
+        if self.sync_interval.is_zero() {
+            self.sync()?;
+        }
+        Ok(())
+    }
+
+    // 前回のfsyncから一定時間が経っていればfsyncする
+    pub fn tick(&mut self, now: DateTime<Utc>) -> io::Result<()> {
+        let interval = chrono::Duration::from_std(self.sync_interval).unwrap_or_else(|_| chrono::Duration::zero());
+        match self.last_sync {
+            Some(last_sync) if now < last_sync + interval => Ok(()),
+            _ => {
+                self.last_sync = Some(now);
+                self.sync()
+            }
+        }
+    }
+
+    // 追記したファイルをfsyncし、一覧と先行書き込みログを空にする
+    pub fn sync(&mut self) -> io::Result<()> {
+        if self.unsynced.is_empty() {
+            return Ok(());
+        }
+        for path in self.unsynced.iter() {
+            sync_file(path)?;
+        }
+        self.unsynced.clear();
+        self.unsynced_file.set_len(0)?;
+        self.unsynced_file.sync_data()?;
+        if let Some(journal) = &mut self.journal {
+            journal.set_len(0)?;
+            journal.sync_data()?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,244 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use chrono::{DateTime, Duration as ChronoDuration, NaiveDate, Timelike, Utc};
+
+use log::warn;
+
+use crate::partition::DayPartition;
+use crate::stream_api::{Common, Latency};
+
+// 取引所の時計に対するローカル時計のずれ(マイクロ秒)
+// NTP等で計測したオフセットを受信時刻に加算して遅延を補正する
+#[derive(Clone, Default)]
+pub struct ClockOffset {
+    micros: Arc<AtomicI64>,
+}
+
+impl ClockOffset {
+    // 固定のオフセット(ミリ秒)を設定する
+    pub fn fixed(offset_millis: f64) -> Self {
+        let offset = ClockOffset::default();
+        offset.set_millis(offset_millis);
+        offset
+    }
+
+    // オフセット(ミリ秒)が書かれたファイルを定期的に読み込む
+    // ファイルはntpdate/chronyc等の出力から外部のスクリプトで更新する想定
+    pub fn watch_file(path: PathBuf, interval: Duration) -> Self {
+        let offset = ClockOffset::default();
+        let watched = offset.clone();
+        thread::spawn(move || loop {
+            match fs::read_to_string(&path) {
+                Ok(text) => match text.trim().parse::<f64>() {
+                    Ok(offset_millis) => watched.set_millis(offset_millis),
+                    Err(error) => {
+                        warn!("ClockOffset.watch_file: {} {}", path.display(), error)
+                    }
+                },
+                Err(error) => warn!("ClockOffset.watch_file: {} {}", path.display(), error),
+            }
+            thread::sleep(interval);
+        });
+        offset
+    }
+
+    pub fn set_millis(&self, offset_millis: f64) {
+        self.micros
+            .store((offset_millis * 1000.0).round() as i64, Ordering::Relaxed);
+    }
+
+    pub fn get_micros(&self) -> i64 {
+        self.micros.load(Ordering::Relaxed)
+    }
+
+    // 受信時刻をオフセットで補正する
+    pub fn adjust(&self, time: DateTime<Utc>) -> DateTime<Utc> {
+        time + ChronoDuration::microseconds(self.get_micros())
+    }
+}
+
+// 遅延のパーセンタイル(マイクロ秒)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LatencyPercentiles {
+    pub count: usize,
+    pub p50: i64,
+    pub p90: i64,
+    pub p99: i64,
+    pub max: i64,
+}
+
+impl LatencyPercentiles {
+    // 遅延のサンプルからパーセンタイルを計算する(最近傍順位法)
+    pub fn from_samples(samples: &mut [i64]) -> Option<Self> {
+        if samples.is_empty() {
+            return None;
+        }
+        samples.sort_unstable();
+        let rank = |p: f64| {
+            let index = ((p / 100.0) * samples.len() as f64).ceil() as usize;
+            samples[index.max(1) - 1]
+        };
+        Some(LatencyPercentiles {
+            count: samples.len(),
+            p50: rank(50.0),
+            p90: rank(90.0),
+            p99: rank(99.0),
+            max: samples[samples.len() - 1],
+        })
+    }
+}
+
+// 集計期間の種別
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LatencyPeriod {
+    Minute,
+    Day,
+}
+
+// チャンネル毎・期間毎の遅延の集計結果
+#[derive(Clone, Debug)]
+pub struct LatencySummary {
+    pub period: LatencyPeriod,
+    pub period_start: DateTime<Utc>,
+    pub percentiles: LatencyPercentiles,
+    channel: String,
+}
+
+impl Common for LatencySummary {
+    fn get_csv(&self) -> String {
+        let millis = |micros: i64| micros as f64 / 1000.0;
+        match self.period {
+            LatencyPeriod::Minute => format!(
+                "{} {} {:.3} {:.3} {:.3} {:.3}\n",
+                self.period_start.timestamp_millis(),
+                self.percentiles.count,
+                millis(self.percentiles.p50),
+                millis(self.percentiles.p90),
+                millis(self.percentiles.p99),
+                millis(self.percentiles.max),
+            ),
+            LatencyPeriod::Day => format!(
+                "{} {} {:.3} {:.3} {:.3} {:.3}\n",
+                self.channel,
+                self.percentiles.count,
+                millis(self.percentiles.p50),
+                millis(self.percentiles.p90),
+                millis(self.percentiles.p99),
+                millis(self.percentiles.max),
+            ),
+        }
+    }
+
+    fn data_time(&self) -> DateTime<Utc> {
+        self.period_start
+    }
+
+    fn channel(&self) -> String {
+        self.channel.clone()
+    }
+}
+
+// チャンネル毎の集計中のサンプル
+struct ChannelSamples {
+    minute: DateTime<Utc>,
+    minute_samples: Vec<i64>,
+    day: NaiveDate,
+    day_samples: Vec<i64>,
+}
+
+// 遅延をチャンネル毎に1分単位・1日単位で集計する
+#[derive(Default)]
+pub struct LatencyStats {
+    channels: HashMap<String, ChannelSamples>,
+    // 1日単位の集計の日付の区切り
+    partition: DayPartition,
+}
+
+// 時刻を分単位に切り捨てる
+fn truncate_minute(time: DateTime<Utc>) -> DateTime<Utc> {
+    time.with_second(0).unwrap().with_nanosecond(0).unwrap()
+}
+
+impl LatencyStats {
+    pub fn new() -> Self {
+        LatencyStats::default()
+    }
+
+    // 1日単位の集計の日付の区切りを設定する
+    pub fn set_partition(&mut self, partition: DayPartition) {
+        self.partition = partition;
+    }
+
+    // 遅延のサンプルを追加し、集計期間が終わったチャンネルの集計結果を返す
+    pub fn add(&mut self, latency: &Latency) -> Vec<LatencySummary> {
+        let time = latency.data_time();
+        let channel = latency.get_channel();
+        let partition = &self.partition;
+        let mut summaries = Vec::new();
+        let samples = self
+            .channels
+            .entry(channel.clone())
+            .or_insert_with(|| ChannelSamples {
+                minute: truncate_minute(time),
+                minute_samples: Vec::new(),
+                day: partition.date(time),
+                day_samples: Vec::new(),
+            });
+        summaries.extend(Self::roll(partition, &channel, samples, time));
+        samples.minute_samples.push(latency.get_latency_micros());
+        samples.day_samples.push(latency.get_latency_micros());
+        summaries
+    }
+
+    // 現在時刻までに集計期間が終わったチャンネルの集計結果を返す
+    // サンプルが届かないチャンネルでも1分毎に集計結果を出力するために呼び出す
+    pub fn flush(&mut self, now: DateTime<Utc>) -> Vec<LatencySummary> {
+        let mut summaries = Vec::new();
+        for (channel, samples) in self.channels.iter_mut() {
+            summaries.extend(Self::roll(&self.partition, channel, samples, now));
+        }
+        summaries
+    }
+
+    // 時刻が集計期間を過ぎていれば集計結果を作成し、次の期間に切り替える
+    fn roll(
+        partition: &DayPartition,
+        channel: &str,
+        samples: &mut ChannelSamples,
+        time: DateTime<Utc>,
+    ) -> Vec<LatencySummary> {
+        let mut summaries = Vec::new();
+        if samples.minute < truncate_minute(time) {
+            if let Some(percentiles) = LatencyPercentiles::from_samples(&mut samples.minute_samples)
+            {
+                summaries.push(LatencySummary {
+                    period: LatencyPeriod::Minute,
+                    period_start: samples.minute,
+                    percentiles,
+                    channel: channel.to_string(),
+                });
+            }
+            samples.minute = truncate_minute(time);
+            samples.minute_samples.clear();
+        }
+        if samples.day < partition.date(time) {
+            if let Some(percentiles) = LatencyPercentiles::from_samples(&mut samples.day_samples) {
+                summaries.push(LatencySummary {
+                    period: LatencyPeriod::Day,
+                    period_start: partition.start(samples.day),
+                    percentiles,
+                    channel: channel.to_string(),
+                });
+            }
+            samples.day = partition.date(time);
+            samples.day_samples.clear();
+        }
+        summaries
+    }
+}
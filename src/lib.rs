@@ -1 +1,24 @@
+pub mod auth;
+pub mod clock;
+pub mod convert;
+pub mod dedup;
+pub mod discovery;
+pub mod exchange_status;
+pub mod journal;
+pub mod latency;
+pub mod logging;
+pub mod metrics;
+pub mod order_book;
+pub mod partition;
+pub mod path_template;
+pub mod product;
+pub mod raw_archive;
+pub mod recorder;
+pub mod redundant;
+pub mod rest_api;
+pub mod rotation;
+pub mod scheduler;
+pub mod silence;
+pub mod stats;
 pub mod stream_api;
+pub mod validate;
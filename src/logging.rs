@@ -0,0 +1,131 @@
+use std::env;
+use std::fmt;
+use std::io::Write;
+use std::str::FromStr;
+
+use chrono::{SecondsFormat, Utc};
+
+use log::kv::{Error as KvError, Key, Value as KvValue, VisitSource};
+use log::Record;
+
+use serde_json::{Map, Value};
+
+// ログに付与する項目(exchange/channel/event/error)のうち、eventに用いる名前
+// 監視や集計で参照するため、変更しないこと
+pub mod event {
+    // ストリーミングAPIへの接続・切断
+    pub const CONNECT: &str = "connect";
+    pub const DISCONNECT: &str = "disconnect";
+    // 切断後の再接続
+    pub const RECONNECT: &str = "reconnect";
+    // 認証の失敗
+    pub const AUTH_FAILED: &str = "auth_failed";
+    // チャンネルの購読(リクエストの送信・承認・失敗)
+    pub const SUBSCRIBE: &str = "subscribe";
+    pub const SUBSCRIBED: &str = "subscribed";
+    pub const SUBSCRIBE_FAILED: &str = "subscribe_failed";
+    // チャンネルの購読の停止(リクエストの送信・承認・失敗)
+    pub const UNSUBSCRIBE: &str = "unsubscribe";
+    pub const UNSUBSCRIBED: &str = "unsubscribed";
+    pub const UNSUBSCRIBE_FAILED: &str = "unsubscribe_failed";
+    // 受信が途絶えたチャンネルの再購読
+    pub const RESUBSCRIBE: &str = "resubscribe";
+    // 受信のない時間が閾値を超えた
+    pub const SILENCE: &str = "silence";
+    // ファイルへの書き込みの失敗
+    pub const WRITE_FAILED: &str = "write_failed";
+    // 板の不整合・スナップショットとのずれ
+    pub const BOOK_INCONSISTENT: &str = "book_inconsistent";
+    pub const BOOK_DRIFT: &str = "book_drift";
+}
+
+// ログの出力形式
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LogFormat {
+    // 1行のテキスト(項目は末尾にkey=valueで付与する)
+    Text,
+    // 1行1オブジェクトのJSON
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!("unknown log format: {} (text or json)", s)),
+        }
+    }
+}
+
+impl fmt::Display for LogFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LogFormat::Text => write!(f, "text"),
+            LogFormat::Json => write!(f, "json"),
+        }
+    }
+}
+
+// ログの既定の出力レベル
+pub const DEFAULT_LOG_LEVEL: &str = "info";
+
+// ロガーを初期化する
+// 出力レベルは引数、環境変数RUST_LOG、既定値(info)の順に参照する(env_loggerと同じ書式)
+pub fn init(level: Option<&str>, format: LogFormat) {
+    let level = match level {
+        Some(level) => level.to_string(),
+        None => env::var("RUST_LOG").unwrap_or_else(|_| String::from(DEFAULT_LOG_LEVEL)),
+    };
+
+    let mut builder = env_logger::Builder::new();
+    builder.parse_filters(&level);
+    builder.format(move |buf, record| writeln!(buf, "{}", format_record(format, record)));
+    builder.init();
+}
+
+// ログの1行を生成する
+pub fn format_record(format: LogFormat, record: &Record) -> String {
+    let time = Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
+    let mut fields = Fields(Vec::new());
+    let _ = record.key_values().visit(&mut fields);
+
+    match format {
+        LogFormat::Text => {
+            let mut line = format!(
+                "[{} {:<5} {}] {}",
+                time,
+                record.level(),
+                record.target(),
+                record.args()
+            );
+            for (key, value) in fields.0 {
+                line.push_str(&format!(" {}={}", key, value));
+            }
+            line
+        }
+        LogFormat::Json => {
+            let mut object = Map::new();
+            object.insert(String::from("time"), Value::from(time));
+            object.insert(String::from("level"), Value::from(record.level().as_str()));
+            object.insert(String::from("target"), Value::from(record.target()));
+            object.insert(String::from("message"), Value::from(record.args().to_string()));
+            for (key, value) in fields.0 {
+                object.insert(key, Value::from(value));
+            }
+            Value::Object(object).to_string()
+        }
+    }
+}
+
+// ログに付与された項目を文字列として集める
+struct Fields(Vec<(String, String)>);
+
+impl<'kvs> VisitSource<'kvs> for Fields {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: KvValue<'kvs>) -> Result<(), KvError> {
+        self.0.push((key.to_string(), value.to_string()));
+        Ok(())
+    }
+}
@@ -1,43 +1,733 @@
 extern crate fetch_market_and_order_data;
 
-use std::fs::{create_dir_all, OpenOptions};
-use std::io::{BufWriter, Write};
-use fetch_market_and_order_data::stream_api::{BfWebsocket, Common, MarketInfo};
+use fetch_market_and_order_data::auth::Credentials;
+use fetch_market_and_order_data::convert::{convert, Format};
+use fetch_market_and_order_data::discovery::{fetch_markets, resolve_products, Market, ProductDiscovery};
+use fetch_market_and_order_data::exchange_status::{HaltTracker, StatusPoller, OPEN_HALTS_FILE_NAME};
+use fetch_market_and_order_data::journal::{recover, Durability};
+use fetch_market_and_order_data::latency::ClockOffset;
+use fetch_market_and_order_data::logging::{self, event, LogFormat};
+use fetch_market_and_order_data::metrics::{serve, Metrics};
+use fetch_market_and_order_data::order_book::{BookEvent, OrderBooks};
+use fetch_market_and_order_data::partition::{parse_timezone, DayPartition};
+use fetch_market_and_order_data::path_template::{PathTemplate, DEFAULT_PATH_TEMPLATE};
+use fetch_market_and_order_data::product::{ChannelKind, Product, ProductRegistry, DEFAULT_PRODUCT_CODES};
+use fetch_market_and_order_data::raw_archive::{prepare_replay_dir, replay};
+use fetch_market_and_order_data::recorder::{Recorder, TICK_INTERVAL};
+use fetch_market_and_order_data::redundant::RedundantWebsocket;
+use fetch_market_and_order_data::rotation::Rotation;
+use fetch_market_and_order_data::rest_api::BF_REST_END_POINT;
+use fetch_market_and_order_data::silence::{parse_channel_silence, SilenceAction, SilenceMonitor};
+use fetch_market_and_order_data::stats::daily_stats;
+use fetch_market_and_order_data::stream_api::{BfWebsocket, Common, MarketInfo, BF_END_POINT};
+use fetch_market_and_order_data::validate::{parse_date, validate_range, DataKind};
 
+use chrono::{FixedOffset, NaiveDate};
+use std::ffi::OsString;
 use std::path::PathBuf;
 use structopt::StructOpt;
 
 use std::thread::sleep;
-use std::time::{Duration, SystemTime};
+use std::time::{Duration, Instant, SystemTime};
 
 use std::sync::mpsc::TryRecvError;
 
-use env_logger;
 use log::{info, warn, error};
-use std::env;
 
+// 各コマンドに共通の設定(記録先と銘柄、ログ)
 #[derive(StructOpt, Debug)]
-#[structopt(name = "fetch_market_and_order_data")]
-struct Opt {
+struct CommonOpt {
     #[structopt(short, long, default_value("."))]
     output_dir: PathBuf,
+
+    // 出力先のディレクトリからの書き込み先のパスのテンプレート
+    // 埋め込み項目は{exchange}{product}{base}{quote}{channel}{kind}{name}{date}{yyyy}{mm}{dd}{ext}
+    #[structopt(long, default_value(DEFAULT_PATH_TEMPLATE))]
+    path_template: PathTemplate,
+
+    // 日付毎のファイルの分割方法(daily/hourly/100MB等のバイト数)
+    #[structopt(long, default_value("daily"))]
+    rotation: Rotation,
+
+    // 日付毎のディレクトリの区切りに用いるタイムゾーン(UTC/JST/+09:00等)
+    #[structopt(long, default_value("UTC"), parse(try_from_str = parse_timezone))]
+    timezone: FixedOffset,
+
+    // 日付毎のディレクトリを区切る時刻(タイムゾーンの0-23時)
+    #[structopt(long, default_value("0"))]
+    day_cutoff_hour: u32,
+
+    // 対象の銘柄(複数指定可、未指定の場合はFX_BTC_JPYとBTC_JPY)
+    #[structopt(long)]
+    product: Vec<String>,
+
+    // HTTP APIのマーケットの一覧から銘柄を取得する(BTCJPY_MAT1WK等の別名を指定できる)
+    #[structopt(long)]
+    discover_products: bool,
+
+    // HTTP APIのエンドポイント
+    #[structopt(long, default_value(BF_REST_END_POINT))]
+    rest_end_point: String,
+
+    // ログの出力レベル(env_loggerの書式、未指定の場合は環境変数RUST_LOG、既定はinfo)
+    #[structopt(long)]
+    log_level: Option<String>,
+
+    // ログの出力形式(text/json)
+    #[structopt(long, env = "LOG_FORMAT", default_value("text"))]
+    log_format: LogFormat,
+}
+
+// 記録先への書き込みの設定(記録・再解析)
+#[derive(StructOpt, Debug)]
+struct WriteOpt {
+    // 重複の確認に用いる直近の約定IDの件数(0の場合は確認しない)
+    #[structopt(long, default_value("100000"))]
+    dedup_window: usize,
+
+    // 追記したファイルをfsyncする間隔(秒、0の場合は追記毎)
+    #[structopt(long, default_value("5"))]
+    fsync_interval_secs: u64,
+
+    // 追記の前に内容を先行書き込みログに書き込み、異常終了時にも追記した内容を失わないようにする
+    #[structopt(long)]
+    journal: bool,
+}
+
+// 遅延の補正に用いる時計のずれの設定(記録・再解析)
+#[derive(StructOpt, Debug)]
+struct ClockOpt {
+    // 遅延の補正に用いるNTPで計測した時計のずれ(ミリ秒)
+    #[structopt(long, allow_hyphen_values(true))]
+    clock_offset_ms: Option<f64>,
+
+    // 時計のずれ(ミリ秒)が書かれたファイル(1分毎に再読み込みする)
+    #[structopt(long, conflicts_with("clock-offset-ms"))]
+    clock_offset_file: Option<PathBuf>,
+}
+
+impl ClockOpt {
+    fn clock_offset(&self) -> ClockOffset {
+        match (&self.clock_offset_ms, &self.clock_offset_file) {
+            (Some(offset_millis), _) => ClockOffset::fixed(*offset_millis),
+            (None, Some(path)) => ClockOffset::watch_file(path.clone(), Duration::from_secs(60)),
+            (None, None) => ClockOffset::default(),
+        }
+    }
+}
+
+#[derive(StructOpt, Debug)]
+struct RecordOpt {
+    #[structopt(flatten)]
+    common: CommonOpt,
+
+    #[structopt(flatten)]
+    write: WriteOpt,
+
+    #[structopt(flatten)]
+    clock: ClockOpt,
+
+    // 同じチャンネルを購読する2つの接続から受信し、一方の切断中も記録を続ける
+    #[structopt(long)]
+    redundant: bool,
+
+    // 冗長化した場合に、もう一方の接続から同じ約定を受信するまで待つ時間(ミリ秒)
+    #[structopt(long, default_value("500"))]
+    merge_hold_ms: u64,
+
+    // プライベートチャンネル用の認証情報ファイル(未指定の場合は環境変数BF_API_KEY/BF_API_SECRETを参照する)
+    #[structopt(long)]
+    credentials_file: Option<PathBuf>,
+
+    // 受信したフレームをそのまま[{取引所}/{受信日}/raw.log.gz]に記録する
+    #[structopt(long)]
+    raw_capture: bool,
+
+    // ストリーミングAPIのエンドポイント
+    #[structopt(long, default_value(BF_END_POINT))]
+    end_point: String,
+
+    // 板情報(差分・スナップショット)も購読する
+    #[structopt(long)]
+    boards: bool,
+
+    // マーケットの一覧を再取得し、購読する銘柄を更新する間隔(秒)
+    #[structopt(long, default_value("3600"))]
+    discovery_interval_secs: u64,
+
+    // HTTP APIから銘柄毎の取引所の状態を取得し、[{取引所}/{取得日}/status.csv]に記録する
+    #[structopt(long)]
+    exchange_status: bool,
+
+    // 取引所の状態を取得する間隔(秒)
+    #[structopt(long, default_value("60"))]
+    status_interval_secs: u64,
+
+    // スナップショットチャンネルを再購読する間隔(秒)
+    #[structopt(long, default_value("3600"))]
+    snapshot_interval_secs: u64,
+
+    // スナップショットと板のずれを警告する閾値(数量の差の合計の割合)
+    #[structopt(long, default_value("0.05"))]
+    book_drift_threshold: f64,
+
+    // 受信がない場合に再接続するまでの時間(秒)
+    #[structopt(long, default_value("180"))]
+    silence_threshold_secs: u64,
+
+    // チャンネル毎の受信がない時間の閾値({チャンネル}={秒}、複数指定可)
+    // 閾値を超えた場合はそのチャンネルを再購読し、再購読後も受信がなければ再接続する
+    #[structopt(long, parse(try_from_str = parse_channel_silence))]
+    channel_silence: Vec<(String, Duration)>,
+
+    // 監視用のHTTPサーバー(/metrics, /health)の待受アドレス(未指定の場合は起動しない)
+    #[structopt(long)]
+    metrics_addr: Option<String>,
+}
+
+#[derive(StructOpt, Debug)]
+struct ReplayOpt {
+    #[structopt(flatten)]
+    common: CommonOpt,
+
+    #[structopt(flatten)]
+    write: WriteOpt,
+
+    #[structopt(flatten)]
+    clock: ClockOpt,
+
+    // 板情報(差分・スナップショット)も出力する
+    #[structopt(long)]
+    boards: bool,
+
+    // 出力先のディレクトリが空でない場合は中身を削除してから書き込む
+    // (未指定の場合、出力先は存在しないか空のディレクトリである必要がある)
+    #[structopt(long)]
+    overwrite: bool,
+
+    // 記録済みのフレーム(raw.log.gz、複数指定可)
+    #[structopt(required(true))]
+    files: Vec<PathBuf>,
+}
+
+#[derive(StructOpt, Debug)]
+struct ValidateOpt {
+    #[structopt(flatten)]
+    common: CommonOpt,
+
+    // 検証する最初の日付(20201001/2020-10-01)
+    #[structopt(long, parse(try_from_str = parse_date))]
+    from: NaiveDate,
+
+    // 検証する最後の日付(未指定の場合はfromと同じ)
+    #[structopt(long, parse(try_from_str = parse_date))]
+    to: Option<NaiveDate>,
+
+    // 重複等を除き時刻順に並び替えたファイルを書き込むディレクトリ
+    #[structopt(long)]
+    repair_dir: Option<PathBuf>,
+}
+
+#[derive(StructOpt, Debug)]
+struct ConvertOpt {
+    #[structopt(flatten)]
+    common: CommonOpt,
+
+    // 約定データ・遅延データの種類(executions/latency、未指定の場合はファイル名から推定する)
+    #[structopt(long)]
+    kind: Option<DataKind>,
+
+    // 読み込むファイルの形式(space/csv/jsonl/parquet/arrow、未指定の場合は推定する)
+    #[structopt(long)]
+    input_format: Option<Format>,
+
+    // 書き込むファイルの形式(未指定の場合は拡張子から推定する)
+    #[structopt(long)]
+    output_format: Option<Format>,
+
+    // 読み込むファイル
+    input: PathBuf,
+
+    // 書き込むファイル
+    output: PathBuf,
+}
+
+#[derive(StructOpt, Debug)]
+struct StatsOpt {
+    #[structopt(flatten)]
+    common: CommonOpt,
+
+    // 集計する日付(20201001/2020-10-01)
+    #[structopt(long, parse(try_from_str = parse_date))]
+    date: NaiveDate,
+
+    // 集計するチャンネル(複数指定可、未指定の場合は銘柄毎の約定のチャンネル)
+    #[structopt(long)]
+    channel: Vec<String>,
+
+    // 約定がない期間を途切れとして扱う長さ(秒)
+    #[structopt(long, default_value("60"))]
+    gap_secs: u64,
+
+    // 表の代わりにJSON(1チャンネル1行)で出力する
+    #[structopt(long)]
+    json: bool,
+}
+
+#[derive(StructOpt, Debug)]
+struct ListProductsOpt {
+    #[structopt(flatten)]
+    common: CommonOpt,
+}
+
+#[derive(StructOpt, Debug)]
+#[structopt(name = "fetch_market_and_order_data")]
+enum Opt {
+    // ストリーミングAPIから受信したマーケット情報を記録する
+    Record(RecordOpt),
+    // 記録済みのフレーム(raw.log.gz)を再解析して約定データ等を出力する
+    Replay(ReplayOpt),
+    // 約定データ・遅延データの形式を変換する(空白区切り/見出し付きCSV/JSON Lines/Parquet/Arrow IPC)
+    Convert(ConvertOpt),
+    // 記録済みの約定データ・遅延データを検証し、異常を行番号とともに出力する
+    Validate(ValidateOpt),
+    // 記録済みの約定データ・遅延データから日付とチャンネル毎の統計を出力する
+    Stats(StatsOpt),
+    // 対象の銘柄の一覧を出力する(銘柄の指定がない場合は取り扱う全ての銘柄)
+    ListProducts(ListProductsOpt),
+}
+
+// サブコマンドとして解釈する引数(ヘルプ・バージョンの表示を含む)
+const SUBCOMMANDS: [&str; 11] = [
+    "record", "replay", "convert", "validate", "stats", "list-products", "help", "-h", "--help", "-V", "--version",
+];
+
+impl Opt {
+    // コマンドライン引数を解析する
+    // サブコマンドの指定がない場合は、サブコマンドを導入する前と同じく記録する(fetch_market_and_order_data -o DIR)
+    fn from_args_or_record<I, T>(args: I) -> Result<Self, structopt::clap::Error>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<OsString>,
+    {
+        let mut args: Vec<OsString> = args.into_iter().map(Into::into).collect();
+        let has_subcommand = args
+            .get(1)
+            .map(|arg| SUBCOMMANDS.iter().any(|subcommand| arg == subcommand) || !arg.to_string_lossy().starts_with('-'))
+            .unwrap_or(false);
+        if !has_subcommand {
+            args.insert(1.min(args.len()), OsString::from("record"));
+        }
+        Opt::from_iter_safe(args)
+    }
+
+    fn common(&self) -> &CommonOpt {
+        match self {
+            Opt::Record(opt) => &opt.common,
+            Opt::Replay(opt) => &opt.common,
+            Opt::Convert(opt) => &opt.common,
+            Opt::Validate(opt) => &opt.common,
+            Opt::Stats(opt) => &opt.common,
+            Opt::ListProducts(opt) => &opt.common,
+        }
+    }
 }
 
 fn main() {
-    env::set_var("RUST_LOG", "info");
-    env_logger::init();
+    // コマンドライン引数から実行するコマンドと配信データ保存先を取得
+    let opt = Opt::from_args_or_record(std::env::args_os()).unwrap_or_else(|error| error.exit());
+    let common = opt.common();
+    logging::init(common.log_level.as_deref(), common.log_format);
+
+    match &opt {
+        Opt::Record(opt) => record(opt),
+        Opt::Replay(opt) => replay_raw(opt),
+        Opt::Convert(opt) => convert_file(opt),
+        Opt::Validate(opt) => validate(opt),
+        Opt::Stats(opt) => stats(opt),
+        Opt::ListProducts(opt) => list_products(opt),
+    }
+}
+
+// 指定された銘柄コード(未指定の場合は既定の銘柄)
+fn product_codes(common: &CommonOpt) -> Vec<String> {
+    if common.product.is_empty() {
+        DEFAULT_PRODUCT_CODES.iter().map(|code| code.to_string()).collect()
+    } else {
+        common.product.clone()
+    }
+}
 
-    // コマンドライン引数から配信データ保存先を取得
-    let opt = Opt::from_args();
-    let output_dir = &opt.output_dir.display().to_string();
+// HTTP APIからマーケットの一覧を取得する(--discover-productsの指定がない場合、取得できない場合はNone)
+fn discover_markets(common: &CommonOpt) -> Option<Vec<Market>> {
+    if !common.discover_products {
+        return None;
+    }
+    match fetch_markets(&common.rest_end_point) {
+        Ok(markets) => Some(markets),
+        Err(error) => {
+            warn!("discover_products: {}. Use registered products.", error);
+            None
+        }
+    }
+}
 
+// 銘柄コードから銘柄を取得する(マーケットの一覧から取得できない場合は登録済みの銘柄を用いる)
+fn resolve(common: &CommonOpt, product_codes: &[String], registry: &ProductRegistry) -> Option<Vec<Product>> {
+    resolve_with_markets(product_codes, discover_markets(common).as_deref(), registry)
+}
+
+// 取得済みのマーケットの一覧から銘柄を取得する(一覧がない場合、取得できない場合は登録済みの銘柄を用いる)
+fn resolve_with_markets(
+    product_codes: &[String],
+    markets: Option<&[Market]>,
+    registry: &ProductRegistry,
+) -> Option<Vec<Product>> {
+    let discovered = match markets.map(|markets| resolve_products(product_codes, markets, registry)) {
+        Some(Ok(products)) => Some(products),
+        Some(Err(error)) => {
+            warn!("discover_products: {}. Use registered products.", error);
+            None
+        }
+        None => None,
+    };
+    match discovered {
+        Some(products) => Some(products),
+        None => match registry.resolve(product_codes) {
+            Ok(products) => Some(products),
+            Err(error) => {
+                error!("ProductRegistry.resolve: {}", error);
+                None
+            }
+        },
+    }
+}
+
+// 共通の設定から書き込み先を作成する
+fn create_recorder(common: &CommonOpt, exchange: &str, products: &[Product]) -> Option<Recorder> {
+    let mut recorder = Recorder::new(&common.output_dir.display().to_string(), exchange);
+    recorder.set_path_template(common.path_template.clone());
+    recorder.set_rotation(common.rotation);
+    recorder.set_products(products);
+    match DayPartition::new(common.timezone, common.day_cutoff_hour) {
+        Ok(partition) => recorder.set_partition(partition),
+        Err(error) => {
+            error!("DayPartition::new: {}", error);
+            return None;
+        }
+    }
+    Some(recorder)
+}
+
+// 先行書き込みログ等、再起動後に引き継ぐ状態を書き込むディレクトリ
+fn journal_dir(common: &CommonOpt, exchange: &str) -> PathBuf {
+    common.output_dir.join(".journal").join(exchange)
+}
+
+// 前回の異常終了時に書き込み途中だったファイルを復旧してから書き込みを始める
+// fsyncしていないファイルの一覧と先行書き込みログは[{指定ディレクトリ}/.journal/{取引所}]に書き込む
+fn prepare_write(common: &CommonOpt, write: &WriteOpt, exchange: &str, recorder: &mut Recorder) -> bool {
+    recorder.set_dedup_window(write.dedup_window);
+    let journal_dir = journal_dir(common, exchange);
+    match recover(&journal_dir) {
+        Ok(recovery) => {
+            for (path, removed) in recovery.repaired.iter() {
+                warn!("recover: Truncated {} bytes of partial line in {}", removed, path.display());
+            }
+            if 0 < recovery.replayed {
+                info!("recover: Replayed {} journal entries", recovery.replayed);
+            }
+        }
+        Err(error) => {
+            error!("recover: {} {}", journal_dir.display(), error);
+            return false;
+        }
+    }
+    match Durability::open(&journal_dir, Duration::from_secs(write.fsync_interval_secs), write.journal) {
+        Ok(durability) => recorder.set_durability(durability),
+        Err(error) => {
+            error!("Durability::open: {} {}", journal_dir.display(), error);
+            return false;
+        }
+    }
+    true
+}
+
+// 記録済みのフレームを再解析し、新しい出力先のディレクトリに書き込む
+fn replay_raw(opt: &ReplayOpt) {
+    let registry = ProductRegistry::bitflyer();
+    let products = match resolve(&opt.common, &product_codes(&opt.common), &registry) {
+        Some(products) => products,
+        None => return,
+    };
+    // 記録済みの日付を再解析しても記録済みの約定IDで除かれたり追記されたりしないよう、空の出力先にのみ書き込む
+    if let Err(error) = prepare_replay_dir(&opt.common.output_dir, &opt.files, opt.overwrite) {
+        error!("replay: {}", error);
+        std::process::exit(2);
+    }
+    let exchange = BfWebsocket::new().get_exchange_name();
+    let mut recorder = match create_recorder(&opt.common, &exchange, &products) {
+        Some(recorder) => recorder,
+        None => return,
+    };
+    if !prepare_write(&opt.common, &opt.write, &exchange, &mut recorder) {
+        return;
+    }
+
+    let mut bf = BfWebsocket::new();
+    bf.set_products(products);
+    bf.set_boards(opt.boards);
+    bf.set_clock_offset(opt.clock.clock_offset());
+    let mut parser = bf.frame_parser();
+    for path in opt.files.iter() {
+        match replay(path, &mut parser, |message| recorder.record(&message)) {
+            Ok(count) => info!("replay: {} frames from {}", count, path.display()),
+            Err(error) => error!("replay: {} {}", path.display(), error),
+        }
+    }
+    recorder.tick(chrono::Utc::now());
+    recorder.finish();
+}
+
+// 約定データ・遅延データの形式を変換する
+fn convert_file(opt: &ConvertOpt) {
+    let output_format = match opt.output_format.or_else(|| Format::from_extension(&opt.output)) {
+        Some(format) => format,
+        None => {
+            error!("convert: Unknown output format of {}. Specify --output-format.", opt.output.display());
+            std::process::exit(2);
+        }
+    };
+    let kind = opt.kind.unwrap_or_else(|| DataKind::from_path(&opt.input));
+    match convert(&opt.input, opt.input_format, &opt.output, output_format, kind) {
+        Ok(count) => info!(
+            "convert: {} rows from {} to {} ({})",
+            count,
+            opt.input.display(),
+            opt.output.display(),
+            output_format
+        ),
+        Err(error) => {
+            error!("convert: {} {}", opt.input.display(), error);
+            std::process::exit(2);
+        }
+    }
+}
+
+// 記録済みの約定データ・遅延データを検証する(異常があれば終了コード1、検証に失敗した場合は2)
+fn validate(opt: &ValidateOpt) {
+    let registry = ProductRegistry::bitflyer();
+    let products = match resolve(&opt.common, &product_codes(&opt.common), &registry) {
+        Some(products) => products,
+        None => std::process::exit(2),
+    };
+    let exchange = BfWebsocket::new().get_exchange_name();
+    let recorder = match create_recorder(&opt.common, &exchange, &products) {
+        Some(recorder) => recorder,
+        None => std::process::exit(2),
+    };
+
+    // 検証するのは約定データとその遅延データのみ
+    let channels: Vec<String> = products.iter().map(|product| product.channel(ChannelKind::Executions)).collect();
+    match validate_range(&recorder, &channels, opt.from, opt.to.unwrap_or(opt.from), opt.repair_dir.as_deref()) {
+        Ok(anomalies) => {
+            for anomaly in anomalies.iter() {
+                println!("{}", anomaly);
+            }
+            info!("validate: {} anomalies", anomalies.len());
+            if !anomalies.is_empty() {
+                std::process::exit(1);
+            }
+        }
+        Err(error) => {
+            error!("validate: {}", error);
+            std::process::exit(2);
+        }
+    }
+}
+
+// 日付とチャンネル毎の統計を出力する
+fn stats(opt: &StatsOpt) {
+    let registry = ProductRegistry::bitflyer();
+    let products = match resolve(&opt.common, &product_codes(&opt.common), &registry) {
+        Some(products) => products,
+        None => std::process::exit(2),
+    };
+    let exchange = BfWebsocket::new().get_exchange_name();
+    let recorder = match create_recorder(&opt.common, &exchange, &products) {
+        Some(recorder) => recorder,
+        None => std::process::exit(2),
+    };
+
+    let channels: Vec<String> = if opt.channel.is_empty() {
+        products.iter().map(|product| product.channel(ChannelKind::Executions)).collect()
+    } else {
+        opt.channel.clone()
+    };
+    for channel in channels.iter() {
+        match daily_stats(&recorder, channel, opt.date, Duration::from_secs(opt.gap_secs), chrono::Utc::now()) {
+            Ok(stats) if opt.json => println!("{}", stats.to_json()),
+            Ok(stats) => println!("{}", stats),
+            Err(error) => {
+                error!("stats: {} {}", channel, error);
+                std::process::exit(2);
+            }
+        }
+    }
+}
+
+// 銘柄の一覧を出力する[銘柄コード 通貨ペア 呼値の単位 数量の単位]
+fn list_products(opt: &ListProductsOpt) {
+    let registry = ProductRegistry::bitflyer();
+    // マーケットの一覧は1度だけ取得し、銘柄の一覧と銘柄の取得で共通して用いる
+    let markets = discover_markets(&opt.common);
+    let product_codes = match (opt.common.product.is_empty(), &markets) {
+        (false, _) => opt.common.product.clone(),
+        (true, Some(markets)) => markets.iter().map(|market| market.product_code.clone()).collect(),
+        (true, None) => registry.products().iter().map(|product| product.get_code()).collect(),
+    };
+    let products = match resolve_with_markets(&product_codes, markets.as_deref(), &registry) {
+        Some(products) => products,
+        None => std::process::exit(2),
+    };
+    for product in products.iter() {
+        println!(
+            "{} {} {} {}",
+            product.get_code(),
+            product.get_currency_pair(),
+            product.get_tick_size(),
+            product.get_size_unit()
+        );
+    }
+}
+
+// ストリーミングAPIから受信したマーケット情報を記録する
+fn record(opt: &RecordOpt) {
+    // プライベートチャンネル用の認証情報を取得する
+    let credentials = match &opt.credentials_file {
+        Some(path) => match Credentials::from_file(path) {
+            Ok(credentials) => Some(credentials),
+            Err(error) => {
+                error!("Credentials::from_file: {}", error);
+                return;
+            }
+        },
+        None => Credentials::from_env(),
+    };
+
+    // 購読する銘柄を取得する
+    let product_codes = product_codes(&opt.common);
+    let registry = ProductRegistry::bitflyer();
+    let mut products = match resolve(&opt.common, &product_codes, &registry) {
+        Some(products) => products,
+        None => return,
+    };
+    info!(
+        "products: {:?}",
+        products.iter().map(|product| product.get_code()).collect::<Vec<_>>()
+    );
+
+    // 遅延の補正に用いる時計のずれを取得する
+    let clock_offset = opt.clock.clock_offset();
+
+    // 受信がない場合に再接続するまでの時間
+    let silence_threshold = Duration::from_secs(opt.silence_threshold_secs);
+
+    // 監視用の計測値
+    let metrics = Metrics::new();
+
+    // 受信したマーケット情報の書き込み先
+    let exchange = BfWebsocket::new().get_exchange_name();
+    let mut recorder = match create_recorder(&opt.common, &exchange, &products) {
+        Some(recorder) => recorder,
+        None => return,
+    };
+    recorder.set_metrics(metrics.clone());
+    if !prepare_write(&opt.common, &opt.write, &exchange, &mut recorder) {
+        return;
+    }
+
+    // 取引の停止中に再起動しても停止していた期間を記録できるよう、停止中の銘柄を引き継ぐ
+    if opt.exchange_status {
+        let path = journal_dir(&opt.common, &exchange).join(OPEN_HALTS_FILE_NAME);
+        match HaltTracker::open(&path) {
+            Ok(halts) => recorder.set_halt_tracker(halts),
+            Err(error) => {
+                error!("HaltTracker::open: {} {}", path.display(), error);
+                return;
+            }
+        }
+    }
+
+    // 板の整合性の確認に用いる板は再接続をまたいで保持する(再接続後はスナップショットで置き換わる)
+    let mut books = OrderBooks::new();
+    books.set_drift_threshold(opt.book_drift_threshold);
+
+    // 監視用のHTTPサーバーを起動する
+    if let Some(metrics_addr) = &opt.metrics_addr {
+        if let Err(error) = serve(metrics_addr.as_str(), metrics.clone(), silence_threshold) {
+            error!("metrics::serve: {} {}", metrics_addr, error);
+            return;
+        }
+    }
+
+    // 購読する銘柄を定期的に更新する
+    let discovery = if opt.common.discover_products {
+        Some(ProductDiscovery::start(
+            &opt.common.rest_end_point,
+            product_codes,
+            registry,
+            products.clone(),
+            Duration::from_secs(opt.discovery_interval_secs),
+        ))
+    } else {
+        None
+    };
+
+    // 取引所の状態を定期的に取得する
+    let product_codes = |products: &[Product]| products.iter().map(|product| product.get_code()).collect();
+    let status_poller = if opt.exchange_status {
+        Some(StatusPoller::start(
+            &opt.common.rest_end_point,
+            product_codes(&products),
+            Duration::from_secs(opt.status_interval_secs),
+        ))
+    } else {
+        None
+    };
+
+    // 集計期間が終わった遅延の集計結果等は受信の有無に関わらず定期的に書き込む
+    let mut last_tick = Instant::now();
+    let mut connected = false;
     loop {
+        if connected {
+            metrics.on_reconnect();
+            info!(event = event::RECONNECT, exchange = exchange.as_str(); "Reconnect to bitFlyer Websocket Service.");
+        }
+        connected = true;
+
         // BitFlyerのストリーミングAPIに接続する
-        let bf = BfWebsocket::new();
-        bf.on_connect();
-        info!("Connect to bitFlyer Websocket Service.");
+        // 冗長化する場合は同じチャンネルを購読する2つの接続から受信したメッセージを統合する
+        let connect = |products: Vec<Product>| {
+            let mut bf = BfWebsocket::new();
+            bf.set_end_point(&opt.end_point);
+            bf.set_products(products);
+            bf.set_boards(opt.boards);
+            if let Some(credentials) = &credentials {
+                bf.set_credentials(credentials.clone());
+            }
+            bf.set_clock_offset(clock_offset.clone());
+            bf.set_raw_capture(opt.raw_capture);
+            bf.set_snapshot_interval(Duration::from_secs(opt.snapshot_interval_secs));
+            bf
+        };
+        let connections = if opt.redundant { 2 } else { 1 };
+        let merge_hold = Duration::from_millis(opt.merge_hold_ms);
+        let mut bf = RedundantWebsocket::start(connect, products.clone(), connections, merge_hold);
+        info!(event = event::CONNECT, exchange = exchange.as_str(); "Connect to bitFlyer Websocket Service.");
 
-        let exchange_name = bf.get_exchange_name();
+        // チャンネル毎の受信がない時間の監視は接続毎に開始する
+        let mut silence = SilenceMonitor::new(opt.channel_silence.iter().cloned().collect(), Instant::now());
 
         let mut last_recv_time = SystemTime::now()
             .duration_since(SystemTime::UNIX_EPOCH)
@@ -46,6 +736,52 @@ fn main() {
 
         // ストリーミングAPIから配信される情報を取得する
         loop {
+            // 受信が途絶えたチャンネルは再購読し、再購読後も受信がなければ再接続する
+            let mut reconnect = false;
+            for action in silence.check(Instant::now()) {
+                match action {
+                    SilenceAction::Resubscribe(channel) => {
+                        warn!(
+                            event = event::SILENCE, exchange = exchange.as_str(), channel = channel.as_str();
+                            "silence: No data received on {}. Resubscribe.", channel
+                        );
+                        bf.request_resubscribe(&channel);
+                    }
+                    SilenceAction::Reconnect(channel) => {
+                        warn!(
+                            event = event::SILENCE, exchange = exchange.as_str(), channel = channel.as_str();
+                            "silence: No data received on {} after resubscribe. Reconnect.", channel
+                        );
+                        reconnect = true;
+                    }
+                }
+            }
+            if reconnect {
+                break;
+            }
+
+            // 購読する銘柄が変わった場合は接続したまま購読を更新する
+            if let Some(update) = discovery.as_ref().and_then(|discovery| discovery.take_update()) {
+                products = update;
+                bf.update_products(products.clone());
+                recorder.set_products(&products);
+                if let Some(status_poller) = &status_poller {
+                    status_poller.set_product_codes(product_codes(&products));
+                }
+            }
+
+            // 取得した取引所の状態を書き込む
+            if let Some(status_poller) = &status_poller {
+                for status in status_poller.take() {
+                    recorder.record(&MarketInfo::ExchangeStatus(status));
+                }
+            }
+
+            if TICK_INTERVAL <= last_tick.elapsed() {
+                recorder.tick(chrono::Utc::now());
+                last_tick = Instant::now();
+            }
+
             let now_recv_time = SystemTime::now()
                 .duration_since(SystemTime::UNIX_EPOCH)
                 .expect("back to the future")
@@ -53,51 +789,66 @@ fn main() {
             let bf_on_message = bf.on_message();
             if let Err(error) = bf_on_message {
                 match error {
-                    // 空データを3分以上受信した場合は再接続する
+                    // 空データを閾値以上受信した場合は再接続する
                     TryRecvError::Empty => {
-                        if 180 <= now_recv_time - last_recv_time {
-                            warn!("bf_on_message: Empty data received for more than 3 minutes.");
+                        if silence_threshold.as_secs() <= now_recv_time - last_recv_time {
+                            warn!(
+                                event = event::SILENCE, exchange = exchange.as_str();
+                                "bf_on_message: Empty data received for more than {} seconds.",
+                                silence_threshold.as_secs()
+                            );
                             break;
                         }
                         sleep(Duration::from_millis(1));
                     }
                     // 切断エラーの場合は再接続をする
                     TryRecvError::Disconnected => {
-                        warn!("bf_on_message: Disconnected.");
+                        warn!(event = event::DISCONNECT, exchange = exchange.as_str(); "bf_on_message: Disconnected.");
                         break;
                     }
                 }
             }
 
             if let Ok(message) = bf_on_message {
+                metrics.on_message(&message, Instant::now());
+                if let Some(channel) = message.get_channel() {
+                    silence.on_message(&channel, Instant::now());
+                }
+                metrics.set_queue_depth(bf.queue_depth());
                 last_recv_time = SystemTime::now()
                     .duration_since(SystemTime::UNIX_EPOCH)
                     .expect("back to the future")
                     .as_secs();
                 match message {
-                    // 約定データを受信した場合
-                    MarketInfo::Executions(execution) => {
-                        // CSVに約定データを書き込む
-                        // 書き込み先は[{指定ディレクトリ}/{取引所}/{約定データの日付}/{約定データのチャンネル}.csv]
-                        let dir_all_name =
-                            format!("{}/{}/{}", output_dir, exchange_name, execution.get_date());
-                        append_csv(&dir_all_name, &execution.get_channel(), execution.get_csv().as_bytes());
-                    }
-                    // 遅延データを受信した場合
-                    MarketInfo::LatencyExchange(latency) => {
-                        // CSVに遅延時間を書き込む
-                        // 書き込み先は[{指定ディレクトリ}/{取引所}/{遅延データの日付}/{遅延データのチャンネル}.csv]
-                        let dir_all_name =
-                            format!("{}/{}/{}", output_dir, exchange_name, latency.get_date());
-                        let file_name = format!("latency_{}", latency.get_channel());
-                        append_csv(&dir_all_name, &file_name, latency.get_csv().as_bytes());
+                    // 購読の失敗等、リクエストに対するエラーを受信した場合
+                    MarketInfo::Error(error) => {
+                        error!(exchange = exchange.as_str(), error:% = error; "bf_on_message: {}", error);
                     }
                     // 受信終了の場合
                     MarketInfo::Close => {
-                        info!("Received Close Message.");
+                        info!(event = event::DISCONNECT, exchange = exchange.as_str(); "Received Close Message.");
                         break;
                     }
-                    _ => {}
+                    // 板情報を受信した場合は板に反映し、不整合があればスナップショットを再取得する
+                    MarketInfo::Boards(board) => {
+                        for book_event in books.apply(&board) {
+                            match book_event {
+                                BookEvent::Inconsistent { channel } => bf.request_resync(&channel),
+                                BookEvent::Validated(validation) => recorder.record_book_validation(&validation),
+                                BookEvent::Drift(validation) => warn!(
+                                    event = event::BOOK_DRIFT, exchange = exchange.as_str(), channel = validation.get_channel();
+                                    "book_drift: {} {:.6} ({} of {} levels mismatched)",
+                                    validation.get_channel(),
+                                    validation.drift,
+                                    validation.mismatches.len(),
+                                    validation.levels
+                                ),
+                            }
+                        }
+                        recorder.record(&MarketInfo::Boards(board));
+                    }
+                    // 約定データ等を受信した場合はファイルに書き込む
+                    message => recorder.record(&message),
                 }
             }
         }
@@ -109,22 +860,118 @@ fn main() {
     }
 }
 
-// CSVファイルに追記モードで書き込む
-fn append_csv(dir_all_name: &String, append_file_name: &String, content: &[u8]) {
-    if let Err(error) = create_dir_all(format!("{}", dir_all_name)) {
-        error!("append_csv.create_dir_all: {}", error);
-        return;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Opt {
+        let mut argv = vec!["fetch-market-and-order-data"];
+        argv.extend_from_slice(args);
+        Opt::from_args_or_record(argv).unwrap()
+    }
+
+    #[test]
+    fn records_without_subcommand() {
+        // サブコマンドを導入する前の起動方法は記録として扱う
+        match parse(&[]) {
+            Opt::Record(opt) => assert_eq!(opt.common.output_dir, PathBuf::from(".")),
+            opt => panic!("unexpected command: {:?}", opt),
+        }
+        match parse(&["-o", "data"]) {
+            Opt::Record(opt) => assert_eq!(opt.common.output_dir, PathBuf::from("data")),
+            opt => panic!("unexpected command: {:?}", opt),
+        }
+        match parse(&["--output-dir", "data", "--boards"]) {
+            Opt::Record(opt) => {
+                assert_eq!(opt.common.output_dir, PathBuf::from("data"));
+                assert!(opt.boards);
+            }
+            opt => panic!("unexpected command: {:?}", opt),
+        }
+    }
+
+    #[test]
+    fn parses_record() {
+        match parse(&["record", "-o", "data", "--product", "ETH_JPY", "--redundant", "--clock-offset-ms", "-1.5"]) {
+            Opt::Record(opt) => {
+                assert_eq!(opt.common.output_dir, PathBuf::from("data"));
+                assert_eq!(opt.common.product, ["ETH_JPY"]);
+                assert!(opt.redundant);
+                assert_eq!(opt.clock.clock_offset_ms, Some(-1.5));
+                assert_eq!(opt.end_point, BF_END_POINT);
+            }
+            opt => panic!("unexpected command: {:?}", opt),
+        }
     }
 
-    let file_name = format!("{}/{}.csv", dir_all_name, append_file_name);
-    let file = OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open(file_name)
-        .unwrap();
-    let mut f = BufWriter::new(file);
+    #[test]
+    fn parses_replay() {
+        match parse(&["replay", "-o", "replayed", "--overwrite", "a/raw.log.gz", "b/raw.log.gz"]) {
+            Opt::Replay(opt) => {
+                assert_eq!(opt.common.output_dir, PathBuf::from("replayed"));
+                assert!(opt.overwrite);
+                assert_eq!(opt.files, [PathBuf::from("a/raw.log.gz"), PathBuf::from("b/raw.log.gz")]);
+            }
+            opt => panic!("unexpected command: {:?}", opt),
+        }
+        // 記録済みのフレームは必須
+        assert!(Opt::from_args_or_record(vec!["fetch-market-and-order-data", "replay"]).is_err());
+    }
+
+    #[test]
+    fn parses_convert() {
+        match parse(&["convert", "--kind", "latency", "--output-format", "parquet", "in.csv", "out.parquet"]) {
+            Opt::Convert(opt) => {
+                assert_eq!(opt.kind, Some(DataKind::Latency));
+                assert_eq!(opt.input_format, None);
+                assert_eq!(opt.output_format, Some(Format::Parquet));
+                assert_eq!(opt.input, PathBuf::from("in.csv"));
+                assert_eq!(opt.output, PathBuf::from("out.parquet"));
+            }
+            opt => panic!("unexpected command: {:?}", opt),
+        }
+    }
+
+    #[test]
+    fn parses_validate() {
+        match parse(&["validate", "--from", "20201001", "--to", "2020-10-03", "--repair-dir", "repaired"]) {
+            Opt::Validate(opt) => {
+                assert_eq!(opt.from, NaiveDate::from_ymd_opt(2020, 10, 1).unwrap());
+                assert_eq!(opt.to, NaiveDate::from_ymd_opt(2020, 10, 3));
+                assert_eq!(opt.repair_dir, Some(PathBuf::from("repaired")));
+            }
+            opt => panic!("unexpected command: {:?}", opt),
+        }
+        // 検証する日付は必須
+        assert!(Opt::from_args_or_record(vec!["fetch-market-and-order-data", "validate"]).is_err());
+    }
+
+    #[test]
+    fn parses_stats() {
+        match parse(&["stats", "--date", "20201001", "--channel", "lightning_executions_BTC_JPY", "--json"]) {
+            Opt::Stats(opt) => {
+                assert_eq!(opt.date, NaiveDate::from_ymd_opt(2020, 10, 1).unwrap());
+                assert_eq!(opt.channel, ["lightning_executions_BTC_JPY"]);
+                assert_eq!(opt.gap_secs, 60);
+                assert!(opt.json);
+            }
+            opt => panic!("unexpected command: {:?}", opt),
+        }
+    }
+
+    #[test]
+    fn parses_list_products() {
+        match parse(&["list-products", "--product", "FX_BTC_JPY", "--discover-products"]) {
+            Opt::ListProducts(opt) => {
+                assert_eq!(opt.common.product, ["FX_BTC_JPY"]);
+                assert!(opt.common.discover_products);
+            }
+            opt => panic!("unexpected command: {:?}", opt),
+        }
+    }
 
-    if let Err(error) = f.write(content) {
-        error!("append_csv.f.write(content): {}", error);
+    #[test]
+    fn rejects_unknown_subcommand() {
+        assert!(Opt::from_args_or_record(vec!["fetch-market-and-order-data", "unknown"]).is_err());
     }
 }
@@ -0,0 +1,245 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as FmtWrite;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::{error, info};
+
+use crate::latency::{LatencyPercentiles, LatencyPeriod, LatencySummary};
+use crate::stream_api::{Common, MarketInfo};
+
+// 監視用の計測値
+struct MetricsState {
+    // 計測の開始時刻(受信がない場合の経過時間の起点)
+    started: Instant,
+    // チャンネル毎の受信したメッセージの数
+    messages: BTreeMap<String, u64>,
+    // チャンネル毎の最後にメッセージを受信した時刻
+    last_message: BTreeMap<String, Instant>,
+    // 最後にいずれかのメッセージを受信した時刻
+    last_any_message: Option<Instant>,
+    reconnects: u64,
+    write_errors: u64,
+    // 重複して受信したため書き込まなかった約定の数
+    duplicate_executions: u64,
+    queue_depth: usize,
+    // チャンネル毎の直近1分間の遅延のパーセンタイル
+    latency: BTreeMap<String, LatencyPercentiles>,
+}
+
+// 受信スレッド・メインスレッド・HTTPサーバーで共有する計測値
+#[derive(Clone)]
+pub struct Metrics {
+    state: Arc<Mutex<MetricsState>>,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Metrics::new()
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Metrics {
+            state: Arc::new(Mutex::new(MetricsState {
+                started: Instant::now(),
+                messages: BTreeMap::new(),
+                last_message: BTreeMap::new(),
+                last_any_message: None,
+                reconnects: 0,
+                write_errors: 0,
+                duplicate_executions: 0,
+                queue_depth: 0,
+                latency: BTreeMap::new(),
+            })),
+        }
+    }
+
+    // 受信したマーケット情報を数える(約定データから算出した遅延データ、受信したフレーム・エラー等は対象外)
+    pub fn on_message(&self, message: &MarketInfo, now: Instant) {
+        if let MarketInfo::LatencyExchange(_) = message {
+            return;
+        }
+        if let Some(channel) = message.get_channel() {
+            let mut state = self.state.lock().unwrap();
+            *state.messages.entry(channel.clone()).or_insert(0) += 1;
+            state.last_message.insert(channel, now);
+            state.last_any_message = Some(now);
+        }
+    }
+
+    // 再接続した回数を数える
+    pub fn on_reconnect(&self) {
+        self.state.lock().unwrap().reconnects += 1;
+    }
+
+    // ファイルへの書き込みの失敗を数える
+    pub fn on_write_error(&self) {
+        self.state.lock().unwrap().write_errors += 1;
+    }
+
+    // 重複して受信した約定を数える
+    pub fn on_duplicate_execution(&self) {
+        self.state.lock().unwrap().duplicate_executions += 1;
+    }
+
+    // 受信スレッドから配信済みで未取得のメッセージの数を設定する
+    pub fn set_queue_depth(&self, queue_depth: usize) {
+        self.state.lock().unwrap().queue_depth = queue_depth;
+    }
+
+    // 遅延の集計結果を反映する(1分毎の集計のみ対象)
+    pub fn on_latency_summary(&self, summary: &LatencySummary) {
+        if let LatencyPeriod::Minute = summary.period {
+            self.state
+                .lock()
+                .unwrap()
+                .latency
+                .insert(summary.get_channel(), summary.percentiles);
+        }
+    }
+
+    // 最後にいずれかのメッセージを受信してからの経過時間(未受信の場合は計測の開始から)
+    pub fn last_message_age(&self, now: Instant) -> Duration {
+        let state = self.state.lock().unwrap();
+        now.saturating_duration_since(state.last_any_message.unwrap_or(state.started))
+    }
+
+    // 受信のない時間が閾値以内か
+    pub fn is_healthy(&self, silence_threshold: Duration, now: Instant) -> bool {
+        self.last_message_age(now) <= silence_threshold
+    }
+
+    // Prometheusのテキスト形式で出力する
+    pub fn render(&self, now: Instant) -> String {
+        let state = self.state.lock().unwrap();
+        let mut text = String::new();
+
+        let _ = writeln!(text, "# HELP recorder_messages_total Messages received per channel.");
+        let _ = writeln!(text, "# TYPE recorder_messages_total counter");
+        for (channel, count) in state.messages.iter() {
+            let _ = writeln!(text, "recorder_messages_total{{channel=\"{}\"}} {}", channel, count);
+        }
+
+        let _ = writeln!(text, "# HELP recorder_last_message_age_seconds Seconds since the last message per channel.");
+        let _ = writeln!(text, "# TYPE recorder_last_message_age_seconds gauge");
+        for (channel, last) in state.last_message.iter() {
+            let _ = writeln!(
+                text,
+                "recorder_last_message_age_seconds{{channel=\"{}\"}} {:.3}",
+                channel,
+                now.saturating_duration_since(*last).as_secs_f64()
+            );
+        }
+
+        let _ = writeln!(text, "# HELP recorder_reconnects_total Reconnections to the streaming API.");
+        let _ = writeln!(text, "# TYPE recorder_reconnects_total counter");
+        let _ = writeln!(text, "recorder_reconnects_total {}", state.reconnects);
+
+        let _ = writeln!(text, "# HELP recorder_write_errors_total Failed writes to output files.");
+        let _ = writeln!(text, "# TYPE recorder_write_errors_total counter");
+        let _ = writeln!(text, "recorder_write_errors_total {}", state.write_errors);
+
+        let _ = writeln!(text, "# HELP recorder_duplicate_executions_total Executions received more than once and not recorded.");
+        let _ = writeln!(text, "# TYPE recorder_duplicate_executions_total counter");
+        let _ = writeln!(text, "recorder_duplicate_executions_total {}", state.duplicate_executions);
+
+        let _ = writeln!(text, "# HELP recorder_queue_depth Messages received but not yet recorded.");
+        let _ = writeln!(text, "# TYPE recorder_queue_depth gauge");
+        let _ = writeln!(text, "recorder_queue_depth {}", state.queue_depth);
+
+        let _ = writeln!(text, "# HELP recorder_latency_seconds Latency percentiles over the last minute per channel.");
+        let _ = writeln!(text, "# TYPE recorder_latency_seconds gauge");
+        for (channel, percentiles) in state.latency.iter() {
+            for (quantile, micros) in [
+                ("0.5", percentiles.p50),
+                ("0.9", percentiles.p90),
+                ("0.99", percentiles.p99),
+                ("1", percentiles.max),
+            ] {
+                let _ = writeln!(
+                    text,
+                    "recorder_latency_seconds{{channel=\"{}\",quantile=\"{}\"}} {:.6}",
+                    channel,
+                    quantile,
+                    micros as f64 / 1_000_000.0
+                );
+            }
+        }
+        text
+    }
+}
+
+// 監視用のHTTPサーバーの接続毎の読み込み・書き込みのタイムアウト
+pub const CONNECTION_TIMEOUT: Duration = Duration::from_secs(5);
+
+// 監視用のHTTPサーバーを別スレッドで起動し、待受アドレスを返す
+// /metrics はPrometheusの計測値、/health は受信のない時間が閾値を超えた場合に503を返す
+// リクエストを送らない接続が他の接続を妨げないよう、接続毎にスレッドを起動し、タイムアウトを設定する
+pub fn serve<A: ToSocketAddrs>(addr: A, metrics: Metrics, silence_threshold: Duration) -> io::Result<SocketAddr> {
+    let listener = TcpListener::bind(addr)?;
+    let local_addr = listener.local_addr()?;
+    info!("metrics: Listen on {}", local_addr);
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(error) => {
+                    error!("metrics.serve: {}", error);
+                    continue;
+                }
+            };
+            let metrics = metrics.clone();
+            thread::spawn(move || {
+                let result = stream
+                    .set_read_timeout(Some(CONNECTION_TIMEOUT))
+                    .and_then(|_| stream.set_write_timeout(Some(CONNECTION_TIMEOUT)))
+                    .and_then(|_| respond(stream, &metrics, silence_threshold));
+                if let Err(error) = result {
+                    error!("metrics.serve: {}", error);
+                }
+            });
+        }
+    });
+    Ok(local_addr)
+}
+
+// リクエスト行のパスに応じてレスポンスを返す
+fn respond(mut stream: TcpStream, metrics: &Metrics, silence_threshold: Duration) -> io::Result<()> {
+    let mut request_line = String::new();
+    BufReader::new(&stream).read_line(&mut request_line)?;
+    let path = request_line.split_whitespace().nth(1).unwrap_or("");
+
+    let now = Instant::now();
+    let (status, content_type, body) = match path {
+        "/metrics" => ("200 OK", "text/plain; version=0.0.4", metrics.render(now)),
+        "/health" => {
+            let age = metrics.last_message_age(now);
+            if metrics.is_healthy(silence_threshold, now) {
+                ("200 OK", "text/plain", String::from("ok\n"))
+            } else {
+                (
+                    "503 Service Unavailable",
+                    "text/plain",
+                    format!("no data received for {} seconds\n", age.as_secs()),
+                )
+            }
+        }
+        _ => ("404 Not Found", "text/plain", String::from("not found\n")),
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
@@ -0,0 +1,311 @@
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+use chrono::{DateTime, Utc};
+
+use log::warn;
+
+use crate::logging::event;
+use crate::stream_api::{Board, Common};
+
+// 板の価格(BTreeMapのキーとして用いるため全順序を定義する)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Price(pub f64);
+
+impl Eq for Price {}
+
+impl PartialOrd for Price {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Price {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+// スナップショットと差分から再構築した板
+#[derive(Clone, Debug, Default)]
+pub struct OrderBook {
+    pub bids: BTreeMap<Price, f64>,
+    pub asks: BTreeMap<Price, f64>,
+    // スナップショットを受信済みか
+    initialized: bool,
+    // 不整合を検知し、スナップショットの受信を待っているか
+    awaiting_snapshot: bool,
+}
+
+impl OrderBook {
+    pub fn new() -> Self {
+        OrderBook::default()
+    }
+
+    // 最良買気配
+    pub fn best_bid(&self) -> Option<f64> {
+        self.bids.keys().next_back().map(|price| price.0)
+    }
+
+    // 最良売気配
+    pub fn best_ask(&self) -> Option<f64> {
+        self.asks.keys().next().map(|price| price.0)
+    }
+
+    pub fn is_initialized(&self) -> bool {
+        self.initialized
+    }
+
+    // 板が整合しているか(最良買気配が最良売気配以上の場合は不整合)
+    pub fn is_consistent(&self) -> bool {
+        match (self.best_bid(), self.best_ask()) {
+            (Some(bid), Some(ask)) => bid < ask,
+            _ => true,
+        }
+    }
+
+    // スナップショットで板を置き換える
+    pub fn reset(&mut self, board: &Board) {
+        self.bids = levels(&board.bids);
+        self.asks = levels(&board.asks);
+        self.initialized = true;
+        self.awaiting_snapshot = false;
+    }
+
+    // 差分を板に反映する(数量が0の気配は削除する)
+    pub fn update(&mut self, board: &Board) {
+        for (book, diffs) in [(&mut self.bids, &board.bids), (&mut self.asks, &board.asks)] {
+            for &(price, size) in diffs.iter() {
+                if size == 0.0 {
+                    book.remove(&Price(price));
+                } else {
+                    book.insert(Price(price), size);
+                }
+            }
+        }
+    }
+
+    // スナップショットと板を比較する
+    // スナップショットの最安の買気配から最高の売気配までの範囲の気配を比較対象とする
+    pub fn validate(&self, snapshot: &Board) -> BookValidation {
+        let mut mismatches = Vec::new();
+        let mut diff_size = 0.0;
+        let mut total_size = 0.0;
+        let mut levels = 0;
+        for (side, local, remote) in [
+            (BookSide::Bid, &self.bids, &snapshot.bids),
+            (BookSide::Ask, &self.asks, &snapshot.asks),
+        ] {
+            let remote = self::levels(remote);
+            let (low, high) = match (remote.keys().next(), remote.keys().next_back()) {
+                (Some(low), Some(high)) => (*low, *high),
+                _ => continue,
+            };
+            let mut prices: Vec<Price> = remote.keys().cloned().collect();
+            prices.extend(local.range(low..=high).map(|(price, _)| *price));
+            prices.sort();
+            prices.dedup();
+
+            for price in prices {
+                let local_size = local.get(&price).cloned();
+                let snapshot_size = remote.get(&price).cloned();
+                levels += 1;
+                total_size += snapshot_size.unwrap_or(0.0);
+                if local_size != snapshot_size {
+                    diff_size += (local_size.unwrap_or(0.0) - snapshot_size.unwrap_or(0.0)).abs();
+                    mismatches.push(BookMismatch {
+                        side,
+                        price: price.0,
+                        local_size,
+                        snapshot_size,
+                    });
+                }
+            }
+        }
+
+        BookValidation {
+            time: snapshot.data_time(),
+            levels,
+            mismatches,
+            // スナップショットの数量の合計に対する、数量の差の絶対値の合計の割合
+            drift: if total_size == 0.0 { 0.0 } else { diff_size / total_size },
+            channel: snapshot.get_channel(),
+        }
+    }
+
+    // 板情報を反映し、新たに不整合を検知した場合はtrueを返す
+    // 不整合の検知後はスナップショットを受信するまで再度検知しない
+    pub fn apply(&mut self, board: &Board) -> bool {
+        if board.is_update {
+            self.update(board);
+        } else {
+            self.reset(board);
+        }
+        if !self.initialized || self.awaiting_snapshot || self.is_consistent() {
+            return false;
+        }
+        self.awaiting_snapshot = true;
+        true
+    }
+}
+
+// 板の売買の別
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BookSide {
+    Bid,
+    Ask,
+}
+
+impl fmt::Display for BookSide {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BookSide::Bid => write!(f, "bid"),
+            BookSide::Ask => write!(f, "ask"),
+        }
+    }
+}
+
+// スナップショットと一致しなかった気配
+#[derive(Clone, Debug, PartialEq)]
+pub struct BookMismatch {
+    pub side: BookSide,
+    pub price: f64,
+    // 板の数量(気配がない場合はNone)
+    pub local_size: Option<f64>,
+    // スナップショットの数量(気配がない場合はNone)
+    pub snapshot_size: Option<f64>,
+}
+
+impl BookMismatch {
+    // 受信時刻(ミリ秒) 売買の別 価格 板の数量 スナップショットの数量(気配がない場合は"-")
+    pub fn get_csv(&self, time: DateTime<Utc>) -> String {
+        let or_dash = |size: Option<f64>| match size {
+            Some(size) => size.to_string(),
+            None => String::from("-"),
+        };
+        format!(
+            "{} {} {} {} {}\n",
+            time.timestamp_millis(),
+            self.side,
+            self.price,
+            or_dash(self.local_size),
+            or_dash(self.snapshot_size)
+        )
+    }
+}
+
+// スナップショットと板の比較結果
+#[derive(Clone, Debug)]
+pub struct BookValidation {
+    time: DateTime<Utc>,
+    // 比較した気配の数
+    pub levels: usize,
+    pub mismatches: Vec<BookMismatch>,
+    // 板のずれの大きさ(0は完全に一致)
+    pub drift: f64,
+    channel: String,
+}
+
+impl Common for BookValidation {
+    // 受信時刻(ミリ秒) 比較した気配の数 一致しなかった気配の数 ずれ
+    fn get_csv(&self) -> String {
+        format!(
+            "{} {} {} {:.6}\n",
+            self.time.timestamp_millis(),
+            self.levels,
+            self.mismatches.len(),
+            self.drift
+        )
+    }
+
+    fn data_time(&self) -> DateTime<Utc> {
+        self.time
+    }
+
+    fn channel(&self) -> String {
+        self.channel.clone()
+    }
+}
+
+// 板情報を反映した結果のイベント
+#[derive(Clone, Debug)]
+pub enum BookEvent {
+    // 差分の反映後に板の不整合を検知した(スナップショットの再取得が必要)
+    Inconsistent { channel: String },
+    // スナップショットと板を比較した
+    Validated(BookValidation),
+    // スナップショットとのずれが閾値を超えた
+    Drift(BookValidation),
+}
+
+// スナップショットとのずれの既定の閾値
+pub const DEFAULT_DRIFT_THRESHOLD: f64 = 0.05;
+
+// 気配値の配列を板に変換する
+fn levels(levels: &[(f64, f64)]) -> BTreeMap<Price, f64> {
+    levels
+        .iter()
+        .filter(|(_, size)| *size != 0.0)
+        .map(|&(price, size)| (Price(price), size))
+        .collect()
+}
+
+// チャンネル毎の板
+pub struct OrderBooks {
+    books: HashMap<String, OrderBook>,
+    drift_threshold: f64,
+}
+
+impl Default for OrderBooks {
+    fn default() -> Self {
+        OrderBooks::new()
+    }
+}
+
+impl OrderBooks {
+    pub fn new() -> Self {
+        OrderBooks {
+            books: HashMap::new(),
+            drift_threshold: DEFAULT_DRIFT_THRESHOLD,
+        }
+    }
+
+    // スナップショットとのずれの閾値を設定する
+    pub fn set_drift_threshold(&mut self, drift_threshold: f64) {
+        self.drift_threshold = drift_threshold;
+    }
+
+    pub fn get(&self, channel: &str) -> Option<&OrderBook> {
+        self.books.get(channel)
+    }
+
+    // 板情報を反映し、発生したイベントを返す
+    // スナップショットの場合は反映前に板と比較する
+    pub fn apply(&mut self, board: &Board) -> Vec<BookEvent> {
+        let mut events = Vec::new();
+        let book = self.books.entry(board.get_channel()).or_default();
+
+        if !board.is_update && book.is_initialized() {
+            let validation = book.validate(board);
+            if self.drift_threshold < validation.drift {
+                events.push(BookEvent::Drift(validation.clone()));
+            }
+            events.push(BookEvent::Validated(validation));
+        }
+
+        if book.apply(board) {
+            warn!(
+                event = event::BOOK_INCONSISTENT, channel = board.get_channel();
+                "OrderBooks.apply: Inconsistent book {}. bid {:?} ask {:?}",
+                board.get_channel(),
+                book.best_bid(),
+                book.best_ask()
+            );
+            events.push(BookEvent::Inconsistent {
+                channel: board.get_channel(),
+            });
+        }
+        events
+    }
+}
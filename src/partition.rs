@@ -0,0 +1,125 @@
+use std::fmt;
+
+use chrono::{DateTime, Duration, FixedOffset, NaiveDate, SecondsFormat, TimeZone, Utc};
+
+use serde_json::json;
+
+// 日付毎のディレクトリに書き込むメタデータのファイル名
+pub const METADATA_FILE_NAME: &str = "metadata.json";
+
+// 遅延データのファイル(latency_*)の形式の版(メタデータに書き込む)
+// 1: 受信時刻(ミリ秒) 遅延(ミリ秒)
+// 2: 受信時刻(ナノ秒) 遅延(ミリ秒) 単調時計の受信時刻(ナノ秒) 受信シーケンス番号
+// メタデータに版がない日付のディレクトリは1とする
+pub const LATENCY_FORMAT_VERSION: u32 = 2;
+
+// 日付の区切りの設定のエラー
+#[derive(Clone, Debug, PartialEq)]
+pub struct PartitionError(String);
+
+impl fmt::Display for PartitionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid partition: {}", self.0)
+    }
+}
+
+impl std::error::Error for PartitionError {}
+
+// タイムゾーンを解析する(UTC/JST/Asia/Tokyo/+09:00/-0530)
+pub fn parse_timezone(s: &str) -> Result<FixedOffset, PartitionError> {
+    let invalid = || PartitionError(format!("unknown timezone: {}", s));
+    match s {
+        "UTC" | "Z" => return Ok(FixedOffset::east_opt(0).unwrap()),
+        "JST" | "Asia/Tokyo" => return Ok(FixedOffset::east_opt(9 * 3600).unwrap()),
+        _ => {}
+    }
+
+    let sign = match s.chars().next() {
+        Some('+') => 1,
+        Some('-') => -1,
+        _ => return Err(invalid()),
+    };
+    let digits: String = s[1..].chars().filter(|c| *c != ':').collect();
+    if digits.len() != 4 || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return Err(invalid());
+    }
+    let hours: i32 = digits[..2].parse().map_err(|_| invalid())?;
+    let minutes: i32 = digits[2..].parse().map_err(|_| invalid())?;
+    if 60 <= minutes {
+        return Err(invalid());
+    }
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60)).ok_or_else(invalid)
+}
+
+// データを日付毎のディレクトリに分ける際の日付の区切り
+// タイムゾーンの区切りの時刻から翌日の区切りの時刻までを1日とする
+#[derive(Clone, Debug, PartialEq)]
+pub struct DayPartition {
+    offset: FixedOffset,
+    cutoff_hour: u32,
+}
+
+impl Default for DayPartition {
+    // UTCの0時で区切る
+    fn default() -> Self {
+        DayPartition {
+            offset: FixedOffset::east_opt(0).unwrap(),
+            cutoff_hour: 0,
+        }
+    }
+}
+
+impl DayPartition {
+    pub fn new(offset: FixedOffset, cutoff_hour: u32) -> Result<Self, PartitionError> {
+        if 24 <= cutoff_hour {
+            return Err(PartitionError(format!("cut-off hour must be 0-23: {}", cutoff_hour)));
+        }
+        Ok(DayPartition { offset, cutoff_hour })
+    }
+
+    pub fn get_offset(&self) -> FixedOffset {
+        self.offset
+    }
+
+    pub fn get_cutoff_hour(&self) -> u32 {
+        self.cutoff_hour
+    }
+
+    // 時刻が属する日付
+    pub fn date(&self, time: DateTime<Utc>) -> NaiveDate {
+        (time.with_timezone(&self.offset) - Duration::hours(self.cutoff_hour as i64)).date_naive()
+    }
+
+    // 時刻が属する日付(年月日)
+    pub fn date_string(&self, time: DateTime<Utc>) -> String {
+        self.date(time).format("%Y%m%d").to_string()
+    }
+
+    // 日付の開始時刻
+    pub fn start(&self, date: NaiveDate) -> DateTime<Utc> {
+        let start = date.and_hms_opt(self.cutoff_hour, 0, 0).unwrap();
+        self.offset
+            .from_local_datetime(&start)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    // 日付の終了時刻(翌日の開始時刻)
+    pub fn end(&self, date: NaiveDate) -> DateTime<Utc> {
+        self.start(date + Duration::days(1))
+    }
+
+    // 日付毎のディレクトリに書き込むメタデータ
+    pub fn metadata(&self, exchange_name: &str, date: NaiveDate) -> String {
+        json!({
+            "exchange": exchange_name,
+            "date": date.format("%Y-%m-%d").to_string(),
+            "timezone": self.offset.to_string(),
+            "cutoff_hour": self.cutoff_hour,
+            "start": self.start(date).to_rfc3339_opts(SecondsFormat::Millis, true),
+            "end": self.end(date).to_rfc3339_opts(SecondsFormat::Millis, true),
+            "latency_format": LATENCY_FORMAT_VERSION,
+        })
+        .to_string()
+    }
+}
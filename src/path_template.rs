@@ -0,0 +1,216 @@
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use chrono::NaiveDate;
+
+use crate::product::Product;
+
+// 既定の書き込み先[{取引所}/{データの日付}/{ファイル名}.{拡張子}]
+pub const DEFAULT_PATH_TEMPLATE: &str = "{exchange}/{date}/{name}.{ext}";
+
+// テンプレートの埋め込み項目
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Placeholder {
+    // 取引所
+    Exchange,
+    // 銘柄コード
+    Product,
+    // 基軸通貨と決済通貨
+    Base,
+    Quote,
+    // チャンネル
+    Channel,
+    // データの種類(executions/board/latency_executions/status等)
+    Kind,
+    // ファイル名(lightning_executions_FX_BTC_JPY/latency_lightning_executions_FX_BTC_JPY/status等)
+    Name,
+    // データの日付(年月日)
+    Date,
+    Year,
+    Month,
+    Day,
+    // 拡張子(csv/log.gz/json)
+    Ext,
+}
+
+impl Placeholder {
+    const ALL: [Placeholder; 12] = [
+        Placeholder::Exchange,
+        Placeholder::Product,
+        Placeholder::Base,
+        Placeholder::Quote,
+        Placeholder::Channel,
+        Placeholder::Kind,
+        Placeholder::Name,
+        Placeholder::Date,
+        Placeholder::Year,
+        Placeholder::Month,
+        Placeholder::Day,
+        Placeholder::Ext,
+    ];
+
+    fn key(&self) -> &'static str {
+        match self {
+            Placeholder::Exchange => "exchange",
+            Placeholder::Product => "product",
+            Placeholder::Base => "base",
+            Placeholder::Quote => "quote",
+            Placeholder::Channel => "channel",
+            Placeholder::Kind => "kind",
+            Placeholder::Name => "name",
+            Placeholder::Date => "date",
+            Placeholder::Year => "yyyy",
+            Placeholder::Month => "mm",
+            Placeholder::Day => "dd",
+            Placeholder::Ext => "ext",
+        }
+    }
+
+    fn from_key(key: &str) -> Option<Self> {
+        Placeholder::ALL.iter().find(|placeholder| placeholder.key() == key).copied()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Segment {
+    Literal(String),
+    Placeholder(Placeholder),
+}
+
+// 書き込み先のテンプレートのエラー
+#[derive(Clone, Debug, PartialEq)]
+pub struct PathTemplateError(String);
+
+impl fmt::Display for PathTemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid path template: {}", self.0)
+    }
+}
+
+impl std::error::Error for PathTemplateError {}
+
+// テンプレートに埋め込む値
+pub struct PathFields<'a> {
+    pub exchange: &'a str,
+    pub date: NaiveDate,
+    pub product_code: &'a str,
+    // 銘柄の登録先が知らない銘柄の場合はNone
+    pub product: Option<&'a Product>,
+    pub channel: &'a str,
+    pub kind: &'a str,
+    pub name: &'a str,
+    pub ext: &'a str,
+}
+
+// 書き込み先のパスのテンプレート(出力先のディレクトリからの相対パス)
+// {exchange}/{product}/{kind}/{yyyy}/{mm}/{dd}.{ext} のように埋め込み項目を{}で囲む
+#[derive(Clone, Debug, PartialEq)]
+pub struct PathTemplate {
+    template: String,
+    segments: Vec<Segment>,
+}
+
+impl Default for PathTemplate {
+    fn default() -> Self {
+        DEFAULT_PATH_TEMPLATE.parse().unwrap()
+    }
+}
+
+impl FromStr for PathTemplate {
+    type Err = PathTemplateError;
+
+    // テンプレートを解析し、書き込み先が日付毎・ファイル毎に分かれるかを確認する
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason: &str| PathTemplateError(format!("{} ({})", reason, s));
+        if s.is_empty() {
+            return Err(invalid("empty"));
+        }
+        if s.starts_with('/') {
+            return Err(invalid("must be relative to the output directory"));
+        }
+        if s.split('/').any(|component| component.is_empty() || component == "..") {
+            return Err(invalid("empty or parent directory component"));
+        }
+
+        let mut segments = Vec::new();
+        let mut rest = s;
+        while !rest.is_empty() {
+            match rest.find(['{', '}']) {
+                Some(0) if rest.starts_with('{') => {
+                    let end = rest.find('}').ok_or_else(|| invalid("unclosed '{'"))?;
+                    let key = &rest[1..end];
+                    let placeholder =
+                        Placeholder::from_key(key).ok_or_else(|| invalid(&format!("unknown placeholder {{{}}}", key)))?;
+                    segments.push(Segment::Placeholder(placeholder));
+                    rest = &rest[end + 1..];
+                }
+                Some(0) => return Err(invalid("unmatched '}'")),
+                Some(index) => {
+                    segments.push(Segment::Literal(rest[..index].to_string()));
+                    rest = &rest[index..];
+                }
+                None => {
+                    segments.push(Segment::Literal(rest.to_string()));
+                    rest = "";
+                }
+            }
+        }
+
+        let template = PathTemplate {
+            template: s.to_string(),
+            segments,
+        };
+        let has = |placeholder: Placeholder| template.contains(placeholder);
+        let daily = has(Placeholder::Date) || (has(Placeholder::Year) && has(Placeholder::Month) && has(Placeholder::Day));
+        if !daily {
+            return Err(invalid("requires {date} or {yyyy}, {mm} and {dd}"));
+        }
+        let per_file = has(Placeholder::Name) || (has(Placeholder::Kind) && (has(Placeholder::Product) || has(Placeholder::Channel)));
+        if !per_file {
+            return Err(invalid("requires {name}, or {kind} with {product} or {channel}"));
+        }
+        Ok(template)
+    }
+}
+
+impl fmt::Display for PathTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.template)
+    }
+}
+
+impl PathTemplate {
+    fn contains(&self, placeholder: Placeholder) -> bool {
+        self.segments.contains(&Segment::Placeholder(placeholder))
+    }
+
+    // 埋め込み項目を置き換えたパス
+    pub fn render(&self, fields: &PathFields) -> PathBuf {
+        let mut path = String::new();
+        for segment in self.segments.iter() {
+            match segment {
+                Segment::Literal(literal) => path.push_str(literal),
+                Segment::Placeholder(placeholder) => path.push_str(&match placeholder {
+                    Placeholder::Exchange => fields.exchange.to_string(),
+                    Placeholder::Product => fields.product_code.to_string(),
+                    Placeholder::Base => fields
+                        .product
+                        .map_or_else(|| String::from("unknown"), |product| product.get_base_currency()),
+                    Placeholder::Quote => fields
+                        .product
+                        .map_or_else(|| String::from("unknown"), |product| product.get_quote_currency()),
+                    Placeholder::Channel => fields.channel.to_string(),
+                    Placeholder::Kind => fields.kind.to_string(),
+                    Placeholder::Name => fields.name.to_string(),
+                    Placeholder::Date => fields.date.format("%Y%m%d").to_string(),
+                    Placeholder::Year => fields.date.format("%Y").to_string(),
+                    Placeholder::Month => fields.date.format("%m").to_string(),
+                    Placeholder::Day => fields.date.format("%d").to_string(),
+                    Placeholder::Ext => fields.ext.to_string(),
+                }),
+            }
+        }
+        PathBuf::from(path)
+    }
+}
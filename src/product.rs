@@ -0,0 +1,186 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+// チャンネルの種類
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ChannelKind {
+    // 約定履歴
+    Executions,
+    // 板情報の差分
+    Board,
+    // 板情報のスナップショット
+    BoardSnapshot,
+}
+
+impl ChannelKind {
+    pub const ALL: [ChannelKind; 3] = [ChannelKind::Executions, ChannelKind::Board, ChannelKind::BoardSnapshot];
+
+    // チャンネル名の接頭辞
+    pub fn prefix(&self) -> &'static str {
+        match self {
+            ChannelKind::Executions => "lightning_executions_",
+            ChannelKind::Board => "lightning_board_",
+            ChannelKind::BoardSnapshot => "lightning_board_snapshot_",
+        }
+    }
+
+    // 種類名(出力先のパスに用いる)
+    pub fn name(&self) -> &'static str {
+        match self {
+            ChannelKind::Executions => "executions",
+            ChannelKind::Board => "board",
+            ChannelKind::BoardSnapshot => "board_snapshot",
+        }
+    }
+
+    // チャンネル名を種類と銘柄に分ける
+    pub fn split(channel: &str) -> Option<(ChannelKind, &str)> {
+        // lightning_board_ はスナップショットの接頭辞にも一致するため、スナップショットから確認する
+        [ChannelKind::BoardSnapshot, ChannelKind::Board, ChannelKind::Executions]
+            .iter()
+            .find_map(|kind| {
+                channel
+                    .strip_prefix(kind.prefix())
+                    .map(|product_code| (*kind, product_code))
+            })
+    }
+}
+
+// チャンネル名から銘柄を取得する(公開チャンネル以外はチャンネル名をそのまま返す)
+pub fn product_code(channel: &str) -> &str {
+    match ChannelKind::split(channel) {
+        Some((_, product_code)) => product_code,
+        None => channel,
+    }
+}
+
+// チャンネル名から種類名を取得する(公開チャンネル以外はチャンネル名をそのまま返す)
+pub fn kind_name(channel: &str) -> &str {
+    match ChannelKind::split(channel) {
+        Some((kind, _)) => kind.name(),
+        None => channel,
+    }
+}
+
+// 銘柄
+#[derive(Clone, Debug, PartialEq)]
+pub struct Product {
+    code: String,
+    // 呼値の単位
+    tick_size: f64,
+    // 数量の単位(最小の注文数量)
+    size_unit: f64,
+    // 基軸通貨と決済通貨
+    base_currency: String,
+    quote_currency: String,
+}
+
+impl Product {
+    pub fn new(code: &str, tick_size: f64, size_unit: f64, base_currency: &str, quote_currency: &str) -> Self {
+        Product {
+            code: code.to_string(),
+            tick_size,
+            size_unit,
+            base_currency: base_currency.to_string(),
+            quote_currency: quote_currency.to_string(),
+        }
+    }
+
+    pub fn get_code(&self) -> String {
+        self.code.clone()
+    }
+
+    pub fn get_tick_size(&self) -> f64 {
+        self.tick_size
+    }
+
+    pub fn get_size_unit(&self) -> f64 {
+        self.size_unit
+    }
+
+    pub fn get_base_currency(&self) -> String {
+        self.base_currency.clone()
+    }
+
+    pub fn get_quote_currency(&self) -> String {
+        self.quote_currency.clone()
+    }
+
+    // 通貨ペア(BTC/JPY等)
+    pub fn get_currency_pair(&self) -> String {
+        format!("{}/{}", self.base_currency, self.quote_currency)
+    }
+
+    // 種類に対応したチャンネル名
+    pub fn channel(&self, kind: ChannelKind) -> String {
+        format!("{}{}", kind.prefix(), self.code)
+    }
+}
+
+// 銘柄の登録先が知らない銘柄を指定された場合のエラー
+#[derive(Clone, Debug, PartialEq)]
+pub struct UnknownProduct(pub String);
+
+impl fmt::Display for UnknownProduct {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown product: {}", self.0)
+    }
+}
+
+impl std::error::Error for UnknownProduct {}
+
+// 既定で購読する銘柄
+pub const DEFAULT_PRODUCT_CODES: [&str; 2] = ["FX_BTC_JPY", "BTC_JPY"];
+
+// 取引所で取り扱う銘柄の一覧
+#[derive(Clone, Debug, Default)]
+pub struct ProductRegistry {
+    products: BTreeMap<String, Product>,
+}
+
+impl ProductRegistry {
+    pub fn new() -> Self {
+        ProductRegistry::default()
+    }
+
+    // bitFlyer Lightningの銘柄
+    pub fn bitflyer() -> Self {
+        let mut registry = ProductRegistry::new();
+        for product in [
+            Product::new("BTC_JPY", 1.0, 0.001, "BTC", "JPY"),
+            Product::new("FX_BTC_JPY", 1.0, 0.01, "BTC", "JPY"),
+            Product::new("ETH_JPY", 1.0, 0.01, "ETH", "JPY"),
+            Product::new("ETH_BTC", 0.00001, 0.01, "ETH", "BTC"),
+            Product::new("BCH_BTC", 0.00001, 0.01, "BCH", "BTC"),
+        ] {
+            registry.insert(product);
+        }
+        registry
+    }
+
+    // 銘柄を登録する(同じ銘柄は置き換える)
+    pub fn insert(&mut self, product: Product) {
+        self.products.insert(product.get_code(), product);
+    }
+
+    pub fn get(&self, code: &str) -> Option<&Product> {
+        self.products.get(code)
+    }
+
+    // 登録済みの銘柄
+    pub fn products(&self) -> Vec<Product> {
+        self.products.values().cloned().collect()
+    }
+
+    // 銘柄コードの一覧から銘柄を取得する
+    pub fn resolve<S: AsRef<str>>(&self, codes: &[S]) -> Result<Vec<Product>, UnknownProduct> {
+        codes
+            .iter()
+            .map(|code| {
+                self.get(code.as_ref())
+                    .cloned()
+                    .ok_or_else(|| UnknownProduct(code.as_ref().to_string()))
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,371 @@
+use std::env;
+use std::fmt;
+use std::fs::{self, create_dir_all, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Cursor, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+
+use flate2::bufread::GzDecoder;
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+
+use log::warn;
+
+use crate::clock::ReceiveTime;
+use crate::stream_api::{Common, FrameParser, MarketInfo};
+
+// 受信したフレームを記録するファイル名
+pub const RAW_FILE_NAME: &str = "raw.log.gz";
+
+// gzipメンバーを閉じて次のメンバーを始める間隔(異常終了時に閉じていないメンバーの期間)
+pub const DEFAULT_MEMBER_INTERVAL: Duration = Duration::from_secs(60);
+
+// 受信したフレーム(未解析)
+#[derive(Clone, Debug)]
+pub struct RawFrame {
+    text: String,
+    pub receive_time: ReceiveTime,
+    pub sequence: u64,
+}
+
+impl Common for RawFrame {
+    // 受信時刻(ナノ秒) 単調時計の受信時刻(ナノ秒) 単調時計に基づく受信時刻(ナノ秒) 受信シーケンス番号 フレームのバイト数 フレーム
+    // フレームに改行が含まれていても復元できるよう、バイト数を記録する
+    fn get_csv(&self) -> String {
+        format!(
+            "{} {} {} {} {} {}\n",
+            self.receive_time.wall_nanos(),
+            self.receive_time.monotonic_nanos(),
+            self.receive_time.steady_wall_nanos(),
+            self.sequence,
+            self.text.len(),
+            self.text
+        )
+    }
+
+    fn data_time(&self) -> DateTime<Utc> {
+        self.receive_time.wall
+    }
+
+    fn channel(&self) -> String {
+        String::from("raw")
+    }
+}
+
+impl RawFrame {
+    pub fn new(text: &str, receive_time: ReceiveTime, sequence: u64) -> Self {
+        RawFrame {
+            text: text.to_string(),
+            receive_time,
+            sequence,
+        }
+    }
+
+    pub fn get_text(&self) -> &str {
+        &self.text
+    }
+}
+
+// 受信したフレームを日毎のgzipファイルに追記する
+// 既存のファイルに追記する場合は新しいgzipメンバーとして書き込み、メンバーは一定間隔で閉じる
+// 異常終了で閉じられなかった最後のメンバーは、次に開いた時に読み込める範囲で閉じ直す
+pub struct RawArchive {
+    path: Option<PathBuf>,
+    encoder: Option<GzEncoder<File>>,
+    member_interval: Duration,
+    // 書き込み中のメンバーを始めた時刻
+    member_start: Option<DateTime<Utc>>,
+}
+
+impl Default for RawArchive {
+    fn default() -> Self {
+        RawArchive {
+            path: None,
+            encoder: None,
+            member_interval: DEFAULT_MEMBER_INTERVAL,
+            member_start: None,
+        }
+    }
+}
+
+impl RawArchive {
+    pub fn new() -> Self {
+        RawArchive::default()
+    }
+
+    // gzipメンバーを閉じる間隔を設定する
+    pub fn set_member_interval(&mut self, member_interval: Duration) {
+        self.member_interval = member_interval;
+    }
+
+    // フレームを書き込む(書き込み先が変わった場合は前のファイルを閉じる)
+    pub fn write(&mut self, path: &Path, frame: &RawFrame) -> io::Result<()> {
+        if self.path.as_deref() != Some(path) {
+            self.finish()?;
+            if let Some(dir) = path.parent() {
+                create_dir_all(dir)?;
+            }
+            if repair_archive(path)? {
+                warn!("RawArchive.write: Closed unfinished gzip member in {}", path.display());
+            }
+            self.path = Some(path.to_path_buf());
+        }
+        if self.encoder.is_none() {
+            let file = OpenOptions::new().create(true).append(true).open(path)?;
+            self.encoder = Some(GzEncoder::new(file, Compression::default()));
+            self.member_start = Some(frame.receive_time.wall);
+        }
+        if let Some(encoder) = self.encoder.as_mut() {
+            encoder.write_all(frame.get_csv().as_bytes())?;
+        }
+        Ok(())
+    }
+
+    // 圧縮済みのデータをファイルに書き出す
+    pub fn flush(&mut self) -> io::Result<()> {
+        match self.encoder.as_mut() {
+            Some(encoder) => encoder.flush(),
+            None => Ok(()),
+        }
+    }
+
+    // メンバーを始めてから一定時間が経っていればメンバーを閉じ(次の書き込みで新しいメンバーを始める)、
+    // そうでなければ圧縮済みのデータを書き出す
+    pub fn tick(&mut self, now: DateTime<Utc>) -> io::Result<()> {
+        let interval = chrono::Duration::from_std(self.member_interval).unwrap_or_else(|_| chrono::Duration::zero());
+        match self.member_start {
+            Some(member_start) if member_start + interval <= now => self.finish_member(),
+            _ => self.flush(),
+        }
+    }
+
+    // 書き込み中のメンバーを閉じる
+    fn finish_member(&mut self) -> io::Result<()> {
+        self.member_start = None;
+        match self.encoder.take() {
+            Some(encoder) => encoder.finish().and_then(|file| file.sync_data()),
+            None => Ok(()),
+        }
+    }
+
+    // 書き込み中のファイルを閉じる
+    pub fn finish(&mut self) -> io::Result<()> {
+        self.path = None;
+        self.finish_member()
+    }
+}
+
+// 閉じられていない最後のgzipメンバーを閉じ直し、閉じ直した場合はtrueを返す
+// 最後のメンバーから読み込めた完全なフレームを新しいメンバーとして書き直し、残りは切り捨てる
+pub fn repair_archive(path: &Path) -> io::Result<bool> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(false),
+        Err(error) => return Err(error),
+    };
+    let mut reader = BufReader::new(file);
+    // 完全なメンバーの終端の位置
+    let mut complete = 0;
+    let mut partial = Vec::new();
+    let mut incomplete = false;
+    while !reader.fill_buf()?.is_empty() {
+        if GzDecoder::new(&mut reader).read_to_end(&mut partial).is_ok() {
+            complete = reader.stream_position()?;
+            partial.clear();
+            continue;
+        }
+        // 不正なデータを含む入力から展開したデータは返されないため、
+        // 1バイトずつ入力して展開し直し、不正なデータの直前までを残す
+        incomplete = true;
+        partial.clear();
+        let mut file = reader.into_inner();
+        file.seek(io::SeekFrom::Start(complete))?;
+        let mut decoder = GzDecoder::new(BufReader::with_capacity(1, file));
+        let mut buffer = [0; 8192];
+        while let Ok(len @ 1..) = decoder.read(&mut buffer) {
+            partial.extend_from_slice(&buffer[..len]);
+        }
+        break;
+    }
+    if !incomplete {
+        return Ok(false);
+    }
+
+    let mut frames = RawFrameReader::new(Cursor::new(&partial[..]));
+    let mut salvaged = 0;
+    while let Ok(Some(_)) = frames.next_frame() {
+        salvaged = frames.reader.position() as usize;
+    }
+
+    let mut file = OpenOptions::new().write(true).open(path)?;
+    file.set_len(complete)?;
+    file.seek(io::SeekFrom::End(0))?;
+    if 0 < salvaged {
+        let mut encoder = GzEncoder::new(file, Compression::default());
+        encoder.write_all(&partial[..salvaged])?;
+        file = encoder.finish()?;
+    }
+    file.sync_all()?;
+    Ok(true)
+}
+
+impl Drop for RawArchive {
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
+
+// 記録済みのフレームを読み込む
+pub struct RawFrameReader<R: BufRead> {
+    reader: R,
+}
+
+impl RawFrameReader<BufReader<MultiGzDecoder<File>>> {
+    // gzipファイルを開く
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = File::open(path)?;
+        Ok(RawFrameReader::new(BufReader::new(MultiGzDecoder::new(file))))
+    }
+}
+
+impl<R: BufRead> RawFrameReader<R> {
+    pub fn new(reader: R) -> Self {
+        RawFrameReader { reader }
+    }
+
+    // 次のフレームを読み込む(ファイルの終端の場合はNone)
+    pub fn next_frame(&mut self) -> io::Result<Option<RawFrame>> {
+        let mut fields = Vec::new();
+        for i in 0..5 {
+            let mut field = Vec::new();
+            self.reader.read_until(b' ', &mut field)?;
+            if field.is_empty() && i == 0 {
+                return Ok(None);
+            }
+            if field.pop() != Some(b' ') {
+                return Err(invalid_data("truncated header"));
+            }
+            fields.push(String::from_utf8(field).map_err(|_| invalid_data("invalid header"))?);
+        }
+        let parse = |i: usize| {
+            fields[i]
+                .parse::<i64>()
+                .map_err(|_| invalid_data("invalid header"))
+        };
+        let (wall, monotonic, steady, sequence, len) =
+            (parse(0)?, parse(1)?, parse(2)?, parse(3)?, parse(4)?);
+
+        let mut text = vec![0; len as usize + 1];
+        self.reader.read_exact(&mut text)?;
+        if text.pop() != Some(b'\n') {
+            return Err(invalid_data("missing line terminator"));
+        }
+        let text = String::from_utf8(text).map_err(|_| invalid_data("invalid frame"))?;
+        Ok(Some(RawFrame::new(
+            &text,
+            ReceiveTime::from_nanos(wall, monotonic as u64, steady),
+            sequence as u64,
+        )))
+    }
+}
+
+impl<R: BufRead> Iterator for RawFrameReader<R> {
+    type Item = io::Result<RawFrame>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_frame().transpose()
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+// 記録済みのフレームを受信時と同じ解析器で再解析し、マーケット情報を配信する
+pub fn replay<P, F>(path: P, parser: &mut FrameParser, mut on_message: F) -> io::Result<u64>
+where
+    P: AsRef<Path>,
+    F: FnMut(MarketInfo),
+{
+    let mut count = 0;
+    for frame in RawFrameReader::open(path)? {
+        let frame = frame?;
+        for market_info in parser.parse(frame.get_text(), frame.receive_time, frame.sequence) {
+            on_message(market_info);
+        }
+        count += 1;
+    }
+    Ok(count)
+}
+
+// 再解析の書き込み先を用意できない理由
+#[derive(Debug)]
+pub enum ReplayDirError {
+    // 書き込み先のディレクトリが空でない
+    NotEmpty(PathBuf),
+    // 削除しようとした書き込み先に再解析するファイルや作業ディレクトリが含まれる
+    ContainsInput(PathBuf),
+    Io(io::Error),
+}
+
+impl fmt::Display for ReplayDirError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayDirError::NotEmpty(path) => write!(
+                f,
+                "{} is not empty. Specify an empty output directory or --overwrite.",
+                path.display()
+            ),
+            ReplayDirError::ContainsInput(path) => {
+                write!(f, "output directory contains {} and can't be overwritten", path.display())
+            }
+            ReplayDirError::Io(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for ReplayDirError {}
+
+impl From<io::Error> for ReplayDirError {
+    fn from(error: io::Error) -> Self {
+        ReplayDirError::Io(error)
+    }
+}
+
+// 再解析の書き込み先を用意する
+// 記録済みのファイルに追記したり、記録済みの約定IDと重複するとして除いたりしないよう、空のディレクトリにのみ書き込む
+// overwriteの場合は中身を削除する(再解析するファイルや作業ディレクトリを含む場合は削除しない)
+pub fn prepare_replay_dir(output_dir: &Path, inputs: &[PathBuf], overwrite: bool) -> Result<(), ReplayDirError> {
+    let mut entries = match fs::read_dir(output_dir) {
+        Ok(entries) => entries,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(create_dir_all(output_dir)?),
+        Err(error) => return Err(error.into()),
+    };
+    if entries.next().is_none() {
+        return Ok(());
+    }
+    if !overwrite {
+        return Err(ReplayDirError::NotEmpty(output_dir.to_path_buf()));
+    }
+
+    let output_dir = output_dir.canonicalize()?;
+    let mut protected = vec![env::current_dir()?.canonicalize()?];
+    for input in inputs {
+        protected.push(input.canonicalize()?);
+    }
+    if let Some(path) = protected.into_iter().find(|path| path.starts_with(&output_dir)) {
+        return Err(ReplayDirError::ContainsInput(path));
+    }
+    for entry in fs::read_dir(&output_dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            fs::remove_dir_all(entry.path())?;
+        } else {
+            fs::remove_file(entry.path())?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,441 @@
+use std::collections::HashSet;
+use std::fs::{create_dir_all, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::{DateTime, NaiveDate, Utc};
+
+use log::error;
+
+use crate::dedup::{ExecutionDedup, DEFAULT_DEDUP_WINDOW};
+use crate::exchange_status::HaltTracker;
+use crate::journal::Durability;
+use crate::latency::{LatencyPeriod, LatencyStats, LatencySummary};
+use crate::logging::event;
+use crate::metrics::Metrics;
+use crate::order_book::BookValidation;
+use crate::partition::{DayPartition, METADATA_FILE_NAME};
+use crate::path_template::{PathFields, PathTemplate};
+use crate::product::{kind_name, product_code, Product, ProductRegistry};
+use crate::raw_archive::RawArchive;
+use crate::rotation::{Rotation, Rotator};
+use crate::stream_api::{Common, Execution, MarketInfo};
+
+// 定期的な処理(Recorder::tick)を行う間隔
+pub const TICK_INTERVAL: Duration = Duration::from_secs(1);
+
+// 受信したマーケット情報をファイルに書き込む
+// ストリーミングAPIからの受信と、記録済みのフレームの再解析で共通して用いる
+pub struct Recorder {
+    output_dir: String,
+    exchange_name: String,
+    // 遅延の集計は再接続をまたいで継続する
+    latency_stats: LatencyStats,
+    raw_archive: RawArchive,
+    // 書き込みの失敗や遅延の集計結果を反映する監視用の計測値
+    metrics: Metrics,
+    // 取引が停止していた期間の検知
+    halts: HaltTracker,
+    // 日付の区切り
+    partition: DayPartition,
+    // メタデータを書き込み済みのパス
+    metadata_paths: HashSet<PathBuf>,
+    // 書き込み先のパスのテンプレート
+    path_template: PathTemplate,
+    // 書き込み先のパスに用いる銘柄
+    products: ProductRegistry,
+    // 日付毎のファイルの分割
+    rotator: Rotator,
+    // 追記の耐障害性(未設定の場合はfsyncしない)
+    durability: Option<Durability>,
+    // 約定の重複の検知(Noneの場合は確認しない)
+    dedup: Option<ExecutionDedup>,
+}
+
+// 書き込むファイルの種類とファイル名
+struct DataFile {
+    kind: String,
+    name: String,
+    product_code: String,
+    ext: &'static str,
+}
+
+impl DataFile {
+    // チャンネル毎のCSV(種類とファイル名にはlatency_等の接頭辞を付ける)
+    fn channel(prefix: &str, channel: &str) -> Self {
+        DataFile {
+            kind: format!("{}{}", prefix, kind_name(channel)),
+            name: format!("{}{}", prefix, channel),
+            product_code: product_code(channel).to_string(),
+            ext: "csv",
+        }
+    }
+
+    // チャンネルに依らないファイル(種類とファイル名は同じ)
+    fn fixed(name: &str, product_code: &str, ext: &'static str) -> Self {
+        DataFile {
+            kind: name.to_string(),
+            name: name.to_string(),
+            product_code: product_code.to_string(),
+            ext,
+        }
+    }
+}
+
+impl Recorder {
+    pub fn new(output_dir: &str, exchange_name: &str) -> Self {
+        Recorder {
+            output_dir: output_dir.to_string(),
+            exchange_name: exchange_name.to_string(),
+            latency_stats: LatencyStats::new(),
+            raw_archive: RawArchive::new(),
+            metrics: Metrics::new(),
+            halts: HaltTracker::new(),
+            partition: DayPartition::default(),
+            metadata_paths: HashSet::new(),
+            path_template: PathTemplate::default(),
+            products: ProductRegistry::bitflyer(),
+            rotator: Rotator::new(Rotation::Daily),
+            durability: None,
+            dedup: Some(ExecutionDedup::new(DEFAULT_DEDUP_WINDOW)),
+        }
+    }
+
+    // 日付の区切りを設定する(遅延の1日単位の集計にも用いる)
+    pub fn set_partition(&mut self, partition: DayPartition) {
+        self.latency_stats.set_partition(partition.clone());
+        self.partition = partition;
+    }
+
+    // 監視用の計測値を設定する
+    pub fn set_metrics(&mut self, metrics: Metrics) {
+        self.metrics = metrics;
+    }
+
+    // 書き込み先のパスのテンプレートを設定する
+    pub fn set_path_template(&mut self, path_template: PathTemplate) {
+        self.path_template = path_template;
+    }
+
+    // 日付毎のファイルの分割方法を設定する
+    pub fn set_rotation(&mut self, rotation: Rotation) {
+        self.rotator = Rotator::new(rotation);
+    }
+
+    // 重複の確認に用いる直近の約定IDの件数を設定する(0の場合は確認しない)
+    pub fn set_dedup_window(&mut self, capacity: usize) {
+        self.dedup = if capacity == 0 { None } else { Some(ExecutionDedup::new(capacity)) };
+    }
+
+    // 取引の停止の検知を設定する(停止中の銘柄を再起動後に引き継ぐ場合)
+    pub fn set_halt_tracker(&mut self, halts: HaltTracker) {
+        self.halts = halts;
+    }
+
+    // 追記したファイルのfsyncと先行書き込みログを設定する
+    pub fn set_durability(&mut self, durability: Durability) {
+        self.durability = Some(durability);
+    }
+
+    // 書き込み先のパスに用いる銘柄(基軸通貨と決済通貨)を登録する
+    pub fn set_products(&mut self, products: &[Product]) {
+        for product in products {
+            self.products.insert(product.clone());
+        }
+    }
+
+    // データの書き込み先[{指定ディレクトリ}/{テンプレートに従ったパス}]
+    // 日付は設定した区切りに従い、初めて書き込む日付にはメタデータも書き込む
+    fn path<T: Common>(&mut self, data: &T, file: &DataFile) -> PathBuf {
+        let date = self.partition.date(data.data_time());
+        let metadata_path = self.render(&data.get_channel(), date, &DataFile::fixed("metadata", &file.product_code, "json"));
+        if !self.metadata_paths.contains(&metadata_path) {
+            let metadata = self.partition.metadata(&self.exchange_name, date);
+            match write_metadata(&metadata_path, &metadata) {
+                Ok(()) => {
+                    self.metadata_paths.insert(metadata_path);
+                }
+                Err(error) => {
+                    error!(
+                        event = event::WRITE_FAILED, exchange = self.exchange_name.as_str(), file = METADATA_FILE_NAME, error:% = error;
+                        "Recorder.path.write_metadata: {} {}", metadata_path.display(), error
+                    );
+                    self.metrics.on_write_error();
+                }
+            }
+        }
+        self.render(&data.get_channel(), date, file)
+    }
+
+    // チャンネル毎のCSVの書き込み先(分割する場合は分割前のファイル)
+    // prefixは遅延データの場合はlatency_等、約定データ等の場合は空
+    pub fn channel_path(&self, date: NaiveDate, prefix: &str, channel: &str) -> PathBuf {
+        self.render(channel, date, &DataFile::channel(prefix, channel))
+    }
+
+    // 出力先のディレクトリ
+    pub fn get_output_dir(&self) -> &str {
+        &self.output_dir
+    }
+
+    // 日付の区切り
+    pub fn get_partition(&self) -> &DayPartition {
+        &self.partition
+    }
+
+    fn render(&self, channel: &str, date: NaiveDate, file: &DataFile) -> PathBuf {
+        let fields = PathFields {
+            exchange: &self.exchange_name,
+            date,
+            product_code: &file.product_code,
+            product: self.products.get(&file.product_code),
+            channel,
+            kind: &file.kind,
+            name: &file.name,
+            ext: file.ext,
+        };
+        Path::new(&self.output_dir).join(self.path_template.render(&fields))
+    }
+
+    // マーケット情報をファイルに書き込む
+    pub fn record(&mut self, message: &MarketInfo) {
+        match message {
+            // 約定データを受信した場合
+            MarketInfo::Executions(execution) => {
+                // CSVに約定データを書き込む
+                // 既定の書き込み先は[{指定ディレクトリ}/{取引所}/{約定データの日付}/{約定データのチャンネル}.csv]
+                // 再接続の前後等で重複して受信した約定は書き込まない
+                // 日付毎の約定IDの一覧は[{指定ディレクトリ}/{取引所}/{約定データの日付}/execution_ids_{約定データのチャンネル}.csv]
+                let ids_path = self.path(execution, &DataFile::channel("execution_ids_", &execution.get_channel()));
+                if !self.is_new_execution(execution, &ids_path) {
+                    self.metrics.on_duplicate_execution();
+                    return;
+                }
+                let path = self.path(execution, &DataFile::channel("", &execution.get_channel()));
+                self.append(execution, &path, execution.get_csv().as_bytes());
+                if self.dedup.is_some() {
+                    self.write(&ids_path, format!("{}\n", execution.get_id()).as_bytes());
+                }
+            }
+            // 遅延データを受信した場合
+            MarketInfo::LatencyExchange(latency) => {
+                // CSVに遅延時間を書き込む
+                // 既定の書き込み先は[{指定ディレクトリ}/{取引所}/{遅延データの日付}/latency_{遅延データのチャンネル}.csv]
+                let path = self.path(latency, &DataFile::channel("latency_", &latency.get_channel()));
+                self.append(latency, &path, latency.get_csv().as_bytes());
+
+                let summaries = self.latency_stats.add(latency);
+                self.write_latency_summaries(&summaries);
+            }
+            // 注文イベントを受信した場合
+            MarketInfo::OrderEvents(event) => {
+                // CSVに注文イベントを書き込む
+                // 既定の書き込み先は[{指定ディレクトリ}/{取引所}/{イベントの日付}/{注文イベントのチャンネル}.csv]
+                let path = self.path(event, &DataFile::channel("", &event.get_channel()));
+                self.append(event, &path, event.get_csv().as_bytes());
+            }
+            // 受信したフレームの場合
+            MarketInfo::RawFrames(frame) => {
+                // 圧縮して書き込む
+                // 既定の書き込み先は[{指定ディレクトリ}/{取引所}/{受信日}/raw.log.gz]
+                let path = self.path(frame, &DataFile::fixed("raw", "raw", "log.gz"));
+                if let Err(error) = self.raw_archive.write(&path, frame) {
+                    error!(
+                        event = event::WRITE_FAILED, exchange = self.exchange_name.as_str(), channel = "raw", error:% = error;
+                        "Recorder.record.raw_archive.write: {}", error
+                    );
+                    self.metrics.on_write_error();
+                }
+            }
+            // 取引所の状態を取得した場合
+            MarketInfo::ExchangeStatus(status) => {
+                // 既定の書き込み先は[{指定ディレクトリ}/{取引所}/{取得日}/status.csv]
+                let path = self.path(status, &DataFile::fixed("status", &status.product_code, "csv"));
+                self.append(status, &path, status.get_csv().as_bytes());
+
+                // 取引が再開した場合は停止していた期間を書き込む
+                // 既定の書き込み先は[{指定ディレクトリ}/{取引所}/{再開日}/halt.csv]
+                if let Some(period) = self.halts.update(status) {
+                    let path = self.path(&period, &DataFile::fixed("halt", &period.product_code, "csv"));
+                    self.append(&period, &path, period.get_csv().as_bytes());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // 約定が初めてのものか(重複を確認しない場合、確認に失敗した場合は初めてのものとして扱う)
+    fn is_new_execution(&mut self, execution: &Execution, ids_path: &Path) -> bool {
+        let dedup = match &mut self.dedup {
+            Some(dedup) => dedup,
+            None => return true,
+        };
+        match dedup.insert(execution.get_id(), ids_path) {
+            Ok(inserted) => inserted,
+            Err(error) => {
+                error!(
+                    event = event::WRITE_FAILED, exchange = self.exchange_name.as_str(), channel = execution.get_channel(), error:% = error;
+                    "Recorder.is_new_execution: {} {}", ids_path.display(), error
+                );
+                self.metrics.on_write_error();
+                true
+            }
+        }
+    }
+
+    // スナップショットと板の比較結果を書き込む
+    // 既定の比較結果の書き込み先は[{指定ディレクトリ}/{取引所}/{受信日}/book_validation_{チャンネル}.csv]
+    // 一致しなかった気配は[{指定ディレクトリ}/{取引所}/{受信日}/book_mismatch_{チャンネル}.csv]
+    pub fn record_book_validation(&mut self, validation: &BookValidation) {
+        let path = self.path(validation, &DataFile::channel("book_validation_", &validation.get_channel()));
+        self.append(validation, &path, validation.get_csv().as_bytes());
+
+        if !validation.mismatches.is_empty() {
+            let path = self.path(validation, &DataFile::channel("book_mismatch_", &validation.get_channel()));
+            let content: String = validation
+                .mismatches
+                .iter()
+                .map(|mismatch| mismatch.get_csv(validation.data_time()))
+                .collect();
+            self.append(validation, &path, content.as_bytes());
+        }
+    }
+
+    // 定期的な処理(集計期間が終わった遅延の集計結果の書き込み等)を行う
+    pub fn tick(&mut self, now: DateTime<Utc>) {
+        let summaries = self.latency_stats.flush(now);
+        self.write_latency_summaries(&summaries);
+
+        if let Err(error) = self.raw_archive.tick(now) {
+            error!(
+                event = event::WRITE_FAILED, exchange = self.exchange_name.as_str(), channel = "raw", error:% = error;
+                "Recorder.tick.raw_archive.tick: {}", error
+            );
+            self.metrics.on_write_error();
+        }
+
+        if let Some(durability) = &mut self.durability {
+            if let Err(error) = durability.tick(now) {
+                error!(
+                    event = event::WRITE_FAILED, exchange = self.exchange_name.as_str(), file = "fsync", error:% = error;
+                    "Recorder.tick.durability.tick: {}", error
+                );
+                self.metrics.on_write_error();
+            }
+        }
+
+        if let Err(error) = self.rotator.tick(now) {
+            error!(
+                event = event::WRITE_FAILED, exchange = self.exchange_name.as_str(), file = "index", error:% = error;
+                "Recorder.tick.rotator.tick: {}", error
+            );
+            self.metrics.on_write_error();
+        }
+    }
+
+    // 書き込み中のファイルを閉じる
+    pub fn finish(&mut self) {
+        if let Err(error) = self.raw_archive.finish() {
+            error!(
+                event = event::WRITE_FAILED, exchange = self.exchange_name.as_str(), channel = "raw", error:% = error;
+                "Recorder.finish.raw_archive.finish: {}", error
+            );
+        }
+        if let Err(error) = self.rotator.flush() {
+            error!(
+                event = event::WRITE_FAILED, exchange = self.exchange_name.as_str(), file = "index", error:% = error;
+                "Recorder.finish.rotator.flush: {}", error
+            );
+        }
+        if let Some(durability) = &mut self.durability {
+            if let Err(error) = durability.sync() {
+                error!(
+                    event = event::WRITE_FAILED, exchange = self.exchange_name.as_str(), file = "fsync", error:% = error;
+                    "Recorder.finish.durability.sync: {}", error
+                );
+            }
+        }
+    }
+
+    // 遅延の集計結果を書き込む
+    // 既定の1分毎の集計の書き込み先は[{指定ディレクトリ}/{取引所}/{集計期間の日付}/latency_stats_{チャンネル}.csv]
+    // 1日毎の集計は[{指定ディレクトリ}/{取引所}/{集計期間の日付}/latency_summary.csv]
+    fn write_latency_summaries(&mut self, summaries: &[LatencySummary]) {
+        for summary in summaries {
+            self.metrics.on_latency_summary(summary);
+            let channel = summary.get_channel();
+            let file = match summary.period {
+                LatencyPeriod::Minute => DataFile::channel("latency_stats_", &channel),
+                LatencyPeriod::Day => DataFile::fixed("latency_summary", product_code(&channel), "csv"),
+            };
+            let path = self.path(summary, &file);
+            self.append(summary, &path, summary.get_csv().as_bytes());
+        }
+    }
+
+    // CSVファイルに追記し、失敗した場合は計測値に反映する
+    // 分割する場合は日付毎のファイルを分割したファイルに追記する
+    fn append<T: Common>(&mut self, data: &T, path: &Path, content: &[u8]) {
+        let time = data.data_time();
+        let day_start = self.partition.start(self.partition.date(time));
+        let path = self.rotator.path(path, time, day_start, content.len());
+        self.write(&path, content);
+    }
+
+    // 分割せずにファイルに追記し、失敗した場合は計測値に反映する
+    fn write(&mut self, path: &Path, content: &[u8]) {
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+        if let Some(durability) = &mut self.durability {
+            if let Err(error) = durability.before_append(path, content) {
+                error!(
+                    event = event::WRITE_FAILED, exchange = self.exchange_name.as_str(), file = file_name.as_str(), error:% = error;
+                    "Recorder.append.before_append: {} {}", path.display(), error
+                );
+                self.metrics.on_write_error();
+            }
+        }
+        if let Err(error) = append_csv(path, content) {
+            error!(
+                event = event::WRITE_FAILED, exchange = self.exchange_name.as_str(), file = file_name.as_str(), error:% = error;
+                "Recorder.append: {} {}", path.display(), error
+            );
+            self.metrics.on_write_error();
+        }
+        if let Some(durability) = &mut self.durability {
+            if let Err(error) = durability.after_append() {
+                error!(
+                    event = event::WRITE_FAILED, exchange = self.exchange_name.as_str(), file = file_name.as_str(), error:% = error;
+                    "Recorder.append.after_append: {} {}", path.display(), error
+                );
+                self.metrics.on_write_error();
+            }
+        }
+    }
+}
+
+// CSVファイルに追記モードで書き込む
+pub fn append_csv(path: &Path, content: &[u8]) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        create_dir_all(dir)?;
+    }
+
+    // 1回の書き込みで追記し、行の途中で途切れにくくする
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    file.write_all(content)
+}
+
+// 日付毎のメタデータを書き込む(既に存在する場合は書き込まない)
+fn write_metadata(path: &Path, metadata: &str) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        create_dir_all(dir)?;
+    }
+    if path.exists() {
+        return Ok(());
+    }
+    let mut file = OpenOptions::new().write(true).create_new(true).open(path)?;
+    writeln!(file, "{}", metadata)
+}
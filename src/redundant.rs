@@ -0,0 +1,276 @@
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::sync::mpsc::TryRecvError;
+use std::time::{Duration, Instant};
+
+use log::{error, info, warn};
+
+use crate::dedup::DEFAULT_DEDUP_WINDOW;
+use crate::logging::event;
+use crate::product::Product;
+use crate::stream_api::{BfWebsocket, ConnectError, Execution, MarketInfo, StreamError};
+
+// 切断された接続を再接続するまでの時間
+const RECONNECT_DELAY: Duration = Duration::from_secs(10);
+
+// 複数の接続から受信したメッセージの統合
+// 約定は約定IDで重複を除き、一定時間待ってから受信時刻の最も早いものを配信する
+// 約定以外(板情報・遅延等)は重複して反映しないよう、有効な接続のうち最も優先度の高い接続のものを配信する
+pub struct StreamMerger {
+    hold: Duration,
+    // 配信を待っている約定(約定ID毎の受信時刻の最も早いものと配信する時刻)
+    pending: BTreeMap<u64, (Execution, Instant)>,
+    // 配信済みの直近の約定ID
+    emitted: BTreeSet<u64>,
+    capacity: usize,
+}
+
+impl StreamMerger {
+    pub fn new(hold: Duration) -> Self {
+        StreamMerger {
+            hold,
+            pending: BTreeMap::new(),
+            emitted: BTreeSet::new(),
+            capacity: DEFAULT_DEDUP_WINDOW,
+        }
+    }
+
+    // 接続から受信したメッセージを追加し、すぐに配信するメッセージを返す
+    // activeは約定以外のメッセージを配信する接続か
+    pub fn push(&mut self, message: MarketInfo, active: bool, now: Instant) -> Option<MarketInfo> {
+        match message {
+            MarketInfo::Executions(execution) => {
+                let id = execution.get_id();
+                if self.emitted.contains(&id) || self.emitted.first().is_some_and(|first| id < *first) {
+                    return None;
+                }
+                match self.pending.get_mut(&id) {
+                    Some((pending, _)) => {
+                        let receive_nanos = |execution: &Execution| execution.get_receive_time().monotonic_nanos();
+                        if receive_nanos(&execution) < receive_nanos(pending) {
+                            *pending = execution;
+                        }
+                    }
+                    None => {
+                        self.pending.insert(id, (execution, now + self.hold));
+                    }
+                }
+                None
+            }
+            message if active => Some(message),
+            _ => None,
+        }
+    }
+
+    // 待つ時間が過ぎた約定を約定IDの順に返す
+    pub fn pop_due(&mut self, now: Instant) -> Vec<MarketInfo> {
+        self.pop(|deadline| deadline <= now)
+    }
+
+    // 配信を待っている全ての約定を約定IDの順に返す(全ての接続が切断された場合等)
+    pub fn drain(&mut self) -> Vec<MarketInfo> {
+        self.pop(|_| true)
+    }
+
+    fn pop<F: Fn(Instant) -> bool>(&mut self, due: F) -> Vec<MarketInfo> {
+        let due: Vec<u64> = self
+            .pending
+            .iter()
+            .filter(|(_, (_, deadline))| due(*deadline))
+            .map(|(id, _)| *id)
+            .collect();
+        let mut messages = Vec::new();
+        for id in due {
+            if let Some((execution, _)) = self.pending.remove(&id) {
+                self.emitted.insert(id);
+                messages.push(MarketInfo::Executions(execution));
+            }
+        }
+        while self.capacity < self.emitted.len() {
+            self.emitted.pop_first();
+        }
+        messages
+    }
+}
+
+// 接続の失敗のうち、呼び出し元に配信するもの(認証の失敗は再接続しても解消しない場合があるため通知する)
+fn connect_error(error: ConnectError) -> Option<MarketInfo> {
+    match error {
+        ConnectError::Auth(error) => Some(MarketInfo::Error(StreamError::Auth {
+            message: error.to_string(),
+        })),
+        ConnectError::Socket(_) => None,
+    }
+}
+
+struct Connection {
+    bf: BfWebsocket,
+    // 切断された場合は再接続する時刻
+    reconnect_at: Option<Instant>,
+}
+
+// 同じチャンネルを購読する複数の接続(1つの場合は受信したメッセージをそのまま配信する)
+// 一方の接続が切断されている間も他方の接続で受信を続け、切断された接続は一定時間後に再接続する
+pub struct RedundantWebsocket<'a> {
+    // 銘柄を指定して接続前の設定をした接続を作成する
+    connect: Box<dyn Fn(Vec<Product>) -> BfWebsocket + 'a>,
+    products: Vec<Product>,
+    connections: Vec<Connection>,
+    merger: StreamMerger,
+    ready: VecDeque<MarketInfo>,
+    // 切断された接続を再接続するまでの時間
+    reconnect_delay: Duration,
+}
+
+impl<'a> RedundantWebsocket<'a> {
+    // connections個の接続を開始する
+    pub fn start<F>(connect: F, products: Vec<Product>, connections: usize, hold: Duration) -> Self
+    where
+        F: Fn(Vec<Product>) -> BfWebsocket + 'a,
+    {
+        let connect: Box<dyn Fn(Vec<Product>) -> BfWebsocket + 'a> = Box::new(connect);
+        let now = Instant::now();
+        let mut ready = VecDeque::new();
+        let connections = (0..connections.max(1))
+            .map(|index| {
+                let bf = connect(products.clone());
+                // 接続に失敗した場合は最初の受信時に再接続する
+                let reconnect_at = match bf.on_connect() {
+                    Ok(()) => None,
+                    Err(error) => {
+                        error!(
+                            event = event::DISCONNECT, connection = index, error:% = error;
+                            "RedundantWebsocket: Can't connect connection {}. {}", index, error
+                        );
+                        ready.extend(connect_error(error));
+                        Some(now)
+                    }
+                };
+                Connection { bf, reconnect_at }
+            })
+            .collect();
+        RedundantWebsocket {
+            connect,
+            products,
+            connections,
+            merger: StreamMerger::new(hold),
+            ready,
+            reconnect_delay: RECONNECT_DELAY,
+        }
+    }
+
+    pub fn set_reconnect_delay(&mut self, reconnect_delay: Duration) {
+        self.reconnect_delay = reconnect_delay;
+    }
+
+    // 受信したメッセージを取得する(全ての接続が切断された場合はDisconnected)
+    pub fn on_message(&mut self) -> Result<MarketInfo, TryRecvError> {
+        if self.connections.len() == 1 {
+            // 接続に失敗した場合は、認証の失敗等を配信してから切断として扱い、呼び出し元で再接続する
+            if self.connections[0].reconnect_at.is_some() {
+                return self.ready.pop_front().ok_or(TryRecvError::Disconnected);
+            }
+            return self.connections[0].bf.on_message();
+        }
+        if let Some(message) = self.ready.pop_front() {
+            return Ok(message);
+        }
+
+        let now = Instant::now();
+        self.reconnect_due(now);
+        let active = self.connections.iter().position(|connection| connection.reconnect_at.is_none());
+        for index in 0..self.connections.len() {
+            if self.connections[index].reconnect_at.is_some() {
+                continue;
+            }
+            while let Ok(message) = self.connections[index].bf.on_message() {
+                if let MarketInfo::Close = message {
+                    warn!(
+                        event = event::DISCONNECT, connection = index;
+                        "RedundantWebsocket.on_message: Connection {} closed. Reconnect in {} seconds.",
+                        index,
+                        self.reconnect_delay.as_secs()
+                    );
+                    self.connections[index].bf.close_thread();
+                    self.connections[index].reconnect_at = Some(now + self.reconnect_delay);
+                    break;
+                }
+                if let Some(message) = self.merger.push(message, Some(index) == active, now) {
+                    self.ready.push_back(message);
+                }
+            }
+        }
+        let disconnected = self.connections.iter().all(|connection| connection.reconnect_at.is_some());
+        if disconnected {
+            self.ready.extend(self.merger.drain());
+        } else {
+            self.ready.extend(self.merger.pop_due(now));
+        }
+
+        match self.ready.pop_front() {
+            Some(message) => Ok(message),
+            None if disconnected => Err(TryRecvError::Disconnected),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+
+    // 再接続する時刻が過ぎた接続を再接続する
+    fn reconnect_due(&mut self, now: Instant) {
+        for (index, connection) in self.connections.iter_mut().enumerate() {
+            if connection.reconnect_at.is_some_and(|reconnect_at| reconnect_at <= now) {
+                connection.bf = (self.connect)(self.products.clone());
+                match connection.bf.on_connect() {
+                    Ok(()) => {
+                        connection.reconnect_at = None;
+                        info!(event = event::CONNECT, connection = index; "RedundantWebsocket: Reconnect connection {}.", index);
+                    }
+                    // 再接続に失敗した場合は一定時間後に再度接続する
+                    Err(error) => {
+                        connection.reconnect_at = Some(now + self.reconnect_delay);
+                        error!(
+                            event = event::DISCONNECT, connection = index, error:% = error;
+                            "RedundantWebsocket: Can't reconnect connection {}. Retry in {} seconds. {}",
+                            index,
+                            self.reconnect_delay.as_secs(),
+                            error
+                        );
+                        self.ready.extend(connect_error(error));
+                    }
+                }
+            }
+        }
+    }
+
+    // 接続中に購読する銘柄を変更する
+    pub fn update_products(&mut self, products: Vec<Product>) {
+        self.products = products.clone();
+        for connection in self.connections.iter_mut() {
+            connection.bf.update_products(products.clone());
+        }
+    }
+
+    // 板の再同期を要求する
+    pub fn request_resync(&self, board_channel: &str) {
+        for connection in self.connections.iter() {
+            connection.bf.request_resync(board_channel);
+        }
+    }
+
+    // 受信が途絶えたチャンネルの再購読を要求する
+    pub fn request_resubscribe(&self, channel: &str) {
+        for connection in self.connections.iter() {
+            connection.bf.request_resubscribe(channel);
+        }
+    }
+
+    // 配信済みで未取得のメッセージの数
+    pub fn queue_depth(&self) -> usize {
+        self.connections.iter().map(|connection| connection.bf.queue_depth()).sum::<usize>() + self.ready.len()
+    }
+
+    // 全ての接続のメッセージ受信用のスレッドを停止する
+    pub fn close_thread(&self) {
+        for connection in self.connections.iter() {
+            connection.bf.close_thread();
+        }
+    }
+}
@@ -0,0 +1,231 @@
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use native_tls::TlsConnector;
+
+use url::Url;
+
+use serde_json::Value;
+
+// HTTP APIのエンドポイント
+pub const BF_REST_END_POINT: &str = "https://api.bitflyer.com";
+
+// HTTP APIの接続・読み書きのタイムアウト
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+// HTTP APIのエラー
+#[derive(Debug)]
+pub enum RestError {
+    Url(String),
+    Io(io::Error),
+    Tls(String),
+    // 200以外のステータスコード
+    Status { code: u16, body: String },
+    // レスポンスの形式が不正
+    Response(String),
+}
+
+impl fmt::Display for RestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RestError::Url(message) => write!(f, "invalid url: {}", message),
+            RestError::Io(error) => write!(f, "{}", error),
+            RestError::Tls(message) => write!(f, "tls: {}", message),
+            RestError::Status { code, body } => write!(f, "status {}: {}", code, body),
+            RestError::Response(message) => write!(f, "invalid response: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for RestError {}
+
+impl From<io::Error> for RestError {
+    fn from(error: io::Error) -> Self {
+        RestError::Io(error)
+    }
+}
+
+// GETリクエストを送信し、レスポンスのJSONを取得する
+pub fn get_json(url: &str) -> Result<Value, RestError> {
+    let body = get(url)?;
+    serde_json::from_str(&body).map_err(|error| RestError::Response(format!("{} {}", error, body)))
+}
+
+// GETリクエストを送信し、レスポンスの本文を取得する(http/https)
+pub fn get(url: &str) -> Result<String, RestError> {
+    let url = Url::parse(url).map_err(|error| RestError::Url(format!("{} {}", url, error)))?;
+    let host = url
+        .host_str()
+        .ok_or_else(|| RestError::Url(format!("no host: {}", url)))?
+        .to_string();
+    let port = url
+        .port_or_known_default()
+        .ok_or_else(|| RestError::Url(format!("no port: {}", url)))?;
+    let path = match url.query() {
+        Some(query) => format!("{}?{}", url.path(), query),
+        None => url.path().to_string(),
+    };
+
+    let stream = connect(&host, port)?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+
+    let request = format!(
+        "GET {} HTTP/1.1\r\nHost: {}\r\nAccept: application/json\r\nConnection: close\r\n\r\n",
+        path, host
+    );
+    let response = match url.scheme() {
+        "http" => send(stream, &request)?,
+        "https" => {
+            let connector = TlsConnector::new().map_err(|error| RestError::Tls(error.to_string()))?;
+            let stream = connector
+                .connect(&host, stream)
+                .map_err(|error| RestError::Tls(error.to_string()))?;
+            send(stream, &request)?
+        }
+        scheme => return Err(RestError::Url(format!("unsupported scheme: {}", scheme))),
+    };
+    parse_response(&response)
+}
+
+// 名前解決したアドレスに順にタイムアウトを指定して接続する
+fn connect(host: &str, port: u16) -> io::Result<TcpStream> {
+    let mut last_error = None;
+    for addr in (host, port).to_socket_addrs()? {
+        match TcpStream::connect_timeout(&addr, TIMEOUT) {
+            Ok(stream) => return Ok(stream),
+            Err(error) => last_error = Some(error),
+        }
+    }
+    Err(last_error.unwrap_or_else(|| {
+        io::Error::new(io::ErrorKind::NotFound, format!("no address: {}", host))
+    }))
+}
+
+// リクエストを送信し、レスポンスを読み込む
+// Content-Lengthがある場合はその長さの本文まで、ない場合(チャンク形式等)は接続が閉じられるまで読み込む
+fn send<S: Read + Write>(mut stream: S, request: &str) -> Result<Vec<u8>, RestError> {
+    stream.write_all(request.as_bytes())?;
+    stream.flush()?;
+
+    let mut response = Vec::new();
+    let mut buf = [0; 8192];
+    let header_end = loop {
+        if let Some(header_end) = find(&response, b"\r\n\r\n") {
+            break header_end;
+        }
+        let len = stream.read(&mut buf)?;
+        if len == 0 {
+            return Err(RestError::Response(String::from("no header")));
+        }
+        response.extend_from_slice(&buf[..len]);
+    };
+
+    let head = String::from_utf8_lossy(&response[..header_end]).to_string();
+    match content_length(&head)? {
+        Some(length) => {
+            let total = header_end + 4 + length;
+            while response.len() < total {
+                let len = stream.read(&mut buf)?;
+                if len == 0 {
+                    return Err(truncated_body(length, response.len() - header_end - 4));
+                }
+                response.extend_from_slice(&buf[..len]);
+            }
+        }
+        None => {
+            stream.read_to_end(&mut response)?;
+        }
+    }
+    Ok(response)
+}
+
+// ヘッダーの値を取得する(名前の大文字・小文字は区別しない)
+fn header<'a>(head: &'a str, name: &str) -> Option<&'a str> {
+    head.split("\r\n").skip(1).find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        if key.trim().eq_ignore_ascii_case(name) {
+            Some(value.trim())
+        } else {
+            None
+        }
+    })
+}
+
+// チャンク形式の本文か
+fn is_chunked(head: &str) -> bool {
+    header(head, "transfer-encoding").is_some_and(|value| value.to_ascii_lowercase().contains("chunked"))
+}
+
+// 本文の長さ(チャンク形式の場合はContent-Lengthを用いない)
+fn content_length(head: &str) -> Result<Option<usize>, RestError> {
+    if is_chunked(head) {
+        return Ok(None);
+    }
+    match header(head, "content-length") {
+        Some(value) => value
+            .parse()
+            .map(Some)
+            .map_err(|_| RestError::Response(format!("invalid content-length: {}", value))),
+        None => Ok(None),
+    }
+}
+
+fn truncated_body(expected: usize, received: usize) -> RestError {
+    RestError::Response(format!("truncated body: {} of {} bytes", received, expected))
+}
+
+// HTTPのレスポンスから本文を取り出す
+fn parse_response(response: &[u8]) -> Result<String, RestError> {
+    let header_end = find(response, b"\r\n\r\n").ok_or_else(|| RestError::Response(String::from("no header")))?;
+    let head = String::from_utf8_lossy(&response[..header_end]);
+    let body = &response[header_end + 4..];
+
+    let status_line = head.split("\r\n").next().unwrap_or("");
+    let code: u16 = status_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse().ok())
+        .ok_or_else(|| RestError::Response(format!("invalid status line: {}", status_line)))?;
+
+    let body = if is_chunked(&head) {
+        decode_chunked(body)?
+    } else {
+        match content_length(&head)? {
+            Some(length) if body.len() < length => return Err(truncated_body(length, body.len())),
+            Some(length) => body[..length].to_vec(),
+            None => body.to_vec(),
+        }
+    };
+    let body = String::from_utf8(body).map_err(|error| RestError::Response(error.to_string()))?;
+    if code != 200 {
+        return Err(RestError::Status { code, body });
+    }
+    Ok(body)
+}
+
+// チャンク形式の本文を復元する
+fn decode_chunked(mut body: &[u8]) -> Result<Vec<u8>, RestError> {
+    let truncated = || RestError::Response(String::from("truncated chunk"));
+    let mut decoded = Vec::new();
+    loop {
+        let line_end = find(body, b"\r\n").ok_or_else(truncated)?;
+        let size_line = String::from_utf8_lossy(&body[..line_end]);
+        let size_field = size_line.split(';').next().unwrap_or("").trim();
+        let size = usize::from_str_radix(size_field, 16)
+            .map_err(|_| RestError::Response(format!("invalid chunk size: {}", size_field)))?;
+        if size == 0 {
+            return Ok(decoded);
+        }
+        let start = line_end + 2;
+        decoded.extend_from_slice(body.get(start..start + size).ok_or_else(truncated)?);
+        body = body.get(start + size + 2..).unwrap_or(&[]);
+    }
+}
+
+// バイト列の位置を検索する
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
@@ -0,0 +1,299 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use chrono::{DateTime, TimeZone, Utc};
+
+use log::warn;
+
+// 分割したファイルの一覧の書き込み間隔
+pub const INDEX_FLUSH_INTERVAL: Duration = Duration::from_secs(60);
+
+// 日付毎のファイルの分割方法
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Rotation {
+    // 分割しない
+    #[default]
+    Daily,
+    // 1時間毎(日付の開始時刻からの経過時間)に分割する
+    Hourly,
+    // バイト数が閾値を超える前に分割する
+    Size(u64),
+}
+
+impl FromStr for Rotation {
+    type Err = String;
+
+    // daily/hourly/バイト数(100MB等、K/M/Gの接頭辞を付けられる)
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "daily" => return Ok(Rotation::Daily),
+            "hourly" => return Ok(Rotation::Hourly),
+            _ => {}
+        }
+        let upper = s.to_ascii_uppercase();
+        let digits = upper.trim_end_matches('B');
+        let (digits, unit) = match digits.chars().last() {
+            Some('K') => (&digits[..digits.len() - 1], 1 << 10),
+            Some('M') => (&digits[..digits.len() - 1], 1 << 20),
+            Some('G') => (&digits[..digits.len() - 1], 1 << 30),
+            _ => (digits, 1),
+        };
+        let bytes: u64 = digits
+            .trim()
+            .parse()
+            .map_err(|_| format!("unknown rotation: {} (daily, hourly or size like 100MB)", s))?;
+        if bytes == 0 {
+            return Err(format!("rotation size must be positive: {}", s));
+        }
+        Ok(Rotation::Size(bytes * unit))
+    }
+}
+
+impl fmt::Display for Rotation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rotation::Daily => write!(f, "daily"),
+            Rotation::Hourly => write!(f, "hourly"),
+            Rotation::Size(bytes) => write!(f, "{}", bytes),
+        }
+    }
+}
+
+// 分割したファイル
+#[derive(Clone, Debug, PartialEq)]
+pub struct Segment {
+    // 日付毎のファイル内の連番(1時間毎の場合は日付の開始時刻からの経過時間)
+    pub number: u32,
+    pub file_name: String,
+    // 最初と最後に書き込んだデータの時刻
+    pub first: DateTime<Utc>,
+    pub last: DateTime<Utc>,
+    pub records: u64,
+    pub bytes: u64,
+}
+
+impl Segment {
+    // 連番 ファイル名 最初のデータの時刻(ミリ秒) 最後のデータの時刻(ミリ秒) 書き込み回数 バイト数
+    pub fn get_csv(&self) -> String {
+        format!(
+            "{} {} {} {} {} {}\n",
+            self.number,
+            self.file_name,
+            self.first.timestamp_millis(),
+            self.last.timestamp_millis(),
+            self.records,
+            self.bytes
+        )
+    }
+
+    pub fn from_csv(line: &str) -> Option<Self> {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() != 6 {
+            return None;
+        }
+        Some(Segment {
+            number: fields[0].parse().ok()?,
+            file_name: fields[1].to_string(),
+            first: Utc.timestamp_millis_opt(fields[2].parse().ok()?).single()?,
+            last: Utc.timestamp_millis_opt(fields[3].parse().ok()?).single()?,
+            records: fields[4].parse().ok()?,
+            bytes: fields[5].parse().ok()?,
+        })
+    }
+}
+
+// 分割したファイルの名前[{ファイル名}.{4桁の連番}.{拡張子}]
+pub fn segment_path(path: &Path, number: u32) -> PathBuf {
+    path.with_file_name(format!("{}.{:04}{}", file_stem(path), number, extension(path)))
+}
+
+// 分割したファイルの一覧の名前[{ファイル名}.index.csv]
+pub fn index_path(path: &Path) -> PathBuf {
+    path.with_file_name(format!("{}.index.csv", file_stem(path)))
+}
+
+// 日付毎のファイルとその分割したファイルのうち存在するものを返す(分割前のファイル、連番の順)
+pub fn segment_files(path: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    if path.is_file() {
+        files.push(path.to_path_buf());
+    }
+    let dir = match path.parent() {
+        Some(dir) if dir.is_dir() => dir,
+        _ => return Ok(files),
+    };
+    let prefix = format!("{}.", file_stem(path));
+    let suffix = extension(path);
+    let mut segments = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let file_name = entry?.file_name().to_string_lossy().to_string();
+        let number = file_name
+            .strip_prefix(&prefix)
+            .and_then(|rest| rest.strip_suffix(&suffix))
+            .filter(|number| number.len() == 4)
+            .and_then(|number| number.parse::<u32>().ok());
+        if let Some(number) = number {
+            segments.push(number);
+        }
+    }
+    segments.sort_unstable();
+    files.extend(segments.into_iter().map(|number| segment_path(path, number)));
+    Ok(files)
+}
+
+fn file_stem(path: &Path) -> String {
+    path.file_stem().unwrap_or_default().to_string_lossy().to_string()
+}
+
+fn extension(path: &Path) -> String {
+    path.extension()
+        .map(|extension| format!(".{}", extension.to_string_lossy()))
+        .unwrap_or_default()
+}
+
+// 日付毎のファイルの分割したファイルの一覧
+struct SegmentIndex {
+    segments: Vec<Segment>,
+    // 一覧を書き込んでから更新されたか
+    dirty: bool,
+}
+
+impl SegmentIndex {
+    // 書き込み済みの一覧を読み込む(存在しない場合は空)
+    fn load(path: &Path) -> io::Result<Self> {
+        let mut segments = Vec::new();
+        if path.exists() {
+            for line in BufReader::new(File::open(path)?).lines() {
+                if let Some(segment) = Segment::from_csv(&line?) {
+                    segments.push(segment);
+                }
+            }
+        }
+        Ok(SegmentIndex { segments, dirty: false })
+    }
+
+    fn write(&self, path: &Path) -> io::Result<()> {
+        // 書き込み途中の一覧が残らないよう、一時ファイルに書き込んでから置き換える
+        let temp_path = path.with_extension("csv.tmp");
+        let mut file = File::create(&temp_path)?;
+        for segment in self.segments.iter() {
+            file.write_all(segment.get_csv().as_bytes())?;
+        }
+        file.sync_all()?;
+        fs::rename(temp_path, path)
+    }
+}
+
+// 日付毎のファイルを分割し、分割したファイルの一覧を書き込む
+pub struct Rotator {
+    rotation: Rotation,
+    // 日付毎のファイル毎の一覧
+    indexes: HashMap<PathBuf, SegmentIndex>,
+    last_flush: Option<DateTime<Utc>>,
+}
+
+impl Rotator {
+    pub fn new(rotation: Rotation) -> Self {
+        Rotator {
+            rotation,
+            indexes: HashMap::new(),
+            last_flush: None,
+        }
+    }
+
+    // 日付毎のファイルに書き込むデータの書き込み先を返す(分割しない場合はそのまま)
+    // day_startはデータの日付の開始時刻
+    pub fn path(&mut self, path: &Path, time: DateTime<Utc>, day_start: DateTime<Utc>, bytes: usize) -> PathBuf {
+        if self.rotation == Rotation::Daily {
+            return path.to_path_buf();
+        }
+
+        let index_path = index_path(path);
+        let index = self.indexes.entry(index_path.clone()).or_insert_with(|| {
+            SegmentIndex::load(&index_path).unwrap_or_else(|error| {
+                warn!("Rotator.path.load: {} {}", index_path.display(), error);
+                SegmentIndex {
+                    segments: Vec::new(),
+                    dirty: false,
+                }
+            })
+        });
+        let bytes = bytes as u64;
+        let number = match self.rotation {
+            Rotation::Hourly => (time - day_start).num_hours().max(0) as u32,
+            Rotation::Size(limit) => match index.segments.last() {
+                Some(last) if last.bytes + bytes <= limit => last.number,
+                Some(last) => last.number + 1,
+                None => 0,
+            },
+            Rotation::Daily => 0,
+        };
+
+        let file_name = segment_path(path, number)
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+        let created = match index.segments.iter_mut().find(|segment| segment.number == number) {
+            Some(segment) => {
+                segment.first = segment.first.min(time);
+                segment.last = segment.last.max(time);
+                segment.records += 1;
+                segment.bytes += bytes;
+                false
+            }
+            None => {
+                index.segments.push(Segment {
+                    number,
+                    file_name,
+                    first: time,
+                    last: time,
+                    records: 1,
+                    bytes,
+                });
+                index.segments.sort_by_key(|segment| segment.number);
+                true
+            }
+        };
+        index.dirty = true;
+
+        // 新しいファイルを作成した場合は一覧にすぐに反映する
+        if created {
+            if let Some(dir) = index_path.parent() {
+                if let Err(error) = fs::create_dir_all(dir).and_then(|_| index.write(&index_path)) {
+                    warn!("Rotator.path.write: {} {}", index_path.display(), error);
+                } else {
+                    index.dirty = false;
+                }
+            }
+        }
+        segment_path(path, number)
+    }
+
+    // 更新された一覧を書き込む(前回から一定時間が経っていない場合は書き込まない)
+    pub fn tick(&mut self, now: DateTime<Utc>) -> io::Result<()> {
+        let interval = chrono::Duration::from_std(INDEX_FLUSH_INTERVAL).unwrap();
+        if let Some(last_flush) = self.last_flush {
+            if now < last_flush + interval {
+                return Ok(());
+            }
+        }
+        self.last_flush = Some(now);
+        self.flush()
+    }
+
+    // 更新された一覧を書き込み、前回から更新されていない一覧はメモリから外す
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.indexes.retain(|_, index| index.dirty);
+        for (path, index) in self.indexes.iter_mut() {
+            index.write(path)?;
+            index.dirty = false;
+        }
+        Ok(())
+    }
+}
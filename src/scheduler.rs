@@ -0,0 +1,88 @@
+use std::collections::{BTreeSet, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::product::ChannelKind;
+
+// スナップショットの再購読の既定の間隔
+pub const DEFAULT_SNAPSHOT_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+// 板情報の差分チャンネルから対応するスナップショットチャンネルを取得する
+pub fn snapshot_channel(board_channel: &str) -> String {
+    match ChannelKind::split(board_channel) {
+        Some((ChannelKind::Board, product_code)) => format!("{}{}", ChannelKind::BoardSnapshot.prefix(), product_code),
+        _ => board_channel.to_string(),
+    }
+}
+
+// 板の再同期の要求を受信スレッドに伝えるためのハンドル
+#[derive(Clone, Default)]
+pub struct ResyncRequests {
+    channels: Arc<Mutex<BTreeSet<String>>>,
+}
+
+impl ResyncRequests {
+    // 板情報のチャンネルのスナップショットの取得を要求する
+    pub fn request(&self, board_channel: &str) {
+        self.channels
+            .lock()
+            .unwrap()
+            .insert(snapshot_channel(board_channel));
+    }
+
+    // 要求されたスナップショットチャンネルを取り出す
+    fn take(&self) -> BTreeSet<String> {
+        std::mem::take(&mut *self.channels.lock().unwrap())
+    }
+}
+
+// スナップショットチャンネルを定期的に、または要求に応じて再購読する時期を管理する
+pub struct SnapshotScheduler {
+    channels: Vec<String>,
+    interval: Duration,
+    last_subscribed: HashMap<String, Instant>,
+    resync: ResyncRequests,
+}
+
+impl SnapshotScheduler {
+    pub fn new(channels: Vec<String>, interval: Duration, resync: ResyncRequests) -> Self {
+        SnapshotScheduler {
+            channels,
+            interval,
+            last_subscribed: HashMap::new(),
+            resync,
+        }
+    }
+
+    // 再購読するスナップショットチャンネルを置き換える(購読する銘柄が変わった場合)
+    pub fn set_channels(&mut self, channels: Vec<String>) {
+        self.last_subscribed.retain(|channel, _| channels.contains(channel));
+        self.channels = channels;
+    }
+
+    // 購読すべきスナップショットチャンネルを取得する
+    // 一度も購読していないチャンネルと、前回の購読から間隔が経過したチャンネル、再同期を要求されたチャンネルが対象
+    pub fn due(&mut self, now: Instant) -> Vec<String> {
+        let mut due: BTreeSet<String> = self
+            .channels
+            .iter()
+            .filter(|channel| match self.last_subscribed.get(*channel) {
+                Some(last) => self.interval <= now.duration_since(*last),
+                None => true,
+            })
+            .cloned()
+            .collect();
+        // 購読対象外のチャンネルの要求は無視する
+        due.extend(
+            self.resync
+                .take()
+                .into_iter()
+                .filter(|channel| self.channels.contains(channel)),
+        );
+
+        for channel in due.iter() {
+            self.last_subscribed.insert(channel.clone(), now);
+        }
+        due.into_iter().collect()
+    }
+}
@@ -0,0 +1,98 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// チャンネル毎の受信がない時間の閾値の設定を解析する({チャンネル}={秒})
+pub fn parse_channel_silence(s: &str) -> Result<(String, Duration), String> {
+    let mut parts = s.splitn(2, '=');
+    let channel = parts.next().unwrap_or("").trim();
+    let secs = parts.next().ok_or_else(|| format!("expected CHANNEL=SECONDS: {}", s))?;
+    if channel.is_empty() {
+        return Err(format!("empty channel: {}", s));
+    }
+    let secs: u64 = secs
+        .trim()
+        .parse()
+        .map_err(|error| format!("invalid seconds: {} {}", s, error))?;
+    if secs == 0 {
+        return Err(format!("seconds must be positive: {}", s));
+    }
+    Ok((channel.to_string(), Duration::from_secs(secs)))
+}
+
+// チャンネルの再購読の要求を受信スレッドに伝えるためのハンドル
+#[derive(Clone, Default)]
+pub struct ResubscribeRequests {
+    channels: Arc<Mutex<BTreeSet<String>>>,
+}
+
+impl ResubscribeRequests {
+    // チャンネルの再購読を要求する
+    pub fn request(&self, channel: &str) {
+        self.channels.lock().unwrap().insert(channel.to_string());
+    }
+
+    // 要求されたチャンネルを取り出す
+    pub fn take(&self) -> BTreeSet<String> {
+        std::mem::take(&mut *self.channels.lock().unwrap())
+    }
+}
+
+// 受信のないチャンネルへの対応
+#[derive(Clone, Debug, PartialEq)]
+pub enum SilenceAction {
+    // チャンネルのみを再購読する
+    Resubscribe(String),
+    // 再購読後も受信がないため、接続し直す
+    Reconnect(String),
+}
+
+// チャンネル毎に受信のない時間を監視する
+// 閾値を超えた場合はまずチャンネルを再購読し、再購読後も閾値を超えて受信がない場合は再接続する
+pub struct SilenceMonitor {
+    thresholds: BTreeMap<String, Duration>,
+    // 最後に受信した時刻(未受信の場合は監視の開始時刻)
+    last_message: BTreeMap<String, Instant>,
+    // 最後の受信以降に再購読した時刻
+    resubscribed: BTreeMap<String, Instant>,
+}
+
+impl SilenceMonitor {
+    pub fn new(thresholds: BTreeMap<String, Duration>, now: Instant) -> Self {
+        let last_message = thresholds.keys().map(|channel| (channel.clone(), now)).collect();
+        SilenceMonitor {
+            thresholds,
+            last_message,
+            resubscribed: BTreeMap::new(),
+        }
+    }
+
+    // チャンネルの受信を記録する(監視対象外のチャンネルは無視する)
+    pub fn on_message(&mut self, channel: &str, now: Instant) {
+        if let Some(last) = self.last_message.get_mut(channel) {
+            *last = now;
+            self.resubscribed.remove(channel);
+        }
+    }
+
+    // 受信のない時間が閾値を超えたチャンネルへの対応を取得する
+    pub fn check(&mut self, now: Instant) -> Vec<SilenceAction> {
+        let mut actions = Vec::new();
+        for (channel, threshold) in self.thresholds.iter() {
+            let since = match self.resubscribed.get(channel) {
+                Some(resubscribed) => *resubscribed,
+                None => self.last_message[channel],
+            };
+            if now.saturating_duration_since(since) < *threshold {
+                continue;
+            }
+            if self.resubscribed.contains_key(channel) {
+                actions.push(SilenceAction::Reconnect(channel.clone()));
+            } else {
+                self.resubscribed.insert(channel.clone(), now);
+                actions.push(SilenceAction::Resubscribe(channel.clone()));
+            }
+        }
+        actions
+    }
+}
@@ -0,0 +1,261 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+use serde_json::{json, Value};
+
+use crate::convert::{ExecutionRow, LatencyRow};
+use crate::latency::LatencyPercentiles;
+use crate::recorder::Recorder;
+use crate::rotation::segment_files;
+use crate::stream_api::Side;
+use crate::validate::DataKind;
+
+// 約定がない期間(約定日時、秒)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Gap {
+    pub start: i64,
+    pub end: i64,
+}
+
+impl Gap {
+    pub fn seconds(&self) -> i64 {
+        self.end - self.start
+    }
+}
+
+// 約定データ・遅延データの1日分の統計
+#[derive(Clone, Debug, PartialEq)]
+pub struct DailyStats {
+    pub date: NaiveDate,
+    pub channel: String,
+    pub trades: usize,
+    // 売買種別毎の出来高(板寄せ等で売買種別がない約定はunknown)
+    pub buy_volume: f64,
+    pub sell_volume: f64,
+    pub unknown_volume: f64,
+    pub vwap: Option<f64>,
+    pub high: Option<f64>,
+    pub low: Option<f64>,
+    // 数量の最も大きい約定
+    pub largest: Option<ExecutionRow>,
+    // 約定の間隔(秒)
+    pub intervals: Option<LatencyPercentiles>,
+    // 遅延(マイクロ秒)
+    pub latency: Option<LatencyPercentiles>,
+    pub gaps: Vec<Gap>,
+    // 解析できずに除いた行
+    pub skipped: usize,
+}
+
+impl DailyStats {
+    // 約定データ・遅延データから統計を計算する
+    // 約定がない期間は[start, end)の範囲で、最初と最後の約定の前後も含めて検出する
+    pub fn compute(
+        date: NaiveDate,
+        channel: &str,
+        executions: &[ExecutionRow],
+        latency: &[LatencyRow],
+        (start, end): (i64, i64),
+        gap_threshold: Duration,
+    ) -> Self {
+        let mut executions: Vec<&ExecutionRow> = executions.iter().collect();
+        executions.sort_by_key(|row| row.exec_time);
+
+        let volume = |side: Side| -> f64 {
+            executions
+                .iter()
+                .filter(|row| row.side == side)
+                .map(|row| row.size)
+                .sum()
+        };
+        let size: f64 = executions.iter().map(|row| row.size).sum();
+        let notional: f64 = executions.iter().map(|row| row.price * row.size).sum();
+        let prices = executions.iter().map(|row| row.price);
+        let largest = executions
+            .iter()
+            .fold(None, |largest: Option<&ExecutionRow>, row| match largest {
+                Some(largest) if row.size <= largest.size => Some(largest),
+                _ => Some(row),
+            })
+            .cloned();
+
+        let mut intervals: Vec<i64> = executions
+            .windows(2)
+            .map(|pair| pair[1].exec_time - pair[0].exec_time)
+            .collect();
+        let mut latency: Vec<i64> = latency
+            .iter()
+            .map(|row| (row.latency_ms * 1000.0).round() as i64)
+            .collect();
+
+        // 日付の開始から最初の約定、約定の間、最後の約定から日付の終了までを確認する
+        let threshold = gap_threshold.as_secs() as i64;
+        let mut times = vec![start];
+        times.extend(executions.iter().map(|row| row.exec_time));
+        times.push(end);
+        let gaps = times
+            .windows(2)
+            .map(|pair| Gap {
+                start: pair[0],
+                end: pair[1],
+            })
+            .filter(|gap| threshold <= gap.seconds())
+            .collect();
+
+        DailyStats {
+            date,
+            channel: channel.to_string(),
+            trades: executions.len(),
+            buy_volume: volume(Side::Buy),
+            sell_volume: volume(Side::Sell),
+            unknown_volume: volume(Side::NoSide),
+            vwap: if 0.0 < size { Some(notional / size) } else { None },
+            high: prices.clone().reduce(f64::max),
+            low: prices.reduce(f64::min),
+            largest,
+            intervals: LatencyPercentiles::from_samples(&mut intervals),
+            latency: LatencyPercentiles::from_samples(&mut latency),
+            gaps,
+            skipped: 0,
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        let percentiles = |percentiles: &Option<LatencyPercentiles>, scale: f64| {
+            percentiles.map(|percentiles| {
+                json!({
+                    "count": percentiles.count,
+                    "p50": percentiles.p50 as f64 / scale,
+                    "p90": percentiles.p90 as f64 / scale,
+                    "p99": percentiles.p99 as f64 / scale,
+                    "max": percentiles.max as f64 / scale,
+                })
+            })
+        };
+        json!({
+            "date": self.date.format("%Y-%m-%d").to_string(),
+            "channel": self.channel,
+            "trades": self.trades,
+            "buy_volume": self.buy_volume,
+            "sell_volume": self.sell_volume,
+            "unknown_volume": self.unknown_volume,
+            "vwap": self.vwap,
+            "high": self.high,
+            "low": self.low,
+            "largest": self.largest.as_ref().map(|row| json!({
+                "exec_time": row.exec_time,
+                "side": row.side.to_string(),
+                "price": row.price,
+                "size": row.size,
+                "id": row.id,
+            })),
+            "interval_secs": percentiles(&self.intervals, 1.0),
+            "latency_ms": percentiles(&self.latency, 1000.0),
+            "gaps": self.gaps.iter().map(|gap| json!({
+                "start": gap.start,
+                "end": gap.end,
+                "seconds": gap.seconds(),
+            })).collect::<Vec<_>>(),
+            "skipped": self.skipped,
+        })
+    }
+}
+
+// 約定日時(秒)をRFC 3339の文字列にする
+fn time_string(secs: i64) -> String {
+    match Utc.timestamp_opt(secs, 0).single() {
+        Some(time) => time.to_rfc3339(),
+        None => secs.to_string(),
+    }
+}
+
+// 表形式の統計
+impl fmt::Display for DailyStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let optional = |value: Option<f64>| value.map_or("-".to_string(), |value| value.to_string());
+        let percentiles = |percentiles: &Option<LatencyPercentiles>, scale: f64| match percentiles {
+            Some(percentiles) => format!(
+                "{} / {} / {} / {} (n={})",
+                percentiles.p50 as f64 / scale,
+                percentiles.p90 as f64 / scale,
+                percentiles.p99 as f64 / scale,
+                percentiles.max as f64 / scale,
+                percentiles.count
+            ),
+            None => "-".to_string(),
+        };
+        writeln!(f, "{:<32} {}", "date", self.date.format("%Y-%m-%d"))?;
+        writeln!(f, "{:<32} {}", "channel", self.channel)?;
+        writeln!(f, "{:<32} {}", "trades", self.trades)?;
+        writeln!(f, "{:<32} {}", "buy volume", self.buy_volume)?;
+        writeln!(f, "{:<32} {}", "sell volume", self.sell_volume)?;
+        writeln!(f, "{:<32} {}", "unknown side volume", self.unknown_volume)?;
+        writeln!(f, "{:<32} {}", "vwap", optional(self.vwap))?;
+        writeln!(f, "{:<32} {}", "high", optional(self.high))?;
+        writeln!(f, "{:<32} {}", "low", optional(self.low))?;
+        let largest = match &self.largest {
+            Some(row) => format!("{} @ {} {} {}", row.size, row.price, row.side, time_string(row.exec_time)),
+            None => "-".to_string(),
+        };
+        writeln!(f, "{:<32} {}", "largest trade", largest)?;
+        writeln!(f, "{:<32} {}", "interval p50/p90/p99/max (s)", percentiles(&self.intervals, 1.0))?;
+        writeln!(f, "{:<32} {}", "latency p50/p90/p99/max (ms)", percentiles(&self.latency, 1000.0))?;
+        writeln!(f, "{:<32} {}", "gaps", self.gaps.len())?;
+        for gap in self.gaps.iter() {
+            writeln!(
+                f,
+                "  {} - {} ({}s)",
+                time_string(gap.start),
+                time_string(gap.end),
+                gap.seconds()
+            )?;
+        }
+        if 0 < self.skipped {
+            writeln!(f, "{:<32} {}", "skipped lines", self.skipped)?;
+        }
+        Ok(())
+    }
+}
+
+// 1日分のファイル(分割したファイルを含む)を読み込み、解析できない行の数を返す
+fn read_rows<T, F: Fn(&str) -> Option<T>>(files: &[PathBuf], parse: F, rows: &mut Vec<T>) -> io::Result<usize> {
+    let mut skipped = 0;
+    for path in files {
+        for line in BufReader::new(File::open(path)?).lines() {
+            let line = line?;
+            match parse(&line) {
+                Some(row) => rows.push(row),
+                None if line.trim().is_empty() => {}
+                None => skipped += 1,
+            }
+        }
+    }
+    Ok(skipped)
+}
+
+// 記録済みの約定データ・遅延データから日付とチャンネルの統計を計算する
+// 記録中の日付は現在時刻までの途切れを検出する
+pub fn daily_stats(
+    recorder: &Recorder,
+    channel: &str,
+    date: NaiveDate,
+    gap_threshold: Duration,
+    now: DateTime<Utc>,
+) -> io::Result<DailyStats> {
+    let mut executions = Vec::new();
+    let mut latency = Vec::new();
+    let path = |kind: DataKind| recorder.channel_path(date, kind.prefix(), channel);
+    let mut skipped = read_rows(&segment_files(&path(DataKind::Executions))?, ExecutionRow::parse, &mut executions)?;
+    skipped += read_rows(&segment_files(&path(DataKind::Latency))?, LatencyRow::parse, &mut latency)?;
+
+    let partition = recorder.get_partition();
+    let start = partition.start(date).timestamp();
+    let end = partition.end(date).min(now).timestamp().max(start);
+    let mut stats = DailyStats::compute(date, channel, &executions, &latency, (start, end), gap_threshold);
+    stats.skipped = skipped;
+    Ok(stats)
+}
@@ -1,36 +1,42 @@
-use std::sync::{Arc, mpsc, atomic::{AtomicBool, Ordering} };
+use std::collections::{BTreeSet, HashMap};
+use std::sync::{Arc, Mutex, mpsc, atomic::{AtomicBool, AtomicUsize, Ordering} };
+use std::io;
 use std::thread;
+use std::time::{Duration as StdDuration, Instant};
 
-use chrono::{DateTime, Timelike, Utc};
+use chrono::{DateTime, Duration, Utc};
 
-use tungstenite::{connect, Message};
+use tungstenite::client::AutoStream;
+use tungstenite::stream::Stream as StreamSwitcher;
+use tungstenite::{connect, Message, WebSocket};
 
 use url::Url;
 
-use serde_json::{from_str, Value};
+use serde_json::{from_str, json, Value};
 
 use std::fmt;
 
 use log::{info, warn, error};
 
+use crate::auth::{authenticate, AuthError, Credentials, AUTH_TIMEOUT};
+use crate::clock::ReceiveTime;
+use crate::exchange_status::ExchangeStatus;
+use crate::latency::ClockOffset;
+use crate::logging::event;
+use crate::product::{ChannelKind, Product, ProductRegistry, DEFAULT_PRODUCT_CODES};
+use crate::raw_archive::RawFrame;
+use crate::scheduler::{ResyncRequests, SnapshotScheduler, DEFAULT_SNAPSHOT_INTERVAL};
+use crate::silence::ResubscribeRequests;
+
 // 共通処理
 pub trait Common {
     // csvに書き込む用のデータを文字列として取得
     fn get_csv(&self) -> String;
 
+    // データの日時(書き込む日付はDayPartitionでこの日時から求める)
     fn data_time(&self) -> DateTime<Utc>;
     fn channel(&self) -> String;
 
-    // データの日付(年月日)を取得
-    fn get_date(&self) -> String {
-        self.data_time().format("%Y%m%d").to_string()
-    }
-
-    // データの日時(年月日時分秒)を取得
-    fn get_date_second(&self) -> String {
-        self.data_time().format("%Y%m%d%H%M%S").to_string()
-    }
-
     // データのチャンネルを取得
     fn get_channel(&self) -> String {
         self.channel().to_string()
@@ -38,7 +44,7 @@ pub trait Common {
 }
 
 // 売買種別
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Side {
     Buy,
     Sell,
@@ -67,24 +73,46 @@ impl Side {
             Side::NoSide
         }
     }
+
+    // 記録したファイルの売買種別(B/S/N)から変換する
+    pub fn from_code(code: &str) -> Option<Self> {
+        match code {
+            "B" => Some(Side::Buy),
+            "S" => Some(Side::Sell),
+            "N" => Some(Side::NoSide),
+            _ => None,
+        }
+    }
 }
 
 // 約定履歴の構造体
 #[derive(Clone)]
 pub struct Execution {
+    // 約定ID
+    id: u64,
     exec_date: DateTime<Utc>,
     exec_unix_time: i64,
     side: Side,
     price: f64,
     size: f64,
     channel: String,
+    receive_time: ReceiveTime,
+    sequence: u64,
 }
 
 impl Common for Execution {
+    // 約定日時(秒) 売買種別 価格 数量 受信時刻(ナノ秒) 単調時計の受信時刻(ナノ秒) 受信シーケンス番号 約定ID
     fn get_csv(&self) -> String {
         format!(
-            "{} {} {} {}\n",
-            self.exec_unix_time, self.side, self.price, self.size
+            "{} {} {} {} {} {} {} {}\n",
+            self.exec_unix_time,
+            self.side,
+            self.price,
+            self.size,
+            self.receive_time.wall_nanos(),
+            self.receive_time.monotonic_nanos(),
+            self.sequence,
+            self.id
         )
     }
 
@@ -98,6 +126,9 @@ impl Common for Execution {
 }
 
 impl Execution {
+    pub fn get_id(&self) -> u64 {
+        self.id
+    }
     pub fn get_side(&self) -> Side {
         self.side
     }
@@ -107,26 +138,46 @@ impl Execution {
     pub fn get_size(&self) -> f64 {
         self.size
     }
+    pub fn get_receive_time(&self) -> ReceiveTime {
+        self.receive_time
+    }
+    pub fn get_sequence(&self) -> u64 {
+        self.sequence
+    }
+}
+
+// 遅延情報の計測対象
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LatencyKind {
+    // 約定1件毎の遅延(受信時刻 - 約定日時)
+    // 板情報には配信時刻が含まれず遅延を計測できないため、板情報の遅延データは作成しない
+    Execution,
 }
 
 // 遅延情報の構造体
+#[derive(Clone, Debug)]
 pub struct Latency {
-    sender_time: i64,
-    receive_time: DateTime<Utc>,
+    latency: Duration,
+    receive_time: ReceiveTime,
+    sequence: u64,
     channel: String,
+    kind: LatencyKind,
 }
 
 impl Common for Latency {
+    // 受信時刻(ナノ秒) 遅延(ミリ秒、マイクロ秒精度) 単調時計の受信時刻(ナノ秒) 受信シーケンス番号
     fn get_csv(&self) -> String {
         format!(
-            "{} {}\n",
-            self.receive_time.timestamp_millis(),
-            self.sender_time
+            "{} {:.3} {} {}\n",
+            self.receive_time.wall_nanos(),
+            self.get_latency_micros() as f64 / 1000.0,
+            self.receive_time.monotonic_nanos(),
+            self.sequence
         )
     }
 
     fn data_time(&self) -> DateTime<Utc> {
-        self.receive_time
+        self.receive_time.wall
     }
 
     fn channel(&self) -> String {
@@ -134,9 +185,25 @@ impl Common for Latency {
     }
 }
 
+impl Latency {
+    pub fn get_latency_micros(&self) -> i64 {
+        self.latency.num_microseconds().unwrap_or(i64::MAX)
+    }
+    pub fn get_kind(&self) -> LatencyKind {
+        self.kind
+    }
+    pub fn get_receive_time(&self) -> ReceiveTime {
+        self.receive_time
+    }
+    pub fn get_sequence(&self) -> u64 {
+        self.sequence
+    }
+}
+
 // 板情報の構造体
 pub struct Board {
-    receive_time: DateTime<Utc>,
+    receive_time: ReceiveTime,
+    sequence: u64,
     pub asks: Vec<(f64, f64)>,
     pub bids: Vec<(f64, f64)>,
     channel: String,
@@ -146,11 +213,16 @@ pub struct Board {
 impl Common for Board {
     // NOTE:
     fn get_csv(&self) -> String {
-        format!("{}\n", self.receive_time.timestamp_millis())
+        format!(
+            "{} {} {}\n",
+            self.receive_time.wall_nanos(),
+            self.receive_time.monotonic_nanos(),
+            self.sequence
+        )
     }
 
     fn data_time(&self) -> DateTime<Utc> {
-        self.receive_time
+        self.receive_time.wall
     }
 
     fn channel(&self) -> String {
@@ -158,6 +230,394 @@ impl Common for Board {
     }
 }
 
+// 注文イベントのチャンネル種別
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OrderEventKind {
+    // 子注文イベント(child_order_events)
+    ChildOrder,
+    // 親注文イベント(parent_order_events)
+    ParentOrder,
+}
+
+impl OrderEventKind {
+    // チャンネル名から注文イベントの種別を取得する
+    pub fn from_channel(channel: &str) -> Option<Self> {
+        match channel {
+            CHILD_ORDER_EVENTS => Some(OrderEventKind::ChildOrder),
+            PARENT_ORDER_EVENTS => Some(OrderEventKind::ParentOrder),
+            _ => None,
+        }
+    }
+}
+
+// 注文イベントの種類
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OrderEventType {
+    Order,
+    OrderFailed,
+    Cancel,
+    CancelFailed,
+    Execution,
+    Expire,
+    Trigger,
+    Complete,
+    Unknown,
+}
+
+impl fmt::Display for OrderEventType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OrderEventType::Order => write!(f, "ORDER"),
+            OrderEventType::OrderFailed => write!(f, "ORDER_FAILED"),
+            OrderEventType::Cancel => write!(f, "CANCEL"),
+            OrderEventType::CancelFailed => write!(f, "CANCEL_FAILED"),
+            OrderEventType::Execution => write!(f, "EXECUTION"),
+            OrderEventType::Expire => write!(f, "EXPIRE"),
+            OrderEventType::Trigger => write!(f, "TRIGGER"),
+            OrderEventType::Complete => write!(f, "COMPLETE"),
+            OrderEventType::Unknown => write!(f, "UNKNOWN"),
+        }
+    }
+}
+
+impl OrderEventType {
+    // 文字列から注文イベントの種類に変換する
+    fn from_str(s: &str) -> Self {
+        match s {
+            "ORDER" => OrderEventType::Order,
+            "ORDER_FAILED" => OrderEventType::OrderFailed,
+            "CANCEL" => OrderEventType::Cancel,
+            "CANCEL_FAILED" => OrderEventType::CancelFailed,
+            "EXECUTION" => OrderEventType::Execution,
+            "EXPIRE" => OrderEventType::Expire,
+            "TRIGGER" => OrderEventType::Trigger,
+            "COMPLETE" => OrderEventType::Complete,
+            _ => OrderEventType::Unknown,
+        }
+    }
+}
+
+// 注文イベントの構造体
+#[derive(Clone, Debug)]
+pub struct OrderEvent {
+    pub kind: OrderEventKind,
+    pub event_type: OrderEventType,
+    pub event_date: DateTime<Utc>,
+    pub product_code: String,
+    // 子注文イベントの場合はchild_order_id、親注文イベントの場合はparent_order_id
+    pub order_id: String,
+    // 子注文イベントの場合はchild_order_acceptance_id、親注文イベントの場合はparent_order_acceptance_id
+    pub order_acceptance_id: String,
+    pub order_type: Option<String>,
+    pub side: Side,
+    pub price: Option<f64>,
+    pub size: Option<f64>,
+    // 約定(EXECUTION)の場合のみ
+    pub exec_id: Option<i64>,
+    pub commission: Option<f64>,
+    pub sfd: Option<f64>,
+    // 失敗(ORDER_FAILED等)の場合のみ
+    pub reason: Option<String>,
+    // 親注文イベントのみ
+    pub child_order_acceptance_id: Option<String>,
+    pub parameter_index: Option<i64>,
+    pub receive_time: ReceiveTime,
+    pub sequence: u64,
+    channel: String,
+}
+
+impl Common for OrderEvent {
+    fn get_csv(&self) -> String {
+        // 値がない項目は"-"で出力する
+        fn or_dash<T: fmt::Display>(v: &Option<T>) -> String {
+            match v {
+                Some(v) => v.to_string(),
+                None => String::from("-"),
+            }
+        }
+        format!(
+            "{} {} {} {} {} {} {} {} {} {} {} {} {}\n",
+            self.event_date.timestamp_millis(),
+            self.event_type,
+            self.product_code,
+            self.order_acceptance_id,
+            self.side,
+            or_dash(&self.price),
+            or_dash(&self.size),
+            or_dash(&self.exec_id),
+            or_dash(&self.commission),
+            or_dash(&self.sfd),
+            self.receive_time.wall_nanos(),
+            self.receive_time.monotonic_nanos(),
+            self.sequence,
+        )
+    }
+
+    fn data_time(&self) -> DateTime<Utc> {
+        self.event_date
+    }
+
+    fn channel(&self) -> String {
+        self.channel.clone()
+    }
+}
+
+impl OrderEvent {
+    // 注文イベント1件分のJSONから構造体を生成する
+    pub fn from_json(
+        kind: OrderEventKind,
+        channel: &str,
+        v: &Value,
+        receive_time: ReceiveTime,
+        sequence: u64,
+    ) -> Option<Self> {
+        let event_date = v["event_date"].as_str()?.parse::<DateTime<Utc>>().ok()?;
+        let (order_id, order_acceptance_id, order_type) = match kind {
+            OrderEventKind::ChildOrder => (
+                &v["child_order_id"],
+                &v["child_order_acceptance_id"],
+                &v["child_order_type"],
+            ),
+            OrderEventKind::ParentOrder => (
+                &v["parent_order_id"],
+                &v["parent_order_acceptance_id"],
+                &v["parent_order_type"],
+            ),
+        };
+        let (child_order_acceptance_id, parameter_index) = match kind {
+            OrderEventKind::ChildOrder => (None, None),
+            OrderEventKind::ParentOrder => (
+                v["child_order_acceptance_id"].as_str().map(String::from),
+                v["parameter_index"].as_i64(),
+            ),
+        };
+        Some(OrderEvent {
+            kind,
+            event_type: OrderEventType::from_str(v["event_type"].as_str().unwrap_or("")),
+            event_date,
+            product_code: v["product_code"].as_str().unwrap_or("").to_string(),
+            order_id: order_id.as_str().unwrap_or("").to_string(),
+            order_acceptance_id: order_acceptance_id.as_str().unwrap_or("").to_string(),
+            order_type: order_type.as_str().map(String::from),
+            side: Side::from_str(v["side"].as_str().unwrap_or("")),
+            price: v["price"].as_f64(),
+            size: v["size"].as_f64(),
+            exec_id: v["exec_id"].as_i64(),
+            commission: v["commission"].as_f64(),
+            sfd: v["sfd"].as_f64(),
+            reason: v["reason"].as_str().map(String::from),
+            child_order_acceptance_id,
+            parameter_index,
+            receive_time,
+            sequence,
+            channel: channel.to_string(),
+        })
+    }
+}
+
+// 注文イベントチャンネルのメッセージ(イベントの配列)を解析する
+pub fn parse_order_events(
+    channel: &str,
+    message: &Value,
+    receive_time: ReceiveTime,
+    sequence: u64,
+) -> Vec<OrderEvent> {
+    let kind = match OrderEventKind::from_channel(channel) {
+        Some(kind) => kind,
+        None => return Vec::new(),
+    };
+    let mut events = Vec::new();
+    for event in message.as_array().map(|a| a.as_slice()).unwrap_or(&[]) {
+        match OrderEvent::from_json(kind, channel, event, receive_time, sequence) {
+            Some(event) => events.push(event),
+            None => warn!("parse_order_events: Invalid event on {}. {}", channel, event),
+        }
+    }
+    events
+}
+
+// 受信スレッドがソケットの読み込みを待つ最大時間
+const READ_TIMEOUT: StdDuration = StdDuration::from_millis(100);
+
+// ソケットの読み込みのタイムアウトを設定する
+fn set_read_timeout(
+    socket: &WebSocket<AutoStream>,
+    timeout: Option<StdDuration>,
+) -> io::Result<()> {
+    match socket.get_ref() {
+        StreamSwitcher::Plain(stream) => stream.set_read_timeout(timeout),
+        StreamSwitcher::Tls(stream) => stream.get_ref().set_read_timeout(timeout),
+    }
+}
+
+// ストリーミングAPIのエンドポイント
+pub const BF_END_POINT: &str = "wss://ws.lightstream.bitflyer.com/json-rpc";
+
+// 子注文イベントのチャンネル
+pub const CHILD_ORDER_EVENTS: &str = "child_order_events";
+// 親注文イベントのチャンネル
+pub const PARENT_ORDER_EVENTS: &str = "parent_order_events";
+
+// ストリーミングAPIのエラー
+#[derive(Clone, Debug, PartialEq)]
+pub enum StreamError {
+    // チャンネルの購読に失敗した
+    Subscribe {
+        channel: String,
+        code: i64,
+        message: String,
+    },
+    // チャンネルの購読停止に失敗した
+    Unsubscribe {
+        channel: String,
+        code: i64,
+        message: String,
+    },
+    // 送信していないリクエストIDのレスポンスを受信した
+    UnknownResponse { id: u64 },
+    // 認証に失敗し、プライベートチャンネルを購読できない
+    Auth { message: String },
+}
+
+impl fmt::Display for StreamError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StreamError::Subscribe {
+                channel,
+                code,
+                message,
+            } => write!(f, "Subscribe {} failed. ({}) {}", channel, code, message),
+            StreamError::Unsubscribe {
+                channel,
+                code,
+                message,
+            } => write!(f, "Unsubscribe {} failed. ({}) {}", channel, code, message),
+            StreamError::UnknownResponse { id } => write!(f, "Unknown response id {}.", id),
+            StreamError::Auth { message } => write!(f, "Authentication failed. {}", message),
+        }
+    }
+}
+
+impl std::error::Error for StreamError {}
+
+// 接続の開始に失敗した理由
+#[derive(Debug)]
+pub enum ConnectError {
+    // 接続・購読の要求に失敗した
+    Socket(tungstenite::Error),
+    // 認証に失敗した
+    Auth(AuthError),
+}
+
+impl fmt::Display for ConnectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConnectError::Socket(error) => write!(f, "{}", error),
+            ConnectError::Auth(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for ConnectError {}
+
+impl From<tungstenite::Error> for ConnectError {
+    fn from(error: tungstenite::Error) -> Self {
+        ConnectError::Socket(error)
+    }
+}
+
+// JSON-RPCのメソッド
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RpcMethod {
+    Subscribe,
+    Unsubscribe,
+}
+
+impl fmt::Display for RpcMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RpcMethod::Subscribe => write!(f, "subscribe"),
+            RpcMethod::Unsubscribe => write!(f, "unsubscribe"),
+        }
+    }
+}
+
+// JSON-RPCのリクエストIDを採番し、レスポンス待ちのリクエストを管理する
+struct RpcRequests {
+    next_id: u64,
+    pending: HashMap<u64, (RpcMethod, String)>,
+}
+
+impl RpcRequests {
+    fn new() -> Self {
+        // 0は認証リクエストで使用する
+        RpcRequests {
+            next_id: 1,
+            pending: HashMap::new(),
+        }
+    }
+
+    // IDを付与したリクエストを生成し、レスポンス待ちとして登録する
+    fn request(&mut self, method: RpcMethod, channel: &str) -> String {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.pending.insert(id, (method, channel.to_string()));
+        json!({
+            "jsonrpc": "2.0",
+            "method": method.to_string(),
+            "params": {"channel": channel},
+            "id": id,
+        })
+        .to_string()
+    }
+
+    // レスポンスを対応するリクエストと照合し、購読中のチャンネルを更新する
+    fn on_response(
+        &mut self,
+        v: &Value,
+        subscriptions: &Mutex<BTreeSet<String>>,
+    ) -> Option<StreamError> {
+        let id = v["id"].as_u64().unwrap_or_default();
+        let (method, channel) = match self.pending.remove(&id) {
+            Some(request) => request,
+            None => return Some(StreamError::UnknownResponse { id }),
+        };
+
+        if v["result"].as_bool() == Some(true) {
+            let mut subscriptions = subscriptions.lock().unwrap();
+            match method {
+                RpcMethod::Subscribe => {
+                    info!(event = event::SUBSCRIBED, channel = channel.as_str(); "on_response: Subscribed {}", channel);
+                    subscriptions.insert(channel);
+                }
+                RpcMethod::Unsubscribe => {
+                    info!(event = event::UNSUBSCRIBED, channel = channel.as_str(); "on_response: Unsubscribed {}", channel);
+                    subscriptions.remove(&channel);
+                }
+            }
+            return None;
+        }
+
+        let code = v["error"]["code"].as_i64().unwrap_or_default();
+        let message = match v["error"]["message"].as_str() {
+            Some(message) => message.to_string(),
+            None => v["error"].to_string(),
+        };
+        match method {
+            RpcMethod::Subscribe => Some(StreamError::Subscribe {
+                channel,
+                code,
+                message,
+            }),
+            RpcMethod::Unsubscribe => Some(StreamError::Unsubscribe {
+                channel,
+                code,
+                message,
+            }),
+        }
+    }
+}
+
 // ストリーミングAPIから得られる取引所からのマーケット情報
 pub enum MarketInfo {
     // 約定データ
@@ -169,16 +629,274 @@ pub enum MarketInfo {
     // 板情報データ
     Boards(Board),
 
+    // 注文イベントデータ(プライベートチャンネル)
+    OrderEvents(OrderEvent),
+
+    // 受信したフレーム(未解析)
+    RawFrames(RawFrame),
+
+    // HTTP APIから取得した取引所の状態
+    ExchangeStatus(ExchangeStatus),
+
+    // リクエストに対するエラー
+    Error(StreamError),
+
     // 受信終了
     Close,
 }
 
+impl MarketInfo {
+    // 受信したフレームの受信時刻を取得する(ストリーミングAPIの受信データ以外はNone)
+    pub fn get_receive_time(&self) -> Option<ReceiveTime> {
+        match self {
+            MarketInfo::Executions(execution) => Some(execution.receive_time),
+            MarketInfo::LatencyExchange(latency) => Some(latency.receive_time),
+            MarketInfo::Boards(board) => Some(board.receive_time),
+            MarketInfo::OrderEvents(event) => Some(event.receive_time),
+            MarketInfo::RawFrames(frame) => Some(frame.receive_time),
+            MarketInfo::ExchangeStatus(_) | MarketInfo::Error(_) | MarketInfo::Close => None,
+        }
+    }
+
+    // 受信データのチャンネルを取得する(受信したフレームとストリーミングAPIの受信データ以外はNone)
+    pub fn get_channel(&self) -> Option<String> {
+        match self {
+            MarketInfo::Executions(execution) => Some(execution.get_channel()),
+            MarketInfo::LatencyExchange(latency) => Some(latency.get_channel()),
+            MarketInfo::Boards(board) => Some(board.get_channel()),
+            MarketInfo::OrderEvents(event) => Some(event.get_channel()),
+            MarketInfo::RawFrames(_)
+            | MarketInfo::ExchangeStatus(_)
+            | MarketInfo::Error(_)
+            | MarketInfo::Close => None,
+        }
+    }
+
+    // 受信したフレームの接続内のシーケンス番号を取得する(ストリーミングAPIの受信データ以外はNone)
+    pub fn get_sequence(&self) -> Option<u64> {
+        match self {
+            MarketInfo::Executions(execution) => Some(execution.sequence),
+            MarketInfo::LatencyExchange(latency) => Some(latency.sequence),
+            MarketInfo::Boards(board) => Some(board.sequence),
+            MarketInfo::OrderEvents(event) => Some(event.sequence),
+            MarketInfo::RawFrames(frame) => Some(frame.sequence),
+            MarketInfo::ExchangeStatus(_) | MarketInfo::Error(_) | MarketInfo::Close => None,
+        }
+    }
+}
+
+// 受信したフレームをマーケット情報に変換する
+// ストリーミングAPIの受信スレッドと、記録済みのフレームの再解析で共通して用いる
+pub struct FrameParser {
+    execute_channels: Vec<String>,
+    board_channels: Vec<String>,
+    snapshot_channels: Vec<String>,
+    clock_offset: ClockOffset,
+}
+
+impl FrameParser {
+    pub fn new(
+        execute_channels: Vec<String>,
+        board_channels: Vec<String>,
+        snapshot_channels: Vec<String>,
+        clock_offset: ClockOffset,
+    ) -> Self {
+        FrameParser {
+            execute_channels,
+            board_channels,
+            snapshot_channels,
+            clock_offset,
+        }
+    }
+
+    // 解析するチャンネルを置き換える(購読する銘柄が変わった場合)
+    pub fn set_channels(
+        &mut self,
+        execute_channels: Vec<String>,
+        board_channels: Vec<String>,
+        snapshot_channels: Vec<String>,
+    ) {
+        self.execute_channels = execute_channels;
+        self.board_channels = board_channels;
+        self.snapshot_channels = snapshot_channels;
+    }
+
+    // JSON-RPCのフレーム(文字列)を解析する
+    // リクエストに対するレスポンス等、チャンネルのメッセージ以外は空で返す
+    pub fn parse(&mut self, text: &str, receive_time: ReceiveTime, sequence: u64) -> Vec<MarketInfo> {
+        let v: Value = match from_str(text) {
+            Ok(v) => v,
+            Err(error) => {
+                warn!("FrameParser.parse: Invalid JSON. {} {}", error, text);
+                return Vec::new();
+            }
+        };
+        match v["params"]["channel"].as_str() {
+            Some(channel) => {
+                self.parse_channel_message(channel, &v["params"]["message"], receive_time, sequence)
+            }
+            None => Vec::new(),
+        }
+    }
+
+    // チャンネルのメッセージを解析する
+    pub fn parse_channel_message(
+        &mut self,
+        channel: &str,
+        message: &Value,
+        receive_time: ReceiveTime,
+        sequence: u64,
+    ) -> Vec<MarketInfo> {
+        let channel = channel.to_string();
+
+        // 受信データが約定履歴の場合、
+        if self.execute_channels.contains(&channel) {
+            self.parse_executions(&channel, message, receive_time, sequence)
+
+        // 受信データが板情報の差分の場合、
+        } else if self.board_channels.contains(&channel) {
+            self.parse_board(&channel, message, receive_time, sequence, true)
+
+        // 受信データがスナップショットの場合、
+        } else if self.snapshot_channels.contains(&channel) {
+            self.parse_board(&channel.replace("_snapshot", ""), message, receive_time, sequence, false)
+
+        // 受信データが注文イベントの場合、
+        } else if OrderEventKind::from_channel(&channel).is_some() {
+            parse_order_events(&channel, message, receive_time, sequence)
+                .into_iter()
+                .map(MarketInfo::OrderEvents)
+                .collect()
+        } else {
+            Vec::new()
+        }
+    }
+
+    // 約定履歴と、約定1件毎の遅延データに変換する
+    fn parse_executions(
+        &mut self,
+        channel: &str,
+        message: &Value,
+        receive_time: ReceiveTime,
+        sequence: u64,
+    ) -> Vec<MarketInfo> {
+        // 時計のずれを補正した受信時刻
+        let adjusted_receive_time = self.clock_offset.adjust(receive_time.steady_wall());
+
+        let mut executes = Vec::new();
+        for v in message.as_array().map(|a| a.as_slice()).unwrap_or(&[]) {
+            let exec_date = match v["exec_date"].as_str().map(|s| s.parse::<DateTime<Utc>>()) {
+                Some(Ok(exec_date)) => exec_date,
+                _ => {
+                    warn!("FrameParser.parse_executions: Invalid execution on {}. {}", channel, v);
+                    continue;
+                }
+            };
+            let (id, price, size) = match (v["id"].as_u64(), v["price"].as_f64(), v["size"].as_f64()) {
+                (Some(id), Some(price), Some(size)) => (id, price, size),
+                _ => {
+                    warn!("FrameParser.parse_executions: Invalid execution on {}. {}", channel, v);
+                    continue;
+                }
+            };
+            executes.push(Execution {
+                id,
+                exec_date,
+                exec_unix_time: exec_date.timestamp(),
+                side: Side::from_str(v["side"].as_str().unwrap_or("")),
+                price,
+                size,
+                channel: channel.to_string(),
+                receive_time,
+                sequence,
+            });
+        }
+        executes.sort_by_key(|a| a.exec_unix_time); // 日付を古い順でソートする
+
+        // 約定1件毎に遅延データを作成する
+        let latencies: Vec<Latency> = executes
+            .iter()
+            .map(|execute| Latency {
+                latency: adjusted_receive_time - execute.exec_date,
+                receive_time,
+                sequence,
+                channel: channel.to_string(),
+                kind: LatencyKind::Execution,
+            })
+            .collect();
+
+        let mut market_infos: Vec<MarketInfo> =
+            executes.into_iter().map(MarketInfo::Executions).collect();
+        market_infos.extend(latencies.into_iter().map(MarketInfo::LatencyExchange));
+        market_infos
+    }
+
+    // 板情報(差分またはスナップショット)に変換する
+    fn parse_board(
+        &self,
+        channel: &str,
+        message: &Value,
+        receive_time: ReceiveTime,
+        sequence: u64,
+        is_update: bool,
+    ) -> Vec<MarketInfo> {
+        // 板の気配値を(価格, 数量)の配列に変換する
+        fn levels(v: &Value) -> Vec<(f64, f64)> {
+            v.as_array()
+                .map(|a| a.as_slice())
+                .unwrap_or(&[])
+                .iter()
+                .filter_map(|level| Some((level["price"].as_f64()?, level["size"].as_f64()?)))
+                .collect()
+        }
+
+        vec![MarketInfo::Boards(Board {
+            receive_time,
+            sequence,
+            asks: levels(&message["asks"]),
+            bids: levels(&message["bids"]),
+            channel: channel.to_string(),
+            is_update,
+        })]
+    }
+}
+
+// 銘柄毎の種類に対応したチャンネル(enabledがfalseの場合は購読しないため空)
+fn product_channels(products: &[Product], kind: ChannelKind, enabled: bool) -> Vec<String> {
+    if !enabled {
+        return Vec::new();
+    }
+    products.iter().map(|product| product.channel(kind)).collect()
+}
+
 // ストリーミングAPIのデータを取得・送信する構造体
 pub struct BfWebsocket {
     exchange_name: String,
+    end_point: String,
     tx: mpsc::Sender<MarketInfo>,
     rx: mpsc::Receiver<MarketInfo>,
+    // 配信済みで未取得のメッセージの数
+    queue_depth: Arc<AtomicUsize>,
     finish: Arc<AtomicBool>,
+    credentials: Option<Credentials>,
+    subscriptions: Arc<Mutex<BTreeSet<String>>>,
+    clock_offset: ClockOffset,
+    raw_capture: bool,
+    snapshot_interval: StdDuration,
+    resync: ResyncRequests,
+    resubscribe: ResubscribeRequests,
+    // 購読する銘柄
+    products: Vec<Product>,
+    // 接続中に変更された購読する銘柄(受信スレッドに伝える)
+    product_updates: Arc<Mutex<Option<Vec<Product>>>>,
+    // 板情報を購読するか
+    boards: bool,
+}
+
+impl Default for BfWebsocket {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl BfWebsocket {
@@ -193,251 +911,391 @@ impl BfWebsocket {
         let finish = Arc::new(AtomicBool::new(false));
         BfWebsocket {
             exchange_name,
+            end_point: String::from(BF_END_POINT),
             tx,
             rx,
+            queue_depth: Arc::new(AtomicUsize::new(0)),
             finish,
+            credentials: None,
+            subscriptions: Arc::new(Mutex::new(BTreeSet::new())),
+            clock_offset: ClockOffset::default(),
+            raw_capture: false,
+            snapshot_interval: DEFAULT_SNAPSHOT_INTERVAL,
+            resync: ResyncRequests::default(),
+            resubscribe: ResubscribeRequests::default(),
+            products: ProductRegistry::bitflyer()
+                .resolve(&DEFAULT_PRODUCT_CODES)
+                .expect("default products are registered"),
+            product_updates: Arc::new(Mutex::new(None)),
+            boards: false,
         }
     }
 
+    // スナップショットチャンネルを再購読する間隔を設定する
+    pub fn set_snapshot_interval(&mut self, snapshot_interval: StdDuration) {
+        self.snapshot_interval = snapshot_interval;
+    }
+
+    // 板の不整合を検知した場合等に、板情報のチャンネルのスナップショットの取得を要求する
+    pub fn request_resync(&self, board_channel: &str) {
+        info!("request_resync: {}", board_channel);
+        self.resync.request(board_channel);
+    }
+
+    // 受信が途絶えたチャンネルの再購読を要求する(購読中のチャンネル以外は無視される)
+    pub fn request_resubscribe(&self, channel: &str) {
+        info!(event = event::RESUBSCRIBE, exchange = self.exchange_name.as_str(), channel; "request_resubscribe: {}", channel);
+        self.resubscribe.request(channel);
+    }
+
+    // 受信したフレームをそのまま配信するかを設定する
+    pub fn set_raw_capture(&mut self, raw_capture: bool) {
+        self.raw_capture = raw_capture;
+    }
+
+    // 購読するチャンネルに対応したフレームの解析器を生成する
+    pub fn frame_parser(&self) -> FrameParser {
+        FrameParser::new(
+            self.get_public_execute_channels(),
+            self.get_public_board_channels(),
+            self.get_public_snapshot_channels(),
+            self.clock_offset.clone(),
+        )
+    }
+
+    // 遅延の計測に用いる時計のずれを設定する
+    pub fn set_clock_offset(&mut self, clock_offset: ClockOffset) {
+        self.clock_offset = clock_offset;
+    }
+
+    // サーバーが購読を承認したチャンネルを取得する
+    pub fn subscriptions(&self) -> Vec<String> {
+        self.subscriptions.lock().unwrap().iter().cloned().collect()
+    }
+
+    // プライベートチャンネルを購読するための認証情報を設定する
+    pub fn set_credentials(&mut self, credentials: Credentials) {
+        self.credentials = Some(credentials);
+    }
+
     // ストリーミングAPIのエンドポイント
     pub fn get_end_point(&self) -> String {
-        String::from("wss://ws.lightstream.bitflyer.com/json-rpc")
+        self.end_point.clone()
     }
 
-    // ストリーミングAPIを利用して購読するチャンネル
-    pub fn get_public_channels(&self) -> [String; 2] {
-        [
-            String::from("lightning_executions_FX_BTC_JPY"),
-            String::from("lightning_executions_BTC_JPY"),
-            // String::from("lightning_board_snapshot_FX_BTC_JPY"),
-            // String::from("lightning_board_FX_BTC_JPY"),
-            // String::from("lightning_board_snapshot_BTC_JPY"),
-            // String::from("lightning_board_BTC_JPY"),
-        ]
+    // ストリーミングAPIのエンドポイントを設定する(試験用の代替サーバー等に接続する場合)
+    pub fn set_end_point(&mut self, end_point: &str) {
+        self.end_point = end_point.to_string();
+    }
+
+    // 購読する銘柄
+    pub fn get_products(&self) -> Vec<Product> {
+        self.products.clone()
+    }
+
+    // 購読する銘柄を設定する
+    pub fn set_products(&mut self, products: Vec<Product>) {
+        self.products = products;
+    }
+
+    // 接続中に購読する銘柄を変更する(追加された銘柄を購読し、除かれた銘柄の購読を停止する)
+    pub fn update_products(&mut self, products: Vec<Product>) {
+        self.products = products.clone();
+        *self.product_updates.lock().unwrap() = Some(products);
+    }
+
+    // 板情報(差分)を購読するかを設定する(スナップショットは再購読の間隔毎に購読する)
+    pub fn set_boards(&mut self, boards: bool) {
+        self.boards = boards;
+    }
+
+    // ストリーミングAPIを利用して購読するチャンネル(スナップショットは定期的に購読するため含まない)
+    pub fn get_public_channels(&self) -> Vec<String> {
+        let mut channels = self.get_public_execute_channels();
+        channels.extend(self.get_public_board_channels());
+        channels
     }
 
     // ストリーミングAPIの約定履歴チャンネル
-    pub fn get_public_execute_channels(&self) -> [String; 2] {
-        [
-            String::from("lightning_executions_FX_BTC_JPY"),
-            String::from("lightning_executions_BTC_JPY"),
-        ]
+    pub fn get_public_execute_channels(&self) -> Vec<String> {
+        product_channels(&self.products, ChannelKind::Executions, true)
     }
 
     // ストリーミングAPIの板情報チャンネル
-    pub fn get_public_board_channels(&self) -> [String; 0] {
-        [
-            // String::from("lightning_board_FX_BTC_JPY"),
-            // String::from("lightning_board_BTC_JPY"),
-        ]
+    pub fn get_public_board_channels(&self) -> Vec<String> {
+        product_channels(&self.products, ChannelKind::Board, self.boards)
     }
 
     // ストリーミングAPIのスナップショットチャンネル
-    pub fn get_public_snapshot_channels(&self) -> [String; 0] {
+    pub fn get_public_snapshot_channels(&self) -> Vec<String> {
+        product_channels(&self.products, ChannelKind::BoardSnapshot, self.boards)
+    }
+
+    // ストリーミングAPIのプライベートチャンネル(要認証)
+    pub fn get_private_channels(&self) -> [String; 2] {
         [
-            // String::from("lightning_board_snapshot_FX_BTC_JPY"),
-            // String::from("lightning_board_snapshot_BTC_JPY"),
+            String::from(CHILD_ORDER_EVENTS),
+            String::from(PARENT_ORDER_EVENTS),
         ]
     }
 
     // ストリーミングAPIを利用して、チャンネルの購読を開始し、受信したメッセージを配信する
-    pub fn on_connect(&self) {
+    // 接続・認証・購読の要求に失敗した場合はエラーを返す
+    pub fn on_connect(&self) -> Result<(), ConnectError> {
         // 接続
-        let (mut socket, _) =
-            connect(Url::parse(&self.get_end_point()).unwrap()).expect("Can't connect");
+        let url = Url::parse(&self.get_end_point())
+            .map_err(|error| ConnectError::Socket(tungstenite::Error::Url(error.to_string().into())))?;
+        let (mut socket, _) = connect(url)?;
+
+        // 受信がなくてもスナップショットの購読や終了フラグを確認できるよう、読み込みにタイムアウトを設定する
+        // 認証のレスポンスを待つ間も止まらないよう、認証の前に設定する
+        if let Err(error) = set_read_timeout(&socket, Some(READ_TIMEOUT)) {
+            error!("on_connect: set_read_timeout. {}", error);
+        }
+
+        // 購読するチャンネル
+        let mut public_channels = self.get_public_channels();
+
+        // 認証情報がある場合は認証し、プライベートチャンネルも購読する
+        // 認証に失敗した場合は注文イベントを記録できないため、接続の失敗とする
+        if let Some(credentials) = &self.credentials {
+            if let Err(error) = authenticate(&mut socket, credentials, AUTH_TIMEOUT) {
+                error!(
+                    event = event::AUTH_FAILED, exchange = self.exchange_name.as_str(), error:% = error;
+                    "on_connect: Authentication failed. {}", error
+                );
+                return Err(ConnectError::Auth(error));
+            }
+            public_channels.extend(self.get_private_channels().iter().cloned());
+        }
 
         // チャンネルの購読を開始
-        for public_channel in self.get_public_channels().iter() {
-            let json = format!(
-        "{{\"jsonrpc\":\"2.0\",\"method\":\"subscribe\",\"params\":{{\"channel\":\"{}\"}}}}",
-        public_channel
-      );
-            socket.write_message(Message::Text(json)).unwrap();
-            info!("on_connect: Subscribe {}", public_channel);
+        let mut requests = RpcRequests::new();
+        for public_channel in public_channels.iter() {
+            let json = requests.request(RpcMethod::Subscribe, public_channel);
+            socket.write_message(Message::Text(json))?;
+            info!(
+                event = event::SUBSCRIBE, exchange = self.exchange_name.as_str(), channel = public_channel.as_str();
+                "on_connect: Subscribe {}", public_channel
+            );
         }
 
         let tx = mpsc::Sender::clone(&self.tx);
-        let public_channels = self.get_public_channels();
-        let public_execute_channels = self.get_public_execute_channels().clone();
-        let public_board_channels = self.get_public_board_channels().clone();
-        let public_snapshot_channels = self.get_public_snapshot_channels().clone();
+        let queue_depth = self.queue_depth.clone();
+        let mut public_snapshot_channels = self.get_public_snapshot_channels();
+        // 銘柄から導出した購読中のチャンネル(銘柄の変更時に差分を購読する)
+        let mut subscribed_product_channels = self.get_public_channels();
+        let product_updates = self.product_updates.clone();
+        let boards = self.boards;
+        // 接続前の変更は購読するチャンネルに反映済み
+        product_updates.lock().unwrap().take();
+        let mut parser = self.frame_parser();
+        let mut scheduler = SnapshotScheduler::new(
+            public_snapshot_channels.clone(),
+            self.snapshot_interval,
+            self.resync.clone(),
+        );
+        let resubscribe = self.resubscribe.clone();
         let finish = self.finish.clone();
+        let subscriptions = self.subscriptions.clone();
+        let raw_capture = self.raw_capture;
+        let exchange = self.exchange_name.clone();
 
         // 別スレッドを立ち上げて、メインスレッドに、受信したデータを配信する
         thread::spawn(move || {
 
-            // 前回の接続した日付
-            let mut last_connected_date = Utc::now();
+            // 接続内で受信したフレームのシーケンス番号
+            let mut sequence: u64 = 0;
+
+            // メインスレッドに配信し、未取得のメッセージの数を数える
+            let send = |market_info: MarketInfo| {
+                queue_depth.fetch_add(1, Ordering::Relaxed);
+                tx.send(market_info).unwrap();
+            };
+
             loop {
 
                 // チャンネルの購読を停止
                 if (*finish).load(Ordering::Relaxed) {
                     for public_channel in public_channels.iter() {
-                        let json = format!("{{\"jsonrpc\":\"2.0\",\"method\":\"unsubscribe\",\"params\":{{\"channel\":\"{}\"}}}}", public_channel);
+                        let json = requests.request(RpcMethod::Unsubscribe, public_channel);
                         if let Err(error) = socket.write_message(Message::Text(json)) {
-                            error!("on_connect.thread: Unsubscribe {}. {}", public_channel, error);
+                            error!(
+                                event = event::UNSUBSCRIBE_FAILED, exchange = exchange.as_str(), channel = public_channel.as_str(), error:% = error;
+                                "on_connect.thread: Unsubscribe {}. {}", public_channel, error
+                            );
                         } else {
-                            info!("on_connect.thread: Unsubscribe {}", public_channel);
+                            info!(
+                                event = event::UNSUBSCRIBE, exchange = exchange.as_str(), channel = public_channel.as_str();
+                                "on_connect.thread: Unsubscribe {}", public_channel
+                            );
                         }
                     }
+                    subscriptions.lock().unwrap().clear();
                     info!("on_connect.thread: thread Finish.");
                     break;
                 }
 
-                // 現在の日付を取得し、前回と日が異なる場合はスナップショットチャンネルに再接続する
-                let connect_time = Utc::now();
-                if last_connected_date.hour() != connect_time.hour() {
-                    for snapshot_channel in public_snapshot_channels.iter() {
-                        let json = format!(
-                    "{{\"jsonrpc\":\"2.0\",\"method\":\"subscribe\",\"params\":{{\"channel\":\"{}\"}}}}",
-                    snapshot_channel
-                  );
+                // 定期的に、または再同期を要求された場合にスナップショットチャンネルを購読する
+                for snapshot_channel in scheduler.due(Instant::now()) {
+                    let json = requests.request(RpcMethod::Subscribe, &snapshot_channel);
+                    if let Err(error) = socket.write_message(Message::Text(json)) {
+                        error!(
+                            event = event::SUBSCRIBE_FAILED, exchange = exchange.as_str(), channel = snapshot_channel.as_str(), error:% = error;
+                            "on_connect.thread: Subscribe {}. {}", snapshot_channel, error
+                        );
+                    } else {
+                        info!(
+                            event = event::SUBSCRIBE, exchange = exchange.as_str(), channel = snapshot_channel.as_str();
+                            "on_connect.thread: Subscribe {}", snapshot_channel
+                        );
+                    }
+                }
+
+                // 購読する銘柄が変わった場合は、差分のチャンネルを購読・購読停止する
+                let products = product_updates.lock().unwrap().take();
+                if let Some(products) = products {
+                    let execute_channels = product_channels(&products, ChannelKind::Executions, true);
+                    let board_channels = product_channels(&products, ChannelKind::Board, boards);
+                    public_snapshot_channels = product_channels(&products, ChannelKind::BoardSnapshot, boards);
+                    let mut channels = execute_channels.clone();
+                    channels.extend(board_channels.iter().cloned());
+
+                    for channel in subscribed_product_channels.iter().filter(|channel| !channels.contains(channel)) {
+                        let json = requests.request(RpcMethod::Unsubscribe, channel);
                         if let Err(error) = socket.write_message(Message::Text(json)) {
-                            error!("on_connect.thread: Subscribe {}. {}", snapshot_channel, error);
-                            continue;
+                            error!(
+                                event = event::UNSUBSCRIBE_FAILED, exchange = exchange.as_str(), channel = channel.as_str(), error:% = error;
+                                "on_connect.thread: Unsubscribe {}. {}", channel, error
+                            );
+                        } else {
+                            info!(
+                                event = event::UNSUBSCRIBE, exchange = exchange.as_str(), channel = channel.as_str();
+                                "on_connect.thread: Unsubscribe {}", channel
+                            );
+                        }
+                    }
+                    for channel in channels.iter().filter(|channel| !subscribed_product_channels.contains(channel)) {
+                        let json = requests.request(RpcMethod::Subscribe, channel);
+                        if let Err(error) = socket.write_message(Message::Text(json)) {
+                            error!(
+                                event = event::SUBSCRIBE_FAILED, exchange = exchange.as_str(), channel = channel.as_str(), error:% = error;
+                                "on_connect.thread: Subscribe {}. {}", channel, error
+                            );
                         } else {
-                            info!("on_connect.thread: Subscribe {}", snapshot_channel);
+                            info!(
+                                event = event::SUBSCRIBE, exchange = exchange.as_str(), channel = channel.as_str();
+                                "on_connect.thread: Subscribe {}", channel
+                            );
+                        }
+                    }
+
+                    public_channels.retain(|channel| !subscribed_product_channels.contains(channel));
+                    public_channels.extend(channels.iter().cloned());
+                    subscribed_product_channels = channels;
+                    parser.set_channels(execute_channels, board_channels, public_snapshot_channels.clone());
+                    scheduler.set_channels(public_snapshot_channels.clone());
+                }
+
+                // 受信が途絶えたチャンネルを購読し直す
+                for channel in resubscribe.take() {
+                    if !public_channels.contains(&channel) {
+                        continue;
+                    }
+                    for method in [RpcMethod::Unsubscribe, RpcMethod::Subscribe] {
+                        let json = requests.request(method, &channel);
+                        if let Err(error) = socket.write_message(Message::Text(json)) {
+                            error!(
+                                event = event::SUBSCRIBE_FAILED, exchange = exchange.as_str(), channel = channel.as_str(), error:% = error;
+                                "on_connect.thread: Resubscribe {}. {}", channel, error
+                            );
                         }
-                        last_connected_date = connect_time;
                     }
+                    info!(
+                        event = event::RESUBSCRIBE, exchange = exchange.as_str(), channel = channel.as_str();
+                        "on_connect.thread: Resubscribe {}", channel
+                    );
                 }
 
                 // 接続等でエラーが発生した場合は終了する
                 let socket_read_message = socket.read_message();
+                // 受信時間(読み込み直後に取得する)
+                let receive_time = ReceiveTime::now();
+                // 読み込みのタイムアウトの場合は、スケジュールと終了フラグを確認して再度読み込む
+                if let Err(tungstenite::Error::Io(error)) = &socket_read_message {
+                    if error.kind() == io::ErrorKind::WouldBlock || error.kind() == io::ErrorKind::TimedOut {
+                        continue;
+                    }
+                }
                 if let Err(error) = socket_read_message {
                     (*finish).store(true, Ordering::Relaxed);
-                    error!("on_connect.thread: True the exit flag. {}", error);
+                    // Closeフレームを受信せずに切断された場合も、受信終了を配信する
+                    send(MarketInfo::Close);
+                    error!(
+                        event = event::DISCONNECT, exchange = exchange.as_str(), error:% = error;
+                        "on_connect.thread: True the exit flag. {}", error
+                    );
                     continue;
                 }
                 let socket_read_message = socket_read_message.unwrap();
+                sequence += 1;
                 match socket_read_message {
                     Message::Text(text) => {
-                        // 受信時間
-                        let receive_time = Utc::now();
-
-                        let v: Value = from_str(&text).unwrap();
-
-                        let channel = v["params"]["channel"].as_str().unwrap().to_string();
-
-                        // 受信データが約定履歴の場合、
-                        if 0 < public_execute_channels
-                            .iter()
-                            .filter(|&x| x == &channel)
-                            .count()
-                        {
-
-                            // 約定履歴データの一番古い日時を代入する用
-                            let mut exec_ts_millis = 5_000_000_000_000;
-
-                            let mut executes = Vec::new();
-                            // 約定データを配信する
-                            for i in 0..v["params"]["message"].as_array().unwrap().len() {
-                                let exec_date = v["params"]["message"][i]["exec_date"]
-                                    .as_str()
-                                    .unwrap()
-                                    .parse::<DateTime<Utc>>()
-                                    .unwrap();
-                                let execute = Execution {
-                                    exec_date: exec_date,
-                                    exec_unix_time: exec_date.timestamp(),
-                                    side: Side::from_str(
-                                        v["params"]["message"][i]["side"].as_str().unwrap(),
-                                    ),
-                                    price: v["params"]["message"][i]["price"].as_f64().unwrap(),
-                                    size: v["params"]["message"][i]["size"].as_f64().unwrap(),
-                                    channel: channel.clone(),
-                                };
-                                exec_ts_millis = std::cmp::min(exec_ts_millis, exec_date.timestamp_millis());
-                                executes.push(execute);
-                            }
-                            executes.sort_by(|a, b| a.exec_unix_time.cmp(&b.exec_unix_time)); // 日付を古い順でソートする
-                            for i in 0..executes.len() {
-                                tx.send(MarketInfo::Executions(executes[i].clone())).unwrap();
-                            }
+                        // 受信したフレームをそのまま配信する
+                        if raw_capture {
+                            let frame = RawFrame::new(&text, receive_time, sequence);
+                            send(MarketInfo::RawFrames(frame));
+                        }
 
-                            // 遅延データを配信する
-                            let latency = Latency {
-                                sender_time: receive_time.timestamp_millis() - exec_ts_millis,
-                                receive_time: receive_time,
-                                channel: channel.clone(),
-                            };
-                            tx.send(MarketInfo::LatencyExchange(latency)).unwrap();
-
-                        // 受信データが板情報の差分の場合、
-                        } else if 0 < public_board_channels
-                            .iter()
-                            .filter(|&x| x == &channel)
-                            .count()
-                        {
-                            let mut asks = Vec::new();
-                            let mut bids = Vec::new();
-                            for i in 0..v["params"]["message"]["asks"].as_array().unwrap().len() {
-                                let price = v["params"]["message"]["asks"][i]["price"].as_f64().unwrap();
-                                let size = v["params"]["message"]["asks"][i]["size"].as_f64().unwrap();
-                                let v = (price, size);
-                                asks.push(v);
+                        let v: Value = match from_str(&text) {
+                            Ok(v) => v,
+                            Err(error) => {
+                                warn!("on_connect.thread: Invalid JSON. {} {}", error, text);
+                                continue;
                             }
+                        };
 
-                            for i in 0..v["params"]["message"]["bids"].as_array().unwrap().len() {
-                                let price = v["params"]["message"]["bids"][i]["price"].as_f64().unwrap();
-                                let size = v["params"]["message"]["bids"][i]["size"].as_f64().unwrap();
-                                let v = (price, size);
-                                bids.push(v);
-                            }
-                            // 板データの差分を配信する
-                            let board = Board {
-                                receive_time: receive_time,
-                                asks,
-                                bids,
-                                channel: channel.clone(),
-                                is_update: true,
-                            };
-                            tx.send(MarketInfo::Boards(board)).unwrap();
-
-                        // 受信データがスナップショットの場合、
-                        } else if 0 < public_snapshot_channels
-                            .iter()
-                            .filter(|&x| x == &channel)
-                            .count()
-                        {
-                            // スナップショットの購読を停止する
-                            let json = format!(
-            "{{\"jsonrpc\":\"2.0\",\"method\":\"unsubscribe\",\"params\":{{\"channel\":\"{}\"}}}}",
-            channel
-          );
-                            if let Err(error) = socket.write_message(Message::Text(json)) {
-                                error!("on_connect.thread: Unsubscribe {}. {}", channel, error);
-                            } else {
-                                info!("on_connect.thread: Unsubscribe {}", channel);
+                        // リクエストに対するレスポンスの場合、
+                        if v.get("method").is_none() && v.get("id").is_some() {
+                            if let Some(error) = requests.on_response(&v, &subscriptions) {
+                                warn!(exchange = exchange.as_str(), error:% = error; "on_connect.thread: {}", error);
+                                send(MarketInfo::Error(error));
                             }
+                            continue;
+                        }
 
-                            let mut asks = Vec::new();
-                            let mut bids = Vec::new();
-                            for i in 0..v["params"]["message"]["asks"].as_array().unwrap().len() {
-                                let price = v["params"]["message"]["asks"][i]["price"].as_f64().unwrap();
-                                let size = v["params"]["message"]["asks"][i]["size"].as_f64().unwrap();
-                                let v = (price, size);
-                                asks.push(v);
+                        let channel = match v["params"]["channel"].as_str() {
+                            Some(channel) => channel.to_string(),
+                            None => {
+                                warn!("on_connect.thread: Unknown message. {}", text);
+                                continue;
                             }
+                        };
 
-                            for i in 0..v["params"]["message"]["bids"].as_array().unwrap().len() {
-                                let price = v["params"]["message"]["bids"][i]["price"].as_f64().unwrap();
-                                let size = v["params"]["message"]["bids"][i]["size"].as_f64().unwrap();
-                                let v = (price, size);
-                                bids.push(v);
+                        // 受信データがスナップショットの場合、スナップショットの購読を停止する
+                        if public_snapshot_channels.contains(&channel) {
+                            let json = requests.request(RpcMethod::Unsubscribe, &channel);
+                            if let Err(error) = socket.write_message(Message::Text(json)) {
+                                error!(
+                                    event = event::UNSUBSCRIBE_FAILED, exchange = exchange.as_str(), channel = channel.as_str(), error:% = error;
+                                    "on_connect.thread: Unsubscribe {}. {}", channel, error
+                                );
+                            } else {
+                                info!(
+                                    event = event::UNSUBSCRIBE, exchange = exchange.as_str(), channel = channel.as_str();
+                                    "on_connect.thread: Unsubscribe {}", channel
+                                );
                             }
+                        }
 
-                            // 板データのスナップショットを配信する
-                            let board = Board {
-                                receive_time: receive_time,
-                                asks,
-                                bids,
-                                channel: channel.replace("_snapshot", ""),
-                                is_update: false,
-                            };
-                            tx.send(MarketInfo::Boards(board)).unwrap();
-                        } else {
-                            continue;
+                        // 受信データをマーケット情報に変換して配信する
+                        for market_info in parser.parse_channel_message(
+                            &channel,
+                            &v["params"]["message"],
+                            receive_time,
+                            sequence,
+                        ) {
+                            send(market_info);
                         }
                     }
                     Message::Ping(data) => {
@@ -448,19 +1306,32 @@ impl BfWebsocket {
                     }
                     Message::Close(_) => {
                         (*finish).store(true, Ordering::Relaxed);
-                        tx.send(MarketInfo::Close).unwrap();
-                        warn!("on_connect.thread: True the exit flag. Received Close Message.");
+                        send(MarketInfo::Close);
+                        warn!(
+                            event = event::DISCONNECT, exchange = exchange.as_str();
+                            "on_connect.thread: True the exit flag. Received Close Message."
+                        );
                         continue;
                     }
                     _ => continue,
                 }
             }
         });
+        Ok(())
     }
 
     // 別スレッドからのメッセージを受け取る
     pub fn on_message(&self) -> Result<MarketInfo, mpsc::TryRecvError> {
-        self.rx.try_recv()
+        let message = self.rx.try_recv();
+        if message.is_ok() {
+            self.queue_depth.fetch_sub(1, Ordering::Relaxed);
+        }
+        message
+    }
+
+    // 別スレッドから配信済みで未取得のメッセージの数
+    pub fn queue_depth(&self) -> usize {
+        self.queue_depth.load(Ordering::Relaxed)
     }
 
     // メッセージ受信用のスレッドを停止する
@@ -0,0 +1,274 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use chrono::NaiveDate;
+
+use crate::convert::{ExecutionRow, LatencyRow};
+use crate::recorder::Recorder;
+use crate::rotation::segment_files;
+use crate::stream_api::Side;
+
+// 日付(年月日)を解析する(20201001/2020-10-01)
+pub fn parse_date(s: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(s, "%Y%m%d")
+        .or_else(|_| NaiveDate::parse_from_str(s, "%Y-%m-%d"))
+        .map_err(|error| format!("invalid date: {} {}", s, error))
+}
+
+// 検証するファイルの種類
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DataKind {
+    // 約定データ[約定日時(秒) 売買種別 価格 数量 受信時刻(ナノ秒) 単調時計の受信時刻(ナノ秒) 受信シーケンス番号 約定ID]
+    // (以前の形式の4・6・7項目の行も含む)
+    Executions,
+    // 遅延データ[受信時刻(ナノ秒) 遅延(ミリ秒) 単調時計の受信時刻(ナノ秒) 受信シーケンス番号]
+    // (以前の形式の2・3項目の行も含む)
+    Latency,
+}
+
+impl DataKind {
+    // ファイル名の接頭辞
+    pub fn prefix(&self) -> &'static str {
+        match self {
+            DataKind::Executions => "",
+            DataKind::Latency => "latency_",
+        }
+    }
+
+    // ファイル名から種類を推定する(latency_で始まる場合は遅延データ)
+    pub fn from_path(path: &Path) -> Self {
+        let latency = path
+            .file_name()
+            .and_then(|file_name| file_name.to_str())
+            .is_some_and(|file_name| file_name.starts_with(DataKind::Latency.prefix()));
+        if latency {
+            DataKind::Latency
+        } else {
+            DataKind::Executions
+        }
+    }
+}
+
+impl FromStr for DataKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "executions" => Ok(DataKind::Executions),
+            "latency" => Ok(DataKind::Latency),
+            _ => Err(format!("unknown data kind: {} (executions/latency)", s)),
+        }
+    }
+}
+
+// 検出した異常の種類
+#[derive(Clone, Debug, PartialEq)]
+pub enum AnomalyKind {
+    // 項目の数や値が不正な行
+    Malformed,
+    // 同じ約定IDの行(最初に出現したファイルと行番号)
+    Duplicate { path: PathBuf, line: usize },
+    // 約定IDのない以前の形式で、同じ内容の行(同じ秒に同じ内容の約定がありうるため修復では除かない)
+    PossibleDuplicate { path: PathBuf, line: usize },
+    // 直前の行より時刻が古い
+    OutOfOrder,
+    // 数量が0以下
+    ZeroSize,
+    // 売買種別が不明(N)
+    UnknownSide,
+}
+
+// 検出した異常
+#[derive(Clone, Debug, PartialEq)]
+pub struct Anomaly {
+    pub path: PathBuf,
+    // 1から始まる行番号
+    pub line: usize,
+    pub kind: AnomalyKind,
+}
+
+impl fmt::Display for Anomaly {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: ", self.path.display(), self.line)?;
+        match &self.kind {
+            AnomalyKind::Malformed => write!(f, "malformed line"),
+            AnomalyKind::Duplicate { path, line } => write!(f, "duplicate of {}:{}", path.display(), line),
+            AnomalyKind::PossibleDuplicate { path, line } => {
+                write!(f, "possible duplicate of {}:{}", path.display(), line)
+            }
+            AnomalyKind::OutOfOrder => write!(f, "timestamp out of order"),
+            AnomalyKind::ZeroSize => write!(f, "zero size"),
+            AnomalyKind::UnknownSide => write!(f, "unknown side"),
+        }
+    }
+}
+
+// 1行を解析した結果
+struct Record {
+    // 並び替えに用いる時刻
+    time: i64,
+    // 重複の確認に用いる値(約定IDがあれば約定ID、なければ行の内容)
+    // 遅延データは同じフレームの約定毎に同じ内容の行となるため、重複を確認しない
+    key: Option<DuplicateKey>,
+    size: Option<f64>,
+    side: Option<Side>,
+}
+
+// 重複の確認に用いる値
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum DuplicateKey {
+    Id(u64),
+    // 約定IDのない行の内容
+    Line(String),
+}
+
+// 変換と同じく、約定IDや受信時刻のない以前の形式の行も読み込む
+fn parse_line(kind: DataKind, line: &str) -> Option<Record> {
+    match kind {
+        DataKind::Executions => {
+            let row = ExecutionRow::parse(line)?;
+            let key = match row.id {
+                Some(id) => DuplicateKey::Id(id),
+                None => DuplicateKey::Line(line.to_string()),
+            };
+            Some(Record {
+                time: row.exec_time,
+                key: Some(key),
+                size: Some(row.size),
+                side: Some(row.side),
+            })
+        }
+        DataKind::Latency => {
+            let row = LatencyRow::parse(line)?;
+            Some(Record {
+                time: row.receive_time,
+                key: None,
+                size: None,
+                side: None,
+            })
+        }
+    }
+}
+
+// 検証結果
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Validation {
+    pub lines: usize,
+    pub anomalies: Vec<Anomaly>,
+    // 不正な行・約定IDが重複した行・数量が0以下の行を除き、時刻の順に並び替えた行
+    pub repaired: Vec<String>,
+}
+
+// 1日分のファイル(分割したファイルを含む)を順に検証する
+pub fn validate_files(kind: DataKind, files: &[PathBuf]) -> io::Result<Validation> {
+    let mut validation = Validation::default();
+    let mut seen: HashMap<DuplicateKey, (PathBuf, usize)> = HashMap::new();
+    let mut last_time: Option<i64> = None;
+    let mut kept: Vec<(i64, String)> = Vec::new();
+
+    for path in files {
+        for (index, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+            let line = line?;
+            let line_number = index + 1;
+            validation.lines += 1;
+            let mut anomaly = |kind: AnomalyKind| {
+                validation.anomalies.push(Anomaly {
+                    path: path.clone(),
+                    line: line_number,
+                    kind,
+                })
+            };
+
+            let mut record = match parse_line(kind, &line) {
+                Some(record) => record,
+                None => {
+                    anomaly(AnomalyKind::Malformed);
+                    continue;
+                }
+            };
+            if let Some(key) = record.key.take() {
+                match (seen.get(&key), &key) {
+                    (Some((path, line)), DuplicateKey::Id(_)) => {
+                        anomaly(AnomalyKind::Duplicate {
+                            path: path.clone(),
+                            line: *line,
+                        });
+                        continue;
+                    }
+                    (Some((path, line)), DuplicateKey::Line(_)) => anomaly(AnomalyKind::PossibleDuplicate {
+                        path: path.clone(),
+                        line: *line,
+                    }),
+                    (None, _) => {
+                        seen.insert(key, (path.clone(), line_number));
+                    }
+                }
+            }
+
+            if last_time.is_some_and(|last_time| record.time < last_time) {
+                anomaly(AnomalyKind::OutOfOrder);
+            }
+            last_time = Some(last_time.map_or(record.time, |last_time| last_time.max(record.time)));
+            if record.side == Some(Side::NoSide) {
+                anomaly(AnomalyKind::UnknownSide);
+            }
+            if record.size.is_some_and(|size| size <= 0.0) {
+                anomaly(AnomalyKind::ZeroSize);
+                continue;
+            }
+            kept.push((record.time, line));
+        }
+    }
+
+    // 同じ時刻の行は元の順序を保つ
+    kept.sort_by_key(|(time, _)| *time);
+    validation.repaired = kept.into_iter().map(|(_, line)| line).collect();
+    Ok(validation)
+}
+
+// 日付の範囲の約定データ・遅延データを検証し、検出した異常を返す
+// repair_dirを指定した場合は修復したファイルを出力先のディレクトリと同じ構成で書き込む
+pub fn validate_range(
+    recorder: &Recorder,
+    channels: &[String],
+    from: NaiveDate,
+    to: NaiveDate,
+    repair_dir: Option<&Path>,
+) -> io::Result<Vec<Anomaly>> {
+    let mut anomalies = Vec::new();
+    let mut date = from;
+    while date <= to {
+        for channel in channels {
+            for kind in [DataKind::Executions, DataKind::Latency] {
+                let path = recorder.channel_path(date, kind.prefix(), channel);
+                let files = segment_files(&path)?;
+                if files.is_empty() {
+                    continue;
+                }
+                let validation = validate_files(kind, &files)?;
+                if let Some(repair_dir) = repair_dir {
+                    let relative = path.strip_prefix(recorder.get_output_dir()).unwrap_or(&path);
+                    write_lines(&repair_dir.join(relative), &validation.repaired)?;
+                }
+                anomalies.extend(validation.anomalies);
+            }
+        }
+        date = date.succ_opt().unwrap();
+    }
+    Ok(anomalies)
+}
+
+fn write_lines(path: &Path, lines: &[String]) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut file = io::BufWriter::new(File::create(path)?);
+    for line in lines {
+        writeln!(file, "{}", line)?;
+    }
+    file.flush()
+}
@@ -0,0 +1,407 @@
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fs;
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use tungstenite::{accept, Message, WebSocket};
+
+use serde_json::{from_str, json, Value};
+
+use log::{info, warn};
+
+// ローカルで起動するbitFlyerのストリーミングAPIの代替サーバー
+// JSON-RPCのリクエスト(auth/subscribe/unsubscribe)に応答し、スクリプトに従ってメッセージを配信する
+// 接続毎にスクリプトを1つずつ順に実行するため、再接続時の挙動も試験できる
+
+// スクリプトの1手順
+#[derive(Clone, Debug, PartialEq)]
+pub enum ScriptStep {
+    // チャンネルが購読されるまでリクエストを処理して待つ
+    AwaitSubscribe(String),
+    // チャンネルに対するリクエストを受信するまで待つ(購読を拒否する場合等)
+    AwaitRequest { method: String, channel: String },
+    // チャンネルのメッセージ(約定履歴・板情報・スナップショット等)を配信する
+    ChannelMessage { channel: String, message: Value },
+    // 任意の文字列をそのまま配信する
+    Raw(String),
+    // Pingを送信する
+    Ping,
+    // 指定した時間待つ
+    Sleep(Duration),
+    // 接続を終了する
+    Close,
+    // Closeフレームを送らずにTCP接続を切断する
+    Drop,
+}
+
+// スクリプトの読み込みエラー
+#[derive(Debug)]
+pub struct ScriptError(String);
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid script: {}", self.0)
+    }
+}
+
+impl std::error::Error for ScriptError {}
+
+// 1接続分のスクリプト
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Script {
+    pub steps: Vec<ScriptStep>,
+}
+
+impl Script {
+    pub fn new(steps: Vec<ScriptStep>) -> Self {
+        Script { steps }
+    }
+
+    // 1行に1手順をJSONで記述したスクリプトを解析する
+    // {"action": "await_subscribe", "channel": "..."}
+    // {"action": "await_request", "method": "subscribe", "channel": "..."}
+    // {"action": "channel_message", "channel": "...", "message": ...}
+    // {"action": "raw", "text": "..."}
+    // {"action": "ping"}
+    // {"action": "sleep", "millis": 100}
+    // {"action": "close"}
+    // {"action": "drop"}
+    // 空行と#から始まる行は無視する
+    pub fn parse(text: &str) -> Result<Self, ScriptError> {
+        let mut steps = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let v: Value =
+                from_str(line).map_err(|error| ScriptError(format!("line {}: {}", i + 1, error)))?;
+            let channel = || {
+                v["channel"]
+                    .as_str()
+                    .map(String::from)
+                    .ok_or_else(|| ScriptError(format!("line {}: channel is required", i + 1)))
+            };
+            let step = match v["action"].as_str() {
+                Some("await_subscribe") => ScriptStep::AwaitSubscribe(channel()?),
+                Some("await_request") => ScriptStep::AwaitRequest {
+                    method: v["method"].as_str().unwrap_or("subscribe").to_string(),
+                    channel: channel()?,
+                },
+                Some("channel_message") => ScriptStep::ChannelMessage {
+                    channel: channel()?,
+                    message: v["message"].clone(),
+                },
+                Some("raw") => ScriptStep::Raw(
+                    v["text"]
+                        .as_str()
+                        .ok_or_else(|| ScriptError(format!("line {}: text is required", i + 1)))?
+                        .to_string(),
+                ),
+                Some("ping") => ScriptStep::Ping,
+                Some("sleep") => {
+                    ScriptStep::Sleep(Duration::from_millis(v["millis"].as_u64().unwrap_or(0)))
+                }
+                Some("close") => ScriptStep::Close,
+                Some("drop") => ScriptStep::Drop,
+                action => {
+                    return Err(ScriptError(format!(
+                        "line {}: unknown action {:?}",
+                        i + 1,
+                        action
+                    )))
+                }
+            };
+            steps.push(step);
+        }
+        Ok(Script { steps })
+    }
+
+    // スクリプトファイルを読み込む
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, ScriptError> {
+        let text = fs::read_to_string(path.as_ref())
+            .map_err(|error| ScriptError(format!("{}: {}", path.as_ref().display(), error)))?;
+        Script::parse(&text)
+    }
+}
+
+// 代替サーバーの状態
+#[derive(Default)]
+struct MockState {
+    // 受け付けた接続数
+    connections: usize,
+    // 受信したリクエスト
+    requests: Vec<Value>,
+    // 最後に購読を変更した接続の購読中のチャンネル
+    subscriptions: BTreeSet<String>,
+    // 購読を拒否するチャンネル
+    rejected_channels: BTreeSet<String>,
+}
+
+// 代替サーバー
+pub struct MockServer {
+    addr: SocketAddr,
+    state: Arc<Mutex<MockState>>,
+}
+
+impl MockServer {
+    // 接続毎のスクリプトを指定してサーバーを起動する
+    pub fn start(scripts: Vec<Script>) -> Self {
+        MockServer::start_with_rejected_channels(scripts, &[])
+    }
+
+    // 購読を拒否するチャンネルを指定してサーバーを起動する
+    pub fn start_with_rejected_channels(scripts: Vec<Script>, rejected_channels: &[&str]) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("Can't bind");
+        let addr = listener.local_addr().unwrap();
+        let state = Arc::new(Mutex::new(MockState {
+            rejected_channels: rejected_channels.iter().map(|c| c.to_string()).collect(),
+            ..MockState::default()
+        }));
+
+        let server_state = state.clone();
+        thread::spawn(move || {
+            let mut scripts = scripts.into_iter();
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(error) => {
+                        warn!("MockServer: {}", error);
+                        continue;
+                    }
+                };
+                let script = scripts.next().unwrap_or_default();
+                let state = server_state.clone();
+                thread::spawn(move || {
+                    {
+                        // 新しい接続では購読中のチャンネルを空にする
+                        let mut state = state.lock().unwrap();
+                        state.connections += 1;
+                        state.subscriptions.clear();
+                    }
+                    if let Err(error) = serve(stream, script, &state) {
+                        info!("MockServer: connection finished. {}", error);
+                    }
+                });
+            }
+        });
+
+        MockServer { addr, state }
+    }
+
+    // ストリーミングAPIのエンドポイント
+    pub fn end_point(&self) -> String {
+        format!("ws://{}", self.addr)
+    }
+
+    // 受け付けた接続数
+    pub fn connections(&self) -> usize {
+        self.state.lock().unwrap().connections
+    }
+
+    // 受信したリクエスト
+    pub fn requests(&self) -> Vec<Value> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    // 購読中のチャンネル
+    pub fn subscriptions(&self) -> Vec<String> {
+        self.state.lock().unwrap().subscriptions.iter().cloned().collect()
+    }
+}
+
+// 1接続分のスクリプトを実行し、終了後はクライアントが切断するまでリクエストに応答する
+fn serve(stream: TcpStream, script: Script, state: &Mutex<MockState>) -> tungstenite::Result<()> {
+    let mut socket = accept(stream).map_err(|error| match error {
+        tungstenite::HandshakeError::Failure(error) => error,
+        tungstenite::HandshakeError::Interrupted(_) => tungstenite::Error::ConnectionClosed,
+    })?;
+
+    // この接続で受信したリクエストの開始位置
+    let first_request = state.lock().unwrap().requests.len();
+    // この接続で購読中のチャンネル(同時に接続した他の接続の影響を受けないよう接続毎に管理する)
+    let mut subscriptions = BTreeSet::new();
+
+    for step in script.steps {
+        match step {
+            ScriptStep::AwaitSubscribe(channel) => {
+                while !subscriptions.contains(&channel) {
+                    read_request(&mut socket, state, &mut subscriptions)?;
+                }
+            }
+            ScriptStep::AwaitRequest { method, channel } => {
+                let received = |state: &MockState| {
+                    state.requests[first_request..].iter().any(|request| {
+                        request["method"] == method.as_str() && request["params"]["channel"] == channel.as_str()
+                    })
+                };
+                while !received(&state.lock().unwrap()) {
+                    read_request(&mut socket, state, &mut subscriptions)?;
+                }
+            }
+            ScriptStep::ChannelMessage { channel, message } => {
+                let json = json!({
+                    "jsonrpc": "2.0",
+                    "method": "channelMessage",
+                    "params": {"channel": channel, "message": message},
+                });
+                socket.write_message(Message::Text(json.to_string()))?;
+            }
+            ScriptStep::Raw(text) => socket.write_message(Message::Text(text))?,
+            ScriptStep::Ping => socket.write_message(Message::Ping(Vec::new()))?,
+            ScriptStep::Sleep(duration) => thread::sleep(duration),
+            ScriptStep::Close => {
+                socket.close(None)?;
+                // クライアントからの終了の応答を待つ
+                while socket.read_message().is_ok() {}
+                return Ok(());
+            }
+            ScriptStep::Drop => {
+                socket.get_ref().shutdown(Shutdown::Both)?;
+                return Ok(());
+            }
+        }
+    }
+
+    loop {
+        read_request(&mut socket, state, &mut subscriptions)?;
+    }
+}
+
+// リクエストを1件受信して応答する
+fn read_request<S: std::io::Read + std::io::Write>(
+    socket: &mut WebSocket<S>,
+    state: &Mutex<MockState>,
+    subscriptions: &mut BTreeSet<String>,
+) -> tungstenite::Result<()> {
+    let text = match socket.read_message()? {
+        Message::Text(text) => text,
+        _ => return Ok(()),
+    };
+    let v: Value = match from_str(&text) {
+        Ok(v) => v,
+        Err(_) => return Ok(()),
+    };
+
+    let channel = v["params"]["channel"].as_str().unwrap_or("").to_string();
+    let response = {
+        let mut state = state.lock().unwrap();
+        state.requests.push(v.clone());
+        match v["method"].as_str() {
+            Some("subscribe") if state.rejected_channels.contains(&channel) => Some(json!({
+                "jsonrpc": "2.0",
+                "id": v["id"],
+                "error": {"code": -32602, "message": format!("Invalid channel: {}", channel)},
+            })),
+            Some("subscribe") => {
+                subscriptions.insert(channel);
+                state.subscriptions = subscriptions.clone();
+                Some(json!({"jsonrpc": "2.0", "id": v["id"], "result": true}))
+            }
+            Some("unsubscribe") => {
+                subscriptions.remove(&channel);
+                state.subscriptions = subscriptions.clone();
+                Some(json!({"jsonrpc": "2.0", "id": v["id"], "result": true}))
+            }
+            Some("auth") => Some(json!({"jsonrpc": "2.0", "id": v["id"], "result": true})),
+            _ => None,
+        }
+    };
+
+    // idのないリクエストには応答しない
+    match response {
+        Some(response) if !v["id"].is_null() => {
+            socket.write_message(Message::Text(response.to_string()))
+        }
+        _ => Ok(()),
+    }
+}
+
+// ローカルで起動するbitFlyerのHTTP APIの代替サーバー
+// パスに関わらず、設定したJSONを返す(マーケットの一覧の取得等の試験用)
+pub struct MockRestServer {
+    addr: SocketAddr,
+    state: Arc<Mutex<MockRestState>>,
+}
+
+struct MockRestState {
+    response: Value,
+    // 受信したリクエストのパス
+    paths: Vec<String>,
+}
+
+impl MockRestServer {
+    pub fn start(response: Value) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("Can't bind");
+        let addr = listener.local_addr().unwrap();
+        let state = Arc::new(Mutex::new(MockRestState {
+            response,
+            paths: Vec::new(),
+        }));
+
+        let server_state = state.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(stream) => {
+                        if let Err(error) = respond_rest(stream, &server_state) {
+                            warn!("MockRestServer: {}", error);
+                        }
+                    }
+                    Err(error) => warn!("MockRestServer: {}", error),
+                }
+            }
+        });
+
+        MockRestServer { addr, state }
+    }
+
+    // HTTP APIのエンドポイント
+    pub fn end_point(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    // 返すJSONを置き換える
+    pub fn set_response(&self, response: Value) {
+        self.state.lock().unwrap().response = response;
+    }
+
+    // 受信したリクエストのパス
+    pub fn paths(&self) -> Vec<String> {
+        self.state.lock().unwrap().paths.clone()
+    }
+}
+
+// リクエストのヘッダーまで読み込み、設定したJSONを返す
+fn respond_rest(stream: TcpStream, state: &Mutex<MockRestState>) -> std::io::Result<()> {
+    use std::io::{BufRead, BufReader, Write};
+
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 || line == "\r\n" {
+            break;
+        }
+    }
+
+    let body = {
+        let mut state = state.lock().unwrap();
+        let path = request_line.split_whitespace().nth(1).unwrap_or("").to_string();
+        state.paths.push(path);
+        state.response.to_string()
+    };
+    let mut stream = reader.into_inner();
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
@@ -0,0 +1,5 @@
+// 結合試験で共有する補助(試験毎に使う補助が異なるため、未使用の警告は抑制する)
+#![allow(dead_code)]
+
+pub mod mock_server;
+pub mod temp_dir;
@@ -0,0 +1,40 @@
+use std::env;
+use std::fs;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::process;
+
+// 試験用の一時ディレクトリ(破棄時に中身ごと削除する)
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    // 名前とプロセスIDから一時ディレクトリを作成する(前回の試験で残ったディレクトリは作り直す)
+    pub fn new(name: &str) -> Self {
+        let path = env::temp_dir().join(format!("{}_{}", name, process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TempDir { path }
+    }
+}
+
+impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl AsRef<Path> for TempDir {
+    fn as_ref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
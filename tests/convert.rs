@@ -0,0 +1,132 @@
+mod common;
+
+use std::fs;
+
+use arrow_schema::{DataType, TimeUnit};
+
+use fetch_market_and_order_data::convert::{convert, read_table, schema, ExecutionRow, Format, LatencyRow, Table};
+use fetch_market_and_order_data::stream_api::Side;
+use fetch_market_and_order_data::validate::DataKind;
+
+use common::temp_dir::TempDir;
+
+const EXECUTIONS: &str = "1601510400 B 1234567.5 0.01 1601510400123456789 98765 1 2000000001
+1601510401 S 1234568 0.25 1601510401000000001 98766 2 2000000002
+1601510402 N 1234569 0.1
+";
+
+const LATENCY: &str = "1601510400123456789 12.500 98765 1
+1601510400123456789 0.250 98766
+";
+
+#[test]
+fn parses_each_recorded_format() {
+    let row = ExecutionRow::parse("1601510400 S 100 0.5 1601510400123456789 98765 7 42").unwrap();
+    assert_eq!(row.side, Side::Sell);
+    assert_eq!(row.receive_time, Some(1601510400123456789));
+    assert_eq!(row.id, Some(42));
+    let row = ExecutionRow::parse("1601510400 B 100 0.5").unwrap();
+    assert_eq!((row.receive_time, row.sequence, row.id), (None, None, None));
+    assert_eq!(row.to_line(), "1601510400 B 100 0.5");
+    assert!(ExecutionRow::parse("1601510400 X 100 0.5").is_none());
+
+    // 受信時刻(ミリ秒)と遅延の2項目の形式は受信時刻をナノ秒に揃える
+    let row = LatencyRow::parse("1601510400123 15").unwrap();
+    assert_eq!(row.receive_time, 1601510400123000000);
+    assert_eq!(row.latency_ms, 15.0);
+    // 書き込む場合は2項目の形式(ミリ秒)に戻す
+    assert_eq!(row.to_line(), "1601510400123 15.000");
+    assert_eq!(LatencyRow::parse(&row.to_line()), Some(row));
+
+    // ミリ秒で表せない受信時刻で単調時計の受信時刻がない場合は-を書き込む
+    let row = LatencyRow {
+        receive_time: 1601510400123456789,
+        latency_ms: 15.0,
+        monotonic_nanos: None,
+        sequence: Some(3),
+    };
+    assert_eq!(row.to_line(), "1601510400123456789 15.000 - 3");
+    assert_eq!(LatencyRow::parse(&row.to_line()), Some(row));
+}
+
+#[test]
+fn round_trips_old_latency_format_through_space_output() {
+    let dir = TempDir::new("convert_old_latency");
+    let old = "1601510400123 12.500\n1601510401000 0.250\n";
+    let source = dir.join("latency_lightning_executions_FX_BTC_JPY.csv");
+    fs::write(&source, old).unwrap();
+
+    // 同じ形式への変換、他の形式を経由した変換のいずれでも受信時刻を保つ
+    let space = dir.join("space.txt");
+    assert_eq!(convert(&source, None, &space, Format::Space, DataKind::Latency).unwrap(), 2);
+    assert_eq!(fs::read_to_string(&space).unwrap(), old);
+    for format in [Format::Csv, Format::JsonLines, Format::Parquet, Format::Arrow] {
+        let converted = dir.join(format!("converted.{}", format));
+        convert(&space, Some(Format::Space), &converted, format, DataKind::Latency).unwrap();
+        let restored = dir.join(format!("restored_{}.txt", format));
+        assert_eq!(convert(&converted, Some(format), &restored, Format::Space, DataKind::Latency).unwrap(), 2);
+        assert_eq!(fs::read_to_string(&restored).unwrap(), old, "{}", format);
+    }
+    match read_table(&space, DataKind::Latency, Some(Format::Space)).unwrap() {
+        Table::Latency(rows) => assert_eq!(rows[0].receive_time, 1601510400123000000),
+        table => panic!("unexpected table: {:?}", table),
+    }
+}
+
+#[test]
+fn round_trips_through_every_format() {
+    let dir = TempDir::new("convert_round_trip");
+    let executions = dir.join("lightning_executions_FX_BTC_JPY.csv");
+    fs::write(&executions, EXECUTIONS).unwrap();
+    let latency = dir.join("latency_lightning_executions_FX_BTC_JPY.csv");
+    fs::write(&latency, LATENCY).unwrap();
+
+    for (source, kind, content, rows) in [
+        (&executions, DataKind::Executions, EXECUTIONS, 3),
+        (&latency, DataKind::Latency, LATENCY, 2),
+    ] {
+        assert_eq!(DataKind::from_path(source), kind);
+        assert_eq!(Format::detect(source, kind).unwrap(), Format::Space);
+        for format in [Format::Csv, Format::JsonLines, Format::Parquet, Format::Arrow] {
+            let converted = dir.join(format!("converted.{}", format));
+            assert_eq!(convert(source, None, &converted, format, kind).unwrap(), rows);
+            let restored = dir.join(format!("restored_{}.txt", format));
+            convert(&converted, Some(format), &restored, Format::Space, kind).unwrap();
+            assert_eq!(fs::read_to_string(&restored).unwrap(), content, "{:?} {}", kind, format);
+        }
+    }
+
+    // 見出し付きのCSVは拡張子が.csvでも見出しで判別する
+    let csv = dir.join("with_header.csv");
+    convert(&executions, None, &csv, Format::Csv, DataKind::Executions).unwrap();
+    assert_eq!(Format::detect(&csv, DataKind::Executions).unwrap(), Format::Csv);
+    let lines: Vec<String> = fs::read_to_string(&csv).unwrap().lines().map(|line| line.to_string()).collect();
+    assert_eq!(lines[0], "exec_time,side,price,size,receive_time,monotonic_nanos,sequence,id");
+    assert_eq!(lines[3], "1601510402,N,1234569,0.1,,,,");
+    match read_table(&csv, DataKind::Executions, None).unwrap() {
+        Table::Executions(rows) => assert_eq!(rows[2].side, Side::NoSide),
+        table => panic!("unexpected table: {:?}", table),
+    }
+}
+
+#[test]
+fn stores_timestamps_and_side_with_typed_columns() {
+    let schema = schema(DataKind::Executions);
+    assert_eq!(
+        schema.field_with_name("exec_time").unwrap().data_type(),
+        &DataType::Timestamp(TimeUnit::Second, Some("UTC".into()))
+    );
+    assert_eq!(
+        schema.field_with_name("receive_time").unwrap().data_type(),
+        &DataType::Timestamp(TimeUnit::Nanosecond, Some("UTC".into()))
+    );
+    assert_eq!(schema.field_with_name("side").unwrap().data_type(), &DataType::Utf8);
+
+    let table = Table::Executions(vec![ExecutionRow::parse("1601510400 B 100 0.5").unwrap()]);
+    let batch = table.to_record_batch().unwrap();
+    assert_eq!(batch.num_rows(), 1);
+    assert_eq!(batch.column_by_name("id").unwrap().null_count(), 1);
+    let mut restored = Table::new(DataKind::Executions);
+    restored.extend_from_record_batch(&batch).unwrap();
+    assert_eq!(restored, table);
+}
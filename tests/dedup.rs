@@ -0,0 +1,73 @@
+mod common;
+
+use std::fs;
+
+use fetch_market_and_order_data::clock::ReceiveTime;
+use fetch_market_and_order_data::dedup::ExecutionDedup;
+use fetch_market_and_order_data::latency::ClockOffset;
+use fetch_market_and_order_data::recorder::Recorder;
+use fetch_market_and_order_data::stream_api::FrameParser;
+
+use common::temp_dir::TempDir;
+
+use serde_json::json;
+
+const EXECUTIONS: &str = "lightning_executions_FX_BTC_JPY";
+
+#[test]
+fn checks_older_ids_against_daily_id_set() {
+    let dir = TempDir::new("dedup_ids");
+    let ids_path = dir.join("execution_ids.csv");
+    fs::write(&ids_path, "1\n2\n3\n").unwrap();
+
+    // 再起動後は書き込み済みの一覧の直近の約定IDから確認する
+    let mut dedup = ExecutionDedup::new(2);
+    assert!(!dedup.insert(3, &ids_path).unwrap());
+    assert!(dedup.insert(4, &ids_path).unwrap());
+    assert!(!dedup.insert(4, &ids_path).unwrap());
+    // 直近の約定IDより古い約定IDは一覧で確認する
+    assert!(!dedup.insert(1, &ids_path).unwrap());
+    assert!(dedup.insert(0, &ids_path).unwrap());
+    assert!(!dedup.insert(0, &ids_path).unwrap());
+}
+
+#[test]
+fn records_each_execution_once() {
+    let mut parser = FrameParser::new(vec![EXECUTIONS.to_string()], vec![], vec![], ClockOffset::default());
+    let frame = |ids: &[u64]| {
+        let messages: Vec<_> = ids
+            .iter()
+            .map(|id| json!({"id": id, "side": "BUY", "price": 1100000.0, "size": 0.01, "exec_date": "2020-10-01T00:00:00.5Z"}))
+            .collect();
+        json!({
+            "jsonrpc": "2.0",
+            "method": "channelMessage",
+            "params": {"channel": EXECUTIONS, "message": messages},
+        })
+        .to_string()
+    };
+
+    let output_dir = TempDir::new("dedup_record");
+    let mut recorder = Recorder::new(&output_dir.display().to_string(), "bitFlyer");
+    // 再接続の前後で同じ約定を受信した
+    for (sequence, ids) in [vec![1, 2], vec![2, 3]].iter().enumerate() {
+        for message in parser.parse(&frame(ids), ReceiveTime::now(), sequence as u64) {
+            recorder.record(&message);
+        }
+    }
+    // 再起動後に同じ約定を受信した
+    let mut recorder = Recorder::new(&output_dir.display().to_string(), "bitFlyer");
+    for message in parser.parse(&frame(&[3, 4]), ReceiveTime::now(), 2) {
+        recorder.record(&message);
+    }
+
+    let date_dir = output_dir.join("bitFlyer").join("20201001");
+    let executions = fs::read_to_string(date_dir.join(format!("{}.csv", EXECUTIONS))).unwrap();
+    let ids: Vec<&str> = executions
+        .lines()
+        .map(|line| line.split_whitespace().last().unwrap())
+        .collect();
+    assert_eq!(ids, vec!["1", "2", "3", "4"]);
+    let id_set = fs::read_to_string(date_dir.join(format!("execution_ids_{}.csv", EXECUTIONS))).unwrap();
+    assert_eq!(id_set, "1\n2\n3\n4\n");
+}
@@ -0,0 +1,136 @@
+mod common;
+
+use std::path::PathBuf;
+use std::process::Command;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use fetch_market_and_order_data::discovery::{
+    fetch_markets, parse_markets, resolve_products, DiscoveryError, ProductDiscovery,
+};
+use fetch_market_and_order_data::product::{Product, ProductRegistry};
+use fetch_market_and_order_data::stream_api::{BfWebsocket, MarketInfo};
+
+use common::mock_server::{MockRestServer, MockServer, Script};
+
+use serde_json::{json, Value};
+
+fn markets(future: &str) -> Value {
+    json!([
+        {"product_code": "BTC_JPY", "market_type": "Spot"},
+        {"product_code": "FX_BTC_JPY", "market_type": "FX"},
+        {"product_code": "ETH_JPY", "market_type": "Spot"},
+        {"product_code": future, "alias": "BTCJPY_MAT1WK", "market_type": "Futures"},
+    ])
+}
+
+fn codes(products: &[Product]) -> Vec<String> {
+    products.iter().map(|product| product.get_code()).collect()
+}
+
+#[test]
+fn resolves_aliases_to_product_codes() {
+    let markets = parse_markets(&markets("BTCJPY28JUN2024")).unwrap();
+    let registry = ProductRegistry::bitflyer();
+
+    let products = resolve_products(&["BTCJPY_MAT1WK", "ETH_JPY", "BTCJPY28JUN2024"], &markets, &registry).unwrap();
+    assert_eq!(codes(&products), ["BTCJPY28JUN2024", "ETH_JPY"]);
+    // 登録されていない銘柄は銘柄コードから通貨ペアを推定する
+    assert_eq!(products[0].get_currency_pair(), "BTC/JPY");
+    assert_eq!(products[1], *registry.get("ETH_JPY").unwrap());
+
+    match resolve_products(&["BTCJPY_MAT3M", "ETH_JPY"], &markets, &registry) {
+        Err(DiscoveryError::UnknownProducts(unknown)) => assert_eq!(unknown, ["BTCJPY_MAT3M"]),
+        result => panic!("unexpected result {:?}", result),
+    }
+}
+
+#[test]
+fn fetches_markets_from_rest_api() {
+    let server = MockRestServer::start(markets("BTCJPY28JUN2024"));
+    let markets = fetch_markets(&server.end_point()).unwrap();
+    assert_eq!(markets.len(), 4);
+    assert_eq!(markets[3].alias.as_deref(), Some("BTCJPY_MAT1WK"));
+    assert_eq!(server.paths(), ["/v1/markets"]);
+}
+
+#[test]
+fn notifies_when_alias_moves_to_next_contract() {
+    let server = MockRestServer::start(markets("BTCJPY28JUN2024"));
+    let registry = ProductRegistry::bitflyer();
+    let requested = vec![String::from("BTCJPY_MAT1WK")];
+    let current = resolve_products(&requested, &fetch_markets(&server.end_point()).unwrap(), &registry).unwrap();
+    let discovery = ProductDiscovery::start(
+        &server.end_point(),
+        requested,
+        registry,
+        current,
+        Duration::from_millis(20),
+    );
+
+    // 変化がない間は通知しない
+    sleep(Duration::from_millis(100));
+    assert_eq!(discovery.take_update(), None);
+
+    server.set_response(markets("BTCJPY05JUL2024"));
+    let deadline = Instant::now() + Duration::from_secs(10);
+    loop {
+        if let Some(products) = discovery.take_update() {
+            assert_eq!(codes(&products), ["BTCJPY05JUL2024"]);
+            break;
+        }
+        assert!(Instant::now() < deadline, "Update was not notified");
+        sleep(Duration::from_millis(10));
+    }
+}
+
+#[test]
+fn updates_subscriptions_without_reconnecting() {
+    let script = Script::from_file(
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/product_update.jsonl"),
+    )
+    .unwrap();
+    let server = MockServer::start(vec![script]);
+    let mut bf = BfWebsocket::new();
+    bf.set_end_point(&server.end_point());
+    bf.on_connect().unwrap();
+
+    let markets = parse_markets(&markets("BTCJPY28JUN2024")).unwrap();
+    let products = resolve_products(&["FX_BTC_JPY", "BTCJPY_MAT1WK"], &markets, &ProductRegistry::bitflyer()).unwrap();
+    bf.update_products(products);
+
+    // 追加された銘柄の約定データを受信する
+    let deadline = Instant::now() + Duration::from_secs(10);
+    let execution = loop {
+        match bf.on_message() {
+            Ok(MarketInfo::Executions(execution)) => break execution,
+            Ok(_) => {}
+            Err(_) => sleep(Duration::from_millis(1)),
+        }
+        assert!(Instant::now() < deadline, "Execution was not received");
+    };
+    bf.close_thread();
+
+    assert_eq!(execution.get_price(), 1100000.0);
+    assert_eq!(server.connections(), 1);
+    assert_eq!(
+        bf.get_public_channels(),
+        ["lightning_executions_FX_BTC_JPY", "lightning_executions_BTCJPY28JUN2024"]
+    );
+}
+
+#[test]
+fn lists_discovered_products_with_single_request() {
+    let server = MockRestServer::start(markets("BTCJPY28JUN2024"));
+    let output = Command::new(env!("CARGO_BIN_EXE_fetch-market-and-order-data"))
+        .args(["list-products", "--discover-products", "--rest-end-point", &server.end_point()])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    // マーケットの一覧は1度だけ取得する
+    assert_eq!(server.paths(), ["/v1/markets"]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let codes: Vec<&str> = stdout.lines().filter_map(|line| line.split_whitespace().next()).collect();
+    assert_eq!(codes, ["BTC_JPY", "FX_BTC_JPY", "ETH_JPY", "BTCJPY28JUN2024"]);
+}
@@ -0,0 +1,117 @@
+mod common;
+
+use std::fs;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use chrono::{TimeZone, Utc};
+
+use fetch_market_and_order_data::exchange_status::{
+    ExchangeHealth, ExchangeStatus, HaltTracker, StatusPoller, OPEN_HALTS_FILE_NAME,
+};
+use fetch_market_and_order_data::recorder::Recorder;
+use fetch_market_and_order_data::stream_api::{Common, MarketInfo};
+
+use common::mock_server::MockRestServer;
+use common::temp_dir::TempDir;
+
+use serde_json::json;
+
+fn status(secs: i64, health: &str, state: &str) -> ExchangeStatus {
+    let time = Utc.timestamp_opt(1_601_510_400 + secs, 0).unwrap();
+    ExchangeStatus::from_json(time, "FX_BTC_JPY", &json!({"health": health, "state": state})).unwrap()
+}
+
+#[test]
+fn parses_health_and_state() {
+    let busy = status(0, "SUPER BUSY", "RUNNING");
+    assert_eq!(busy.health, ExchangeHealth::SuperBusy);
+    assert!(!busy.is_halted());
+    assert_eq!(busy.get_csv(), "1601510400000 FX_BTC_JPY SUPER_BUSY RUNNING 0\n");
+
+    assert!(status(0, "STOP", "RUNNING").is_halted());
+    // メンテナンス中は板が稼働していない
+    let closed = status(0, "NORMAL", "CLOSED");
+    assert!(closed.is_halted());
+    assert_eq!(closed.get_csv(), "1601510400000 FX_BTC_JPY NORMAL CLOSED 1\n");
+}
+
+#[test]
+fn tracks_halt_periods() {
+    let mut tracker = HaltTracker::new();
+    assert_eq!(tracker.update(&status(0, "NORMAL", "RUNNING")), None);
+    assert_eq!(tracker.update(&status(60, "STOP", "CLOSED")), None);
+    assert_eq!(tracker.update(&status(120, "STOP", "CLOSED")), None);
+
+    let period = tracker.update(&status(180, "BUSY", "RUNNING")).unwrap();
+    assert_eq!(period.get_csv(), "1601510460000 1601510580000 FX_BTC_JPY STOP CLOSED\n");
+    assert_eq!(tracker.update(&status(240, "NORMAL", "RUNNING")), None);
+}
+
+#[test]
+fn keeps_open_halts_across_restart() {
+    let dir = TempDir::new("exchange_status_open_halts");
+    let path = dir.join(".journal/bitFlyer").join(OPEN_HALTS_FILE_NAME);
+
+    let mut tracker = HaltTracker::open(&path).unwrap();
+    assert!(tracker.halted().is_empty());
+    assert_eq!(tracker.update(&status(60, "STOP", "CIRCUIT BREAK")), None);
+    assert_eq!(fs::read_to_string(&path).unwrap(), "1601510460000 FX_BTC_JPY STOP CIRCUIT_BREAK 1\n");
+    drop(tracker);
+
+    // 再起動後も停止の開始時刻を引き継ぎ、再開した時点で停止していた期間を返す
+    let mut tracker = HaltTracker::open(&path).unwrap();
+    assert_eq!(tracker.halted(), [status(60, "STOP", "CIRCUIT BREAK")]);
+    let period = tracker.update(&status(300, "NORMAL", "RUNNING")).unwrap();
+    assert_eq!(period.get_csv(), "1601510460000 1601510700000 FX_BTC_JPY STOP CIRCUIT_BREAK\n");
+    assert_eq!(fs::read_to_string(&path).unwrap(), "");
+    drop(tracker);
+
+    assert!(HaltTracker::open(&path).unwrap().halted().is_empty());
+}
+
+#[test]
+fn records_status_and_halt_files() {
+    let output_dir = TempDir::new("exchange_status_record");
+    let mut recorder = Recorder::new(&output_dir.display().to_string(), "bitFlyer");
+    for message in [
+        status(0, "NORMAL", "RUNNING"),
+        status(60, "STOP", "CLOSED"),
+        status(120, "NORMAL", "RUNNING"),
+    ] {
+        recorder.record(&MarketInfo::ExchangeStatus(message));
+    }
+
+    let dir = output_dir.join("bitFlyer/20201001");
+    let statuses = fs::read_to_string(dir.join("status.csv")).unwrap();
+    assert_eq!(statuses.lines().count(), 3);
+    assert_eq!(
+        fs::read_to_string(dir.join("halt.csv")).unwrap(),
+        "1601510460000 1601510520000 FX_BTC_JPY STOP CLOSED\n"
+    );
+}
+
+#[test]
+fn polls_board_state_per_product() {
+    let server = MockRestServer::start(json!({"health": "BUSY", "state": "RUNNING"}));
+    let poller = StatusPoller::start(
+        &server.end_point(),
+        vec![String::from("FX_BTC_JPY"), String::from("ETH_JPY")],
+        Duration::from_secs(60),
+    );
+
+    let deadline = Instant::now() + Duration::from_secs(10);
+    let mut statuses = Vec::new();
+    while statuses.len() < 2 {
+        assert!(Instant::now() < deadline, "Statuses were not polled");
+        statuses.extend(poller.take());
+        sleep(Duration::from_millis(10));
+    }
+    let products: Vec<_> = statuses.iter().map(|status| status.product_code.as_str()).collect();
+    assert_eq!(products, ["FX_BTC_JPY", "ETH_JPY"]);
+    assert!(statuses.iter().all(|status| status.health == ExchangeHealth::Busy));
+    assert_eq!(
+        server.paths(),
+        ["/v1/getboardstate?product_code=FX_BTC_JPY", "/v1/getboardstate?product_code=ETH_JPY"]
+    );
+}
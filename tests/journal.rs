@@ -0,0 +1,91 @@
+mod common;
+
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::time::Duration;
+
+use fetch_market_and_order_data::clock::ReceiveTime;
+use fetch_market_and_order_data::journal::{read_journal, recover, repair_partial_line, Durability, JOURNAL_FILE_NAME};
+use fetch_market_and_order_data::latency::ClockOffset;
+use fetch_market_and_order_data::recorder::Recorder;
+use fetch_market_and_order_data::stream_api::FrameParser;
+
+use common::temp_dir::TempDir;
+
+use serde_json::json;
+
+const EXECUTIONS: &str = "lightning_executions_FX_BTC_JPY";
+
+#[test]
+fn truncates_partial_trailing_line() {
+    let dir = TempDir::new("journal_repair");
+    let path = dir.join("trades.csv");
+    fs::write(&path, "1 B 100\n2 S 10").unwrap();
+    assert_eq!(repair_partial_line(&path).unwrap(), 6);
+    assert_eq!(fs::read_to_string(&path).unwrap(), "1 B 100\n");
+    assert_eq!(repair_partial_line(&path).unwrap(), 0);
+
+    // 改行を含まない場合は空にする
+    fs::write(&path, "1 B").unwrap();
+    assert_eq!(repair_partial_line(&path).unwrap(), 3);
+    assert_eq!(fs::read_to_string(&path).unwrap(), "");
+}
+
+#[test]
+fn replays_journal_entries_missing_from_files() {
+    let dir = TempDir::new("journal_replay");
+    let journal_dir = dir.join(".journal");
+    let path = dir.join("trades.csv");
+
+    let mut durability = Durability::open(&journal_dir, Duration::from_secs(60), true).unwrap();
+    fs::write(&path, "").unwrap();
+    durability.before_append(&path, b"1 B 100\n").unwrap();
+    fs::write(&path, "1 B 100\n").unwrap();
+    durability.before_append(&path, b"2 S 200\n3 B 300\n").unwrap();
+    // 2件目の追記の途中で異常終了した
+    OpenOptions::new().append(true).open(&path).unwrap().write_all(b"2 S").unwrap();
+    // 書き込み途中で途切れた先行書き込みログは反映しない
+    OpenOptions::new()
+        .append(true)
+        .open(journal_dir.join(JOURNAL_FILE_NAME))
+        .unwrap()
+        .write_all(b"16 8 ")
+        .unwrap();
+    drop(durability);
+    assert_eq!(read_journal(&journal_dir.join(JOURNAL_FILE_NAME)).unwrap().len(), 2);
+
+    let recovery = recover(&journal_dir).unwrap();
+    assert_eq!(recovery.replayed, 1);
+    assert!(recovery.repaired.is_empty());
+    assert_eq!(fs::read_to_string(&path).unwrap(), "1 B 100\n2 S 200\n3 B 300\n");
+    assert!(read_journal(&journal_dir.join(JOURNAL_FILE_NAME)).unwrap().is_empty());
+}
+
+#[test]
+fn syncs_and_clears_journal_after_recording() {
+    let mut parser = FrameParser::new(vec![EXECUTIONS.to_string()], vec![], vec![], ClockOffset::default());
+    let frame = json!({
+        "jsonrpc": "2.0",
+        "method": "channelMessage",
+        "params": {"channel": EXECUTIONS, "message": [
+            {"id": 1, "side": "BUY", "price": 1100000.0, "size": 0.01, "exec_date": "2020-10-01T00:00:00.5Z"}
+        ]},
+    });
+
+    let output_dir = TempDir::new("journal_record");
+    let journal_dir = output_dir.join(".journal");
+    let mut recorder = Recorder::new(&output_dir.display().to_string(), "bitFlyer");
+    recorder.set_durability(Durability::open(&journal_dir, Duration::from_secs(60), true).unwrap());
+    for message in parser.parse(&frame.to_string(), ReceiveTime::now(), 1) {
+        recorder.record(&message);
+    }
+    let entries = read_journal(&journal_dir.join(JOURNAL_FILE_NAME)).unwrap();
+    assert!(entries.iter().any(|entry| entry.path.ends_with(format!("20201001/{}.csv", EXECUTIONS))));
+
+    // fsync後は先行書き込みログを空にする
+    recorder.finish();
+    assert!(read_journal(&journal_dir.join(JOURNAL_FILE_NAME)).unwrap().is_empty());
+    let executions =
+        fs::read_to_string(output_dir.join("bitFlyer/20201001").join(format!("{}.csv", EXECUTIONS))).unwrap();
+    assert!(executions.starts_with("1601510400 B "));
+}
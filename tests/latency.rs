@@ -0,0 +1,195 @@
+mod common;
+
+use std::fs;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Duration as ChronoDuration, TimeZone, Utc};
+
+use fetch_market_and_order_data::clock::ReceiveTime;
+use fetch_market_and_order_data::latency::{ClockOffset, LatencyPercentiles, LatencyPeriod, LatencyStats};
+use fetch_market_and_order_data::recorder::Recorder;
+use fetch_market_and_order_data::stream_api::{Common, FrameParser, Latency, MarketInfo};
+
+use common::temp_dir::TempDir;
+
+use serde_json::json;
+
+const FX_BTC_JPY: &str = "lightning_executions_FX_BTC_JPY";
+const BTC_JPY: &str = "lightning_executions_BTC_JPY";
+
+// 約定日時から遅延(ミリ秒)後に受信した約定データを解析し、遅延データを取得する
+fn parse(offset: &ClockOffset, channel: &str, receive: DateTime<Utc>, latency_millis: i64) -> Vec<MarketInfo> {
+    let mut parser = FrameParser::new(
+        vec![FX_BTC_JPY.to_string(), BTC_JPY.to_string()],
+        vec![],
+        vec![],
+        offset.clone(),
+    );
+    let exec_date = receive - ChronoDuration::milliseconds(latency_millis);
+    let frame = json!({
+        "jsonrpc": "2.0",
+        "method": "channelMessage",
+        "params": {"channel": channel, "message": [
+            {"id": 1, "side": "BUY", "price": 1100000.0, "size": 0.01, "exec_date": exec_date.to_rfc3339()}
+        ]},
+    });
+    let receive_nanos = receive.timestamp_nanos_opt().unwrap();
+    let receive_time = ReceiveTime::from_nanos(receive_nanos, 0, receive_nanos);
+    parser.parse(&frame.to_string(), receive_time, 1)
+}
+
+fn latency(offset: &ClockOffset, channel: &str, receive: DateTime<Utc>, latency_millis: i64) -> Latency {
+    parse(offset, channel, receive, latency_millis)
+        .into_iter()
+        .find_map(|message| match message {
+            MarketInfo::LatencyExchange(latency) => Some(latency),
+            _ => None,
+        })
+        .expect("Latency was not parsed")
+}
+
+#[test]
+fn computes_nearest_rank_percentiles() {
+    let mut samples: Vec<i64> = (1..=100).rev().collect();
+    assert_eq!(
+        LatencyPercentiles::from_samples(&mut samples),
+        Some(LatencyPercentiles { count: 100, p50: 50, p90: 90, p99: 99, max: 100 })
+    );
+
+    // 順位は切り上げる(3件の場合、p50は2番目、p90・p99は3番目)
+    let mut samples = vec![30, 10, 20];
+    assert_eq!(
+        LatencyPercentiles::from_samples(&mut samples),
+        Some(LatencyPercentiles { count: 3, p50: 20, p90: 30, p99: 30, max: 30 })
+    );
+
+    let mut samples = vec![7];
+    assert_eq!(
+        LatencyPercentiles::from_samples(&mut samples),
+        Some(LatencyPercentiles { count: 1, p50: 7, p90: 7, p99: 7, max: 7 })
+    );
+
+    assert_eq!(LatencyPercentiles::from_samples(&mut []), None);
+}
+
+#[test]
+fn summarizes_each_channel_at_minute_boundary() {
+    let offset = ClockOffset::default();
+    let mut stats = LatencyStats::new();
+    let start = Utc.with_ymd_and_hms(2020, 10, 1, 0, 0, 10).unwrap();
+    for &(channel, latency_millis) in [(FX_BTC_JPY, 100), (FX_BTC_JPY, 300), (BTC_JPY, 200)].iter() {
+        assert!(stats.add(&latency(&offset, channel, start, latency_millis)).is_empty());
+    }
+    // 同じ分の間は集計結果を出力しない
+    assert!(stats.flush(start + ChronoDuration::seconds(49)).is_empty());
+
+    let mut summaries = stats.flush(start + ChronoDuration::seconds(50));
+    summaries.sort_by_key(|summary| summary.channel());
+    assert_eq!(summaries.len(), 2);
+    let minute = Utc.with_ymd_and_hms(2020, 10, 1, 0, 0, 0).unwrap();
+    assert_eq!(summaries[0].channel(), BTC_JPY);
+    assert_eq!(summaries[0].period, LatencyPeriod::Minute);
+    assert_eq!(summaries[0].period_start, minute);
+    assert_eq!(summaries[0].percentiles.count, 1);
+    assert_eq!(summaries[0].percentiles.max, 200_000);
+    assert_eq!(summaries[1].channel(), FX_BTC_JPY);
+    assert_eq!(summaries[1].period, LatencyPeriod::Minute);
+    assert_eq!(summaries[1].period_start, minute);
+    assert_eq!(summaries[1].percentiles.count, 2);
+    assert_eq!(summaries[1].percentiles.p50, 100_000);
+    assert_eq!(summaries[1].percentiles.max, 300_000);
+
+    // 出力済みの分は再び出力しない
+    assert!(stats.flush(start + ChronoDuration::seconds(55)).is_empty());
+}
+
+#[test]
+fn writes_daily_summary_at_day_boundary() {
+    let offset = ClockOffset::default();
+    let output_dir = TempDir::new("latency_daily_summary");
+    let mut recorder = Recorder::new(&output_dir.display().to_string(), "bitFlyer");
+    let receive = Utc.with_ymd_and_hms(2020, 10, 1, 23, 59, 30).unwrap();
+    for &latency_millis in [100, 200].iter() {
+        for message in parse(&offset, FX_BTC_JPY, receive, latency_millis) {
+            recorder.record(&message);
+        }
+    }
+
+    let day_dir = output_dir.join("bitFlyer/20201001");
+    recorder.tick(Utc.with_ymd_and_hms(2020, 10, 1, 23, 59, 59).unwrap());
+    assert!(!day_dir.join("latency_summary.csv").exists());
+
+    // 日付が変わると前日の集計結果を書き込む
+    recorder.tick(Utc.with_ymd_and_hms(2020, 10, 2, 0, 0, 1).unwrap());
+    let summary = fs::read_to_string(day_dir.join("latency_summary.csv")).unwrap();
+    assert_eq!(summary, format!("{} 2 100.000 200.000 200.000 200.000\n", FX_BTC_JPY));
+    let minutes = fs::read_to_string(day_dir.join(format!("latency_stats_{}.csv", FX_BTC_JPY))).unwrap();
+    assert_eq!(minutes, "1601596740000 2 100.000 200.000 200.000 200.000\n");
+}
+
+#[test]
+fn applies_fixed_clock_offset() {
+    let receive = Utc.with_ymd_and_hms(2020, 10, 1, 0, 0, 10).unwrap();
+    let offset = ClockOffset::fixed(2.5);
+    assert_eq!(offset.get_micros(), 2_500);
+    assert_eq!(latency(&offset, FX_BTC_JPY, receive, 100).get_latency_micros(), 102_500);
+
+    let offset = ClockOffset::fixed(-1.25);
+    assert_eq!(latency(&offset, FX_BTC_JPY, receive, 100).get_latency_micros(), 98_750);
+}
+
+#[test]
+fn applies_clock_offset_from_file() {
+    let receive = Utc.with_ymd_and_hms(2020, 10, 1, 0, 0, 10).unwrap();
+    let dir = TempDir::new("latency_offset_file");
+    let path = dir.join("offset");
+
+    // 読み込めないファイルの場合はオフセットなし
+    let offset = ClockOffset::watch_file(path.clone(), Duration::from_millis(10));
+    sleep(Duration::from_millis(50));
+    assert_eq!(offset.get_micros(), 0);
+
+    fs::write(&path, "3.5\n").unwrap();
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while offset.get_micros() != 3_500 {
+        assert!(Instant::now() < deadline, "Offset file was not read");
+        sleep(Duration::from_millis(10));
+    }
+    assert_eq!(latency(&offset, FX_BTC_JPY, receive, 100).get_latency_micros(), 103_500);
+
+    // 解析できない内容・読み込めないファイルの場合は直前のオフセットを維持する
+    fs::write(&path, "not a number\n").unwrap();
+    sleep(Duration::from_millis(50));
+    assert_eq!(offset.get_micros(), 3_500);
+    fs::remove_file(&path).unwrap();
+    sleep(Duration::from_millis(50));
+    assert_eq!(offset.get_micros(), 3_500);
+    assert_eq!(latency(&offset, FX_BTC_JPY, receive, 100).get_latency_micros(), 103_500);
+}
+
+#[test]
+fn does_not_estimate_board_latency() {
+    let board = "lightning_board_FX_BTC_JPY";
+    let mut parser = FrameParser::new(vec![FX_BTC_JPY.to_string()], vec![board.to_string()], vec![], ClockOffset::default());
+    let receive = Utc.with_ymd_and_hms(2020, 10, 1, 0, 0, 10).unwrap().timestamp_nanos_opt().unwrap();
+    let receive_time = ReceiveTime::from_nanos(receive, 0, receive);
+    let execution = json!({
+        "jsonrpc": "2.0",
+        "method": "channelMessage",
+        "params": {"channel": FX_BTC_JPY, "message": [
+            {"id": 1, "side": "BUY", "price": 1100000.0, "size": 0.01, "exec_date": "2020-10-01T00:00:09.900Z"}
+        ]},
+    });
+    assert_eq!(parser.parse(&execution.to_string(), receive_time, 1).len(), 2);
+
+    // 板情報には配信時刻がないため、直近の約定があっても遅延データを作成しない
+    let frame = json!({
+        "jsonrpc": "2.0",
+        "method": "channelMessage",
+        "params": {"channel": board, "message": {"mid_price": 1100000.0, "asks": [], "bids": []}},
+    });
+    let messages = parser.parse(&frame.to_string(), receive_time, 2);
+    assert_eq!(messages.len(), 1);
+    assert!(matches!(messages[0], MarketInfo::Boards(_)));
+}
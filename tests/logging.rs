@@ -0,0 +1,56 @@
+use fetch_market_and_order_data::logging::{event, format_record, LogFormat};
+
+use log::{Level, Record};
+use serde_json::Value;
+
+#[test]
+fn parses_log_format() {
+    assert_eq!("json".parse::<LogFormat>(), Ok(LogFormat::Json));
+    assert_eq!("TEXT".parse::<LogFormat>(), Ok(LogFormat::Text));
+    assert!("yaml".parse::<LogFormat>().is_err());
+}
+
+#[test]
+fn formats_fields_as_json() {
+    let fields = [
+        ("event", event::SUBSCRIBE_FAILED),
+        ("exchange", "bitFlyer"),
+        ("channel", "lightning_executions_BTC_JPY"),
+        ("error", "Invalid channel"),
+    ];
+    let line = format_record(
+        LogFormat::Json,
+        &Record::builder()
+            .args(format_args!("Subscribe {} failed.", "lightning_executions_BTC_JPY"))
+            .level(Level::Warn)
+            .target("fetch_market_and_order_data::stream_api")
+            .key_values(&fields)
+            .build(),
+    );
+
+    let v: Value = serde_json::from_str(&line).unwrap();
+    assert_eq!(v["level"], "WARN");
+    assert_eq!(v["message"], "Subscribe lightning_executions_BTC_JPY failed.");
+    assert_eq!(v["event"], "subscribe_failed");
+    assert_eq!(v["exchange"], "bitFlyer");
+    assert_eq!(v["channel"], "lightning_executions_BTC_JPY");
+    assert_eq!(v["error"], "Invalid channel");
+    assert!(v["time"].as_str().unwrap().ends_with('Z'));
+}
+
+#[test]
+fn appends_fields_to_text() {
+    let fields = [("event", event::RECONNECT), ("exchange", "bitFlyer")];
+    let line = format_record(
+        LogFormat::Text,
+        &Record::builder()
+            .args(format_args!("Reconnect to bitFlyer Websocket Service."))
+            .level(Level::Info)
+            .target("fetch_market_and_order_data")
+            .key_values(&fields)
+            .build(),
+    );
+    assert!(line.ends_with(
+        "INFO  fetch_market_and_order_data] Reconnect to bitFlyer Websocket Service. event=reconnect exchange=bitFlyer"
+    ));
+}
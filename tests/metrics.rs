@@ -0,0 +1,99 @@
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::time::{Duration, Instant};
+
+use fetch_market_and_order_data::clock::ReceiveTime;
+use fetch_market_and_order_data::latency::ClockOffset;
+use fetch_market_and_order_data::metrics::{serve, Metrics, CONNECTION_TIMEOUT};
+use fetch_market_and_order_data::stream_api::{FrameParser, MarketInfo};
+
+use serde_json::json;
+
+const EXECUTIONS: &str = "lightning_executions_FX_BTC_JPY";
+
+// 約定データを1件含むフレームを解析する
+fn executions() -> Vec<MarketInfo> {
+    let mut parser = FrameParser::new(vec![EXECUTIONS.to_string()], vec![], vec![], ClockOffset::default());
+    let frame = json!({
+        "jsonrpc": "2.0",
+        "method": "channelMessage",
+        "params": {"channel": EXECUTIONS, "message": [
+            {"id": 1, "side": "BUY", "price": 1100000.0, "size": 0.01, "exec_date": "2020-10-01T00:00:00.5Z"}
+        ]},
+    });
+    parser.parse(&frame.to_string(), ReceiveTime::now(), 1)
+}
+
+fn get(addr: SocketAddr, path: &str) -> String {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+}
+
+#[test]
+fn counts_messages_per_channel() {
+    let metrics = Metrics::new();
+    let now = Instant::now();
+    for message in executions() {
+        metrics.on_message(&message, now);
+    }
+    metrics.on_message(&MarketInfo::Close, now);
+    metrics.on_reconnect();
+    metrics.on_write_error();
+    metrics.set_queue_depth(3);
+
+    let text = metrics.render(now + Duration::from_secs(2));
+    assert!(text.contains(&format!("recorder_messages_total{{channel=\"{}\"}} 1\n", EXECUTIONS)));
+    assert!(text.contains(&format!(
+        "recorder_last_message_age_seconds{{channel=\"{}\"}} 2.000\n",
+        EXECUTIONS
+    )));
+    assert!(text.contains("recorder_reconnects_total 1\n"));
+    assert!(text.contains("recorder_write_errors_total 1\n"));
+    assert!(text.contains("recorder_queue_depth 3\n"));
+}
+
+#[test]
+fn health_fails_after_silence_threshold() {
+    let metrics = Metrics::new();
+    let now = Instant::now();
+    for message in executions() {
+        metrics.on_message(&message, now);
+    }
+    assert!(metrics.is_healthy(Duration::from_secs(10), now + Duration::from_secs(10)));
+    assert!(!metrics.is_healthy(Duration::from_secs(10), now + Duration::from_secs(11)));
+}
+
+#[test]
+fn serves_metrics_and_health() {
+    let metrics = Metrics::new();
+    for message in executions() {
+        metrics.on_message(&message, Instant::now());
+    }
+    let addr = serve("127.0.0.1:0", metrics.clone(), Duration::from_secs(60)).unwrap();
+
+    let response = get(addr, "/metrics");
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.contains("recorder_messages_total"));
+    assert!(get(addr, "/health").starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(get(addr, "/unknown").starts_with("HTTP/1.1 404 Not Found\r\n"));
+
+    // 受信のない時間が閾値を超えた場合は失敗する
+    let silent = serve("127.0.0.1:0", Metrics::new(), Duration::from_secs(0)).unwrap();
+    std::thread::sleep(Duration::from_millis(10));
+    assert!(get(silent, "/health").starts_with("HTTP/1.1 503 Service Unavailable\r\n"));
+}
+
+#[test]
+fn responds_while_another_connection_is_idle() {
+    let addr = serve("127.0.0.1:0", Metrics::new(), Duration::from_secs(60)).unwrap();
+
+    // リクエストを送らない接続があっても、他の接続に応答する
+    let idle = TcpStream::connect(addr).unwrap();
+    let started = Instant::now();
+    assert!(get(addr, "/health").starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(started.elapsed() < CONNECTION_TIMEOUT);
+    drop(idle);
+}
@@ -0,0 +1,236 @@
+mod common;
+
+use std::fs;
+use std::path::PathBuf;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use fetch_market_and_order_data::latency::ClockOffset;
+use fetch_market_and_order_data::recorder::Recorder;
+use fetch_market_and_order_data::stream_api::{
+    BfWebsocket, FrameParser, MarketInfo, Side, StreamError,
+};
+
+use common::mock_server::{MockServer, Script};
+use common::temp_dir::TempDir;
+
+fn fixture(name: &str) -> Script {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name);
+    Script::from_file(path).unwrap()
+}
+
+fn connect(server: &MockServer) -> BfWebsocket {
+    let mut bf = BfWebsocket::new();
+    bf.set_end_point(&server.end_point());
+    bf
+}
+
+// 最初の約定データを受信するまで待つ
+fn receive_first_execution(bf: &BfWebsocket) -> Vec<MarketInfo> {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    let mut messages = Vec::new();
+    while Instant::now() < deadline {
+        match bf.on_message() {
+            Ok(MarketInfo::Executions(execution)) => {
+                messages.push(MarketInfo::Executions(execution));
+                return messages;
+            }
+            Ok(message) => messages.push(message),
+            Err(_) => sleep(Duration::from_millis(1)),
+        }
+    }
+    panic!("Execution was not received");
+}
+
+// 切断されるまで受信したメッセージを取得する
+fn receive_until_close(bf: &BfWebsocket) -> Vec<MarketInfo> {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    let mut messages = Vec::new();
+    while Instant::now() < deadline {
+        match bf.on_message() {
+            Ok(MarketInfo::Close) => return messages,
+            Ok(message) => messages.push(message),
+            Err(_) => sleep(Duration::from_millis(1)),
+        }
+    }
+    panic!("Close message was not received");
+}
+
+#[test]
+fn receives_executions_and_confirms_subscriptions() {
+    let server = MockServer::start(vec![fixture("executions.jsonl")]);
+    let bf = connect(&server);
+    bf.on_connect().unwrap();
+    let mut messages = receive_first_execution(&bf);
+
+    // サーバーが承認したチャンネルのみ購読中となる
+    let mut expected = bf.get_public_channels().to_vec();
+    expected.sort();
+    assert_eq!(bf.subscriptions(), expected);
+
+    messages.extend(receive_until_close(&bf));
+    let executions: Vec<_> = messages
+        .iter()
+        .filter_map(|message| match message {
+            MarketInfo::Executions(execution) => Some(execution),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(executions.len(), 3);
+    // 同じフレーム内の約定は約定日時の古い順に並び替えられる
+    assert_eq!(executions[0].get_side(), Side::Buy);
+    assert_eq!(executions[1].get_side(), Side::Sell);
+    assert_eq!(executions[2].get_side(), Side::NoSide);
+    // 異なるフレームはシーケンス番号で受信順を復元できる
+    assert_eq!(executions[0].get_sequence(), executions[1].get_sequence());
+    assert!(executions[1].get_sequence() < executions[2].get_sequence());
+
+    let latencies = messages
+        .iter()
+        .filter(|message| matches!(message, MarketInfo::LatencyExchange(_)))
+        .count();
+    assert_eq!(latencies, 3);
+
+    let subscribes: Vec<_> = server
+        .requests()
+        .into_iter()
+        .filter(|request| request["method"] == "subscribe")
+        .collect();
+    assert!(subscribes.iter().all(|request| request["id"].is_u64()));
+}
+
+#[test]
+fn reports_rejected_subscriptions() {
+    let server = MockServer::start_with_rejected_channels(
+        vec![fixture("rejected.jsonl")],
+        &["lightning_executions_BTC_JPY"],
+    );
+    let bf = connect(&server);
+    bf.on_connect().unwrap();
+    let messages = receive_until_close(&bf);
+
+    let errors: Vec<_> = messages
+        .iter()
+        .filter_map(|message| match message {
+            MarketInfo::Error(error) => Some(error.clone()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(errors.len(), 1);
+    assert!(matches!(
+        &errors[0],
+        StreamError::Subscribe { channel, code: -32602, .. } if channel == "lightning_executions_BTC_JPY"
+    ));
+}
+
+#[test]
+fn reconnects_after_server_closes() {
+    let server = MockServer::start(vec![fixture("close.jsonl"), fixture("executions.jsonl")]);
+
+    let bf = connect(&server);
+    bf.on_connect().unwrap();
+    let messages = receive_until_close(&bf);
+    assert!(!messages
+        .iter()
+        .any(|message| matches!(message, MarketInfo::Executions(_))));
+    bf.close_thread();
+
+    // 再接続後は新しい接続で約定履歴を受信できる
+    let bf = connect(&server);
+    bf.on_connect().unwrap();
+    let messages = receive_until_close(&bf);
+    assert_eq!(server.connections(), 2);
+    assert_eq!(
+        messages
+            .iter()
+            .filter(|message| matches!(message, MarketInfo::Executions(_)))
+            .count(),
+        3
+    );
+}
+
+#[test]
+fn parses_board_snapshot_and_diff() {
+    let server = MockServer::start(vec![fixture("board.jsonl")]);
+    let mut bf = connect(&server);
+    bf.set_raw_capture(true);
+    bf.on_connect().unwrap();
+    let messages = receive_until_close(&bf);
+
+    // 板情報チャンネルを購読する解析器で、受信したフレームを解析する
+    let mut parser = FrameParser::new(
+        vec![],
+        vec![String::from("lightning_board_FX_BTC_JPY")],
+        vec![String::from("lightning_board_snapshot_FX_BTC_JPY")],
+        ClockOffset::default(),
+    );
+    let boards: Vec<_> = messages
+        .iter()
+        .filter_map(|message| match message {
+            MarketInfo::RawFrames(frame) => Some(frame),
+            _ => None,
+        })
+        .flat_map(|frame| parser.parse(frame.get_text(), frame.receive_time, frame.sequence))
+        .filter_map(|message| match message {
+            MarketInfo::Boards(board) => Some(board),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(boards.len(), 2);
+    assert!(!boards[0].is_update);
+    assert_eq!(boards[0].bids, vec![(1099999.0, 0.1), (1099998.0, 0.2)]);
+    assert_eq!(boards[0].asks, vec![(1100001.0, 0.3)]);
+    assert!(boards[1].is_update);
+    assert_eq!(boards[1].bids, vec![(1099999.0, 0.0)]);
+}
+
+#[test]
+fn records_executions_to_daily_files() {
+    let server = MockServer::start(vec![fixture("executions.jsonl")]);
+    let bf = connect(&server);
+    bf.on_connect().unwrap();
+    let messages = receive_until_close(&bf);
+
+    let output_dir = TempDir::new("mock_server_record");
+    let mut recorder = Recorder::new(&output_dir.display().to_string(), &bf.get_exchange_name());
+    for message in messages.iter() {
+        recorder.record(message);
+    }
+    recorder.finish();
+
+    let dir = output_dir.join("bitFlyer/20201001");
+    let executions = fs::read_to_string(dir.join("lightning_executions_FX_BTC_JPY.csv")).unwrap();
+    let lines: Vec<_> = executions.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].starts_with("1601510400 B 1100000 0.01 "));
+    assert!(lines[1].starts_with("1601510401 S 1100001 0.02 "));
+    assert!(dir.join("lightning_executions_BTC_JPY.csv").exists());
+}
+
+#[test]
+fn resubscribes_silent_channel_without_reconnecting() {
+    let server = MockServer::start(vec![fixture("resubscribe.jsonl")]);
+    let bf = connect(&server);
+    bf.on_connect().unwrap();
+
+    // 購読が完了してから再購読を要求する
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while bf.subscriptions().len() < bf.get_public_channels().len() {
+        assert!(Instant::now() < deadline, "Subscriptions were not confirmed");
+        sleep(Duration::from_millis(1));
+    }
+    bf.request_resubscribe("lightning_executions_FX_BTC_JPY");
+    receive_first_execution(&bf);
+    bf.close_thread();
+
+    assert_eq!(server.connections(), 1);
+    let methods: Vec<_> = server
+        .requests()
+        .iter()
+        .filter(|request| request["params"]["channel"] == "lightning_executions_FX_BTC_JPY")
+        .map(|request| request["method"].as_str().unwrap().to_string())
+        .collect();
+    assert_eq!(methods[..3], ["subscribe", "unsubscribe", "subscribe"]);
+}
@@ -0,0 +1,113 @@
+use fetch_market_and_order_data::clock::ReceiveTime;
+use fetch_market_and_order_data::latency::ClockOffset;
+use fetch_market_and_order_data::order_book::{BookEvent, BookSide, OrderBooks};
+use fetch_market_and_order_data::stream_api::{Board, FrameParser, MarketInfo};
+
+use serde_json::{json, Value};
+
+const BOARD: &str = "lightning_board_FX_BTC_JPY";
+const SNAPSHOT: &str = "lightning_board_snapshot_FX_BTC_JPY";
+
+// 板情報のフレームを解析して板情報を取得する
+fn board(channel: &str, bids: Value, asks: Value) -> Board {
+    let mut parser = FrameParser::new(
+        vec![],
+        vec![BOARD.to_string()],
+        vec![SNAPSHOT.to_string()],
+        ClockOffset::default(),
+    );
+    let frame = json!({
+        "jsonrpc": "2.0",
+        "method": "channelMessage",
+        "params": {"channel": channel, "message": {"mid_price": 0, "bids": bids, "asks": asks}},
+    });
+    match parser.parse(&frame.to_string(), ReceiveTime::now(), 1).remove(0) {
+        MarketInfo::Boards(board) => board,
+        _ => panic!("board was not parsed"),
+    }
+}
+
+fn snapshot(bids: Value, asks: Value) -> Board {
+    board(SNAPSHOT, bids, asks)
+}
+
+fn diff(bids: Value, asks: Value) -> Board {
+    board(BOARD, bids, asks)
+}
+
+#[test]
+fn matching_snapshot_has_no_drift() {
+    let mut books = OrderBooks::new();
+    assert!(books
+        .apply(&snapshot(
+            json!([{"price": 100.0, "size": 1.0}, {"price": 99.0, "size": 2.0}]),
+            json!([{"price": 101.0, "size": 1.5}])
+        ))
+        .is_empty());
+    books.apply(&diff(
+        json!([{"price": 100.0, "size": 0}]),
+        json!([{"price": 102.0, "size": 0.5}]),
+    ));
+
+    let events = books.apply(&snapshot(
+        json!([{"price": 99.0, "size": 2.0}]),
+        json!([{"price": 101.0, "size": 1.5}, {"price": 102.0, "size": 0.5}]),
+    ));
+    assert_eq!(events.len(), 1);
+    match &events[0] {
+        BookEvent::Validated(validation) => {
+            assert_eq!(validation.levels, 3);
+            assert!(validation.mismatches.is_empty());
+            assert_eq!(validation.drift, 0.0);
+        }
+        event => panic!("unexpected event {:?}", event),
+    }
+}
+
+#[test]
+fn drifted_book_reports_mismatched_levels() {
+    let mut books = OrderBooks::new();
+    books.set_drift_threshold(0.1);
+    books.apply(&snapshot(
+        json!([{"price": 100.0, "size": 1.0}]),
+        json!([{"price": 101.0, "size": 1.0}]),
+    ));
+    // 差分を取りこぼした場合を想定し、スナップショットと異なる数量にする
+    books.apply(&diff(json!([{"price": 100.0, "size": 3.0}]), json!([])));
+
+    let events = books.apply(&snapshot(
+        json!([{"price": 100.0, "size": 1.0}]),
+        json!([{"price": 101.0, "size": 1.0}]),
+    ));
+    assert_eq!(events.len(), 2);
+    let validation = match &events[0] {
+        BookEvent::Drift(validation) => validation,
+        event => panic!("unexpected event {:?}", event),
+    };
+    assert_eq!(validation.mismatches.len(), 1);
+    assert_eq!(validation.mismatches[0].side, BookSide::Bid);
+    assert_eq!(validation.mismatches[0].price, 100.0);
+    assert_eq!(validation.mismatches[0].local_size, Some(3.0));
+    assert_eq!(validation.mismatches[0].snapshot_size, Some(1.0));
+    assert_eq!(validation.drift, 1.0);
+    assert!(matches!(events[1], BookEvent::Validated(_)));
+
+    // スナップショットで置き換えた後は一致する
+    assert_eq!(books.get(BOARD).unwrap().bids.len(), 1);
+}
+
+#[test]
+fn crossed_book_requests_resync_once() {
+    let mut books = OrderBooks::new();
+    books.apply(&snapshot(
+        json!([{"price": 100.0, "size": 1.0}]),
+        json!([{"price": 101.0, "size": 1.0}]),
+    ));
+
+    let events = books.apply(&diff(json!([{"price": 101.5, "size": 1.0}]), json!([])));
+    assert!(matches!(&events[..], [BookEvent::Inconsistent { channel }] if channel == BOARD));
+    // スナップショットを受信するまで再度は要求しない
+    assert!(books
+        .apply(&diff(json!([{"price": 102.0, "size": 1.0}]), json!([])))
+        .is_empty());
+}
@@ -0,0 +1,89 @@
+mod common;
+
+use std::fs;
+
+use chrono::{NaiveDate, TimeZone, Utc};
+
+use fetch_market_and_order_data::clock::ReceiveTime;
+use fetch_market_and_order_data::latency::ClockOffset;
+use fetch_market_and_order_data::partition::{parse_timezone, DayPartition, LATENCY_FORMAT_VERSION};
+use fetch_market_and_order_data::recorder::Recorder;
+use fetch_market_and_order_data::stream_api::FrameParser;
+
+use common::temp_dir::TempDir;
+
+use serde_json::{json, Value};
+
+const EXECUTIONS: &str = "lightning_executions_FX_BTC_JPY";
+
+// 日本時間4時で区切る
+fn jst_cutoff_4() -> DayPartition {
+    DayPartition::new(parse_timezone("JST").unwrap(), 4).unwrap()
+}
+
+#[test]
+fn parses_timezones() {
+    assert_eq!(parse_timezone("UTC").unwrap().local_minus_utc(), 0);
+    assert_eq!(parse_timezone("Asia/Tokyo").unwrap().local_minus_utc(), 9 * 3600);
+    assert_eq!(parse_timezone("+09:00").unwrap().local_minus_utc(), 9 * 3600);
+    assert_eq!(parse_timezone("-0530").unwrap().local_minus_utc(), -(5 * 3600 + 30 * 60));
+    assert!(parse_timezone("Tokyo").is_err());
+    assert!(parse_timezone("+9").is_err());
+    assert!(DayPartition::new(parse_timezone("UTC").unwrap(), 24).is_err());
+}
+
+#[test]
+fn splits_days_at_cutoff_hour() {
+    let partition = jst_cutoff_4();
+    // 2020-10-01 03:59:59 JST は前日、04:00 JST は当日
+    assert_eq!(partition.date_string(Utc.with_ymd_and_hms(2020, 9, 30, 18, 59, 59).unwrap()), "20200930");
+    assert_eq!(partition.date_string(Utc.with_ymd_and_hms(2020, 9, 30, 19, 0, 0).unwrap()), "20201001");
+
+    let date = NaiveDate::from_ymd_opt(2020, 10, 1).unwrap();
+    assert_eq!(partition.start(date), Utc.with_ymd_and_hms(2020, 9, 30, 19, 0, 0).unwrap());
+    assert_eq!(partition.end(date), Utc.with_ymd_and_hms(2020, 10, 1, 19, 0, 0).unwrap());
+
+    // 既定はUTCの0時で区切る
+    let utc = DayPartition::default();
+    assert_eq!(utc.date_string(Utc.with_ymd_and_hms(2020, 9, 30, 23, 59, 59).unwrap()), "20200930");
+}
+
+#[test]
+fn records_into_partitioned_directories_with_metadata() {
+    let mut parser = FrameParser::new(vec![EXECUTIONS.to_string()], vec![], vec![], ClockOffset::default());
+    let frame = json!({
+        "jsonrpc": "2.0",
+        "method": "channelMessage",
+        "params": {"channel": EXECUTIONS, "message": [
+            {"id": 1, "side": "BUY", "price": 1100000.0, "size": 0.01, "exec_date": "2020-09-30T18:59:59.5Z"},
+            {"id": 2, "side": "SELL", "price": 1100001.0, "size": 0.02, "exec_date": "2020-09-30T19:00:00.5Z"}
+        ]},
+    });
+
+    let output_dir = TempDir::new("partition_record");
+    let mut recorder = Recorder::new(&output_dir.display().to_string(), "bitFlyer");
+    recorder.set_partition(jst_cutoff_4());
+    // 受信時刻は 2020-10-01 04:00:01 JST
+    let receive_nanos = Utc.with_ymd_and_hms(2020, 9, 30, 19, 0, 1).unwrap().timestamp_nanos_opt().unwrap();
+    let receive_time = ReceiveTime::from_nanos(receive_nanos, 0, receive_nanos);
+    for message in parser.parse(&frame.to_string(), receive_time, 1) {
+        recorder.record(&message);
+    }
+
+    let exchange_dir = output_dir.join("bitFlyer");
+    let previous = fs::read_to_string(exchange_dir.join("20200930").join(format!("{}.csv", EXECUTIONS))).unwrap();
+    assert!(previous.starts_with("1601492399 B "));
+    let current = fs::read_to_string(exchange_dir.join("20201001").join(format!("{}.csv", EXECUTIONS))).unwrap();
+    assert!(current.starts_with("1601492400 S "));
+    // 遅延データは受信時刻の日付に分ける
+    assert!(exchange_dir.join("20201001").join(format!("latency_{}.csv", EXECUTIONS)).exists());
+
+    let metadata: Value =
+        serde_json::from_str(&fs::read_to_string(exchange_dir.join("20201001/metadata.json")).unwrap()).unwrap();
+    assert_eq!(metadata["date"], "2020-10-01");
+    assert_eq!(metadata["timezone"], "+09:00");
+    assert_eq!(metadata["cutoff_hour"], 4);
+    assert_eq!(metadata["start"], "2020-09-30T19:00:00.000Z");
+    assert_eq!(metadata["end"], "2020-10-01T19:00:00.000Z");
+    assert_eq!(metadata["latency_format"], LATENCY_FORMAT_VERSION);
+}
@@ -0,0 +1,88 @@
+mod common;
+
+use std::fs;
+use std::path::PathBuf;
+
+use chrono::NaiveDate;
+
+use fetch_market_and_order_data::clock::ReceiveTime;
+use fetch_market_and_order_data::latency::ClockOffset;
+use fetch_market_and_order_data::path_template::{PathFields, PathTemplate};
+use fetch_market_and_order_data::product::ProductRegistry;
+use fetch_market_and_order_data::recorder::Recorder;
+use fetch_market_and_order_data::stream_api::FrameParser;
+
+use common::temp_dir::TempDir;
+
+use serde_json::json;
+
+const EXECUTIONS: &str = "lightning_executions_FX_BTC_JPY";
+
+#[test]
+fn rejects_invalid_templates() {
+    for template in [
+        "",
+        "/{exchange}/{date}/{name}.{ext}",
+        "{exchange}/../{date}/{name}.{ext}",
+        "{exchange}/{date}/{name}.{extension}",
+        "{exchange}/{date/{name}.{ext}",
+        "{exchange}/{date}}/{name}.{ext}",
+        // 日付毎に分かれない
+        "{exchange}/{yyyy}/{mm}/{name}.{ext}",
+        // ファイル毎に分かれない
+        "{exchange}/{date}/{kind}.{ext}",
+    ] {
+        assert!(template.parse::<PathTemplate>().is_err(), "{}", template);
+    }
+    assert!("{exchange}/{date}/{name}.{ext}".parse::<PathTemplate>().is_ok());
+    assert!("exchange={exchange}/product={product}/{kind}/{yyyy}/{mm}/{dd}.{ext}".parse::<PathTemplate>().is_ok());
+}
+
+#[test]
+fn renders_placeholders() {
+    let registry = ProductRegistry::bitflyer();
+    let fields = PathFields {
+        exchange: "bitFlyer",
+        date: NaiveDate::from_ymd_opt(2020, 10, 1).unwrap(),
+        product_code: "ETH_BTC",
+        product: registry.get("ETH_BTC"),
+        channel: "lightning_executions_ETH_BTC",
+        kind: "latency_executions",
+        name: "latency_lightning_executions_ETH_BTC",
+        ext: "csv",
+    };
+    assert_eq!(
+        PathTemplate::default().render(&fields),
+        PathBuf::from("bitFlyer/20201001/latency_lightning_executions_ETH_BTC.csv")
+    );
+    let template: PathTemplate = "{exchange}/{base}_{quote}/{product}/{kind}/{yyyy}/{mm}/{dd}.{ext}".parse().unwrap();
+    assert_eq!(
+        template.render(&fields),
+        PathBuf::from("bitFlyer/ETH_BTC/ETH_BTC/latency_executions/2020/10/01.csv")
+    );
+}
+
+#[test]
+fn records_with_hive_style_template() {
+    let mut parser = FrameParser::new(vec![EXECUTIONS.to_string()], vec![], vec![], ClockOffset::default());
+    let frame = json!({
+        "jsonrpc": "2.0",
+        "method": "channelMessage",
+        "params": {"channel": EXECUTIONS, "message": [
+            {"id": 1, "side": "BUY", "price": 1100000.0, "size": 0.01, "exec_date": "2020-10-01T00:00:00.5Z"}
+        ]},
+    });
+
+    let output_dir = TempDir::new("path_template_record");
+    let mut recorder = Recorder::new(&output_dir.display().to_string(), "bitFlyer");
+    recorder.set_path_template("{exchange}/product={product}/kind={kind}/{yyyy}/{mm}/{dd}.{ext}".parse().unwrap());
+    for message in parser.parse(&frame.to_string(), ReceiveTime::now(), 1) {
+        recorder.record(&message);
+    }
+
+    let product_dir = output_dir.join("bitFlyer").join("product=FX_BTC_JPY");
+    let executions = fs::read_to_string(product_dir.join("kind=executions/2020/10/01.csv")).unwrap();
+    assert!(executions.starts_with("1601510400 B "));
+    assert!(product_dir.join("kind=metadata/2020/10/01.json").exists());
+    assert!(product_dir.join("kind=latency_executions").exists());
+}
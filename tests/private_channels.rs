@@ -0,0 +1,198 @@
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::TryRecvError;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use fetch_market_and_order_data::auth::{authenticate, sign, AuthError, Credentials, AUTH_TIMEOUT};
+use fetch_market_and_order_data::clock::ReceiveTime;
+use fetch_market_and_order_data::product::ProductRegistry;
+use fetch_market_and_order_data::redundant::RedundantWebsocket;
+use fetch_market_and_order_data::stream_api::{
+    parse_order_events, BfWebsocket, ConnectError, MarketInfo, OrderEventKind, OrderEventType, Side,
+    StreamError, CHILD_ORDER_EVENTS, PARENT_ORDER_EVENTS,
+};
+
+use serde_json::{from_str, json, Value};
+use tungstenite::{accept, client, Message};
+use url::Url;
+
+const API_KEY: &str = "test-key";
+const API_SECRET: &str = "test-secret";
+
+// 認証リクエストの署名を検証し、結果を返すローカルのWebSocketサーバーを起動する
+fn spawn_auth_server() -> (String, thread::JoinHandle<bool>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let handle = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut socket = accept(stream).unwrap();
+        let text = match socket.read_message().unwrap() {
+            Message::Text(text) => text,
+            message => panic!("unexpected message: {:?}", message),
+        };
+        let v: Value = from_str(&text).unwrap();
+        assert_eq!(v["method"], "auth");
+        assert_eq!(v["params"]["api_key"], API_KEY);
+
+        let params = &v["params"];
+        let expected = sign(
+            API_SECRET,
+            params["timestamp"].as_i64().unwrap(),
+            params["nonce"].as_str().unwrap(),
+        );
+        let verified = params["signature"].as_str() == Some(expected.as_str());
+        let response = if verified {
+            json!({"jsonrpc": "2.0", "id": v["id"], "result": true})
+        } else {
+            json!({"jsonrpc": "2.0", "id": v["id"], "error": {"code": -32000, "message": "invalid signature"}})
+        };
+        socket.write_message(Message::Text(response.to_string())).unwrap();
+        verified
+    });
+    (format!("ws://{}", addr), handle)
+}
+
+fn connect_and_authenticate(credentials: &Credentials) -> (Result<(), AuthError>, bool) {
+    let (url, server) = spawn_auth_server();
+    let url = Url::parse(&url).unwrap();
+    let stream = TcpStream::connect(url.socket_addrs(|| None).unwrap()[0]).unwrap();
+    let (mut socket, _) = client(url, stream).unwrap();
+    let result = authenticate(&mut socket, credentials, AUTH_TIMEOUT);
+    (result, server.join().unwrap())
+}
+
+// 認証リクエストを受信しても応答しないローカルのWebSocketサーバーを起動する
+fn spawn_silent_server() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut socket = accept(stream).unwrap();
+        while socket.read_message().is_ok() {}
+    });
+    format!("ws://{}", addr)
+}
+
+#[test]
+fn authenticate_with_valid_signature() {
+    let (result, verified) = connect_and_authenticate(&Credentials::new(API_KEY, API_SECRET));
+    assert!(verified);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn authenticate_with_wrong_secret_is_rejected() {
+    let (result, verified) = connect_and_authenticate(&Credentials::new(API_KEY, "wrong-secret"));
+    assert!(!verified);
+    assert!(matches!(result, Err(AuthError::Rejected(_))));
+}
+
+#[test]
+fn authenticate_times_out_without_response() {
+    let url = Url::parse(&spawn_silent_server()).unwrap();
+    let stream = TcpStream::connect(url.socket_addrs(|| None).unwrap()[0]).unwrap();
+    stream.set_read_timeout(Some(Duration::from_millis(10))).unwrap();
+    let (mut socket, _) = client(url, stream).unwrap();
+
+    let started = Instant::now();
+    let result = authenticate(&mut socket, &Credentials::new(API_KEY, API_SECRET), Duration::from_millis(200));
+    assert!(matches!(result, Err(AuthError::Timeout)));
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn reports_rejected_authentication_and_disconnects() {
+    let (url, server) = spawn_auth_server();
+    let mut bf = BfWebsocket::new();
+    bf.set_end_point(&url);
+    bf.set_credentials(Credentials::new(API_KEY, "wrong-secret"));
+    assert!(matches!(bf.on_connect(), Err(ConnectError::Auth(AuthError::Rejected(_)))));
+    assert!(!server.join().unwrap());
+
+    // 認証に失敗した接続はプライベートチャンネルなしで受信を続けず、エラーを配信して切断とする
+    let (url, server) = spawn_auth_server();
+    let products = ProductRegistry::bitflyer().resolve(&["FX_BTC_JPY"]).unwrap();
+    let connect = |products| {
+        let mut bf = BfWebsocket::new();
+        bf.set_end_point(&url);
+        bf.set_products(products);
+        bf.set_credentials(Credentials::new(API_KEY, "wrong-secret"));
+        bf
+    };
+    let mut bf = RedundantWebsocket::start(connect, products, 1, Duration::from_millis(50));
+    assert!(!server.join().unwrap());
+    match bf.on_message() {
+        Ok(MarketInfo::Error(StreamError::Auth { message })) => assert!(message.contains("invalid signature")),
+        _ => panic!("Authentication error was not received"),
+    }
+    assert!(matches!(bf.on_message(), Err(TryRecvError::Disconnected)));
+}
+
+#[test]
+fn parse_child_order_events() {
+    let message = json!([
+        {
+            "product_code": "FX_BTC_JPY",
+            "child_order_id": "JFX20201001-000001-000001F",
+            "child_order_acceptance_id": "JRF20201001-000001-000001",
+            "event_date": "2020-10-01T00:00:00.1234567Z",
+            "event_type": "EXECUTION",
+            "exec_id": 123456789,
+            "side": "BUY",
+            "price": 1100000,
+            "size": 0.01,
+            "commission": 0,
+            "sfd": 0
+        },
+        {
+            "product_code": "FX_BTC_JPY",
+            "child_order_id": "JFX20201001-000001-000002F",
+            "child_order_acceptance_id": "JRF20201001-000001-000002",
+            "event_date": "2020-10-01T00:00:01Z",
+            "event_type": "ORDER_FAILED",
+            "reason": "INSUFFICIENT_FUND"
+        }
+    ]);
+    let events = parse_order_events(CHILD_ORDER_EVENTS, &message, ReceiveTime::now(), 1);
+    assert_eq!(events.len(), 2);
+
+    assert_eq!(events[0].kind, OrderEventKind::ChildOrder);
+    assert_eq!(events[0].event_type, OrderEventType::Execution);
+    assert_eq!(events[0].side, Side::Buy);
+    assert_eq!(events[0].exec_id, Some(123456789));
+    assert_eq!(events[0].price, Some(1100000.0));
+    assert_eq!(events[0].order_acceptance_id, "JRF20201001-000001-000001");
+
+    assert_eq!(events[1].event_type, OrderEventType::OrderFailed);
+    assert_eq!(events[1].reason.as_deref(), Some("INSUFFICIENT_FUND"));
+    assert_eq!(events[1].price, None);
+}
+
+#[test]
+fn parse_parent_order_events() {
+    let message = json!([{
+        "product_code": "BTC_JPY",
+        "parent_order_id": "JCP20201001-000001-000001",
+        "parent_order_acceptance_id": "JRF20201001-000001-000003",
+        "event_date": "2020-10-01T00:00:00Z",
+        "event_type": "TRIGGER",
+        "parent_order_type": "IFD",
+        "child_order_type": "LIMIT",
+        "parameter_index": 2,
+        "child_order_acceptance_id": "JRF20201001-000001-000004",
+        "side": "SELL",
+        "price": 1200000,
+        "size": 0.02
+    }]);
+    let events = parse_order_events(PARENT_ORDER_EVENTS, &message, ReceiveTime::now(), 1);
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].kind, OrderEventKind::ParentOrder);
+    assert_eq!(events[0].event_type, OrderEventType::Trigger);
+    assert_eq!(events[0].side, Side::Sell);
+    assert_eq!(events[0].order_type.as_deref(), Some("IFD"));
+    assert_eq!(events[0].parameter_index, Some(2));
+    assert_eq!(
+        events[0].child_order_acceptance_id.as_deref(),
+        Some("JRF20201001-000001-000004")
+    );
+}
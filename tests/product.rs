@@ -0,0 +1,57 @@
+use fetch_market_and_order_data::product::{product_code, ChannelKind, ProductRegistry, UnknownProduct};
+use fetch_market_and_order_data::stream_api::BfWebsocket;
+
+#[test]
+fn derives_channels_from_product() {
+    let registry = ProductRegistry::bitflyer();
+    let product = registry.get("ETH_BTC").unwrap();
+    assert_eq!(product.get_tick_size(), 0.00001);
+    assert_eq!(product.get_currency_pair(), "ETH/BTC");
+    assert_eq!(product.channel(ChannelKind::Executions), "lightning_executions_ETH_BTC");
+    assert_eq!(product.channel(ChannelKind::Board), "lightning_board_ETH_BTC");
+    assert_eq!(product.channel(ChannelKind::BoardSnapshot), "lightning_board_snapshot_ETH_BTC");
+}
+
+#[test]
+fn splits_channel_into_kind_and_product() {
+    assert_eq!(
+        ChannelKind::split("lightning_board_snapshot_FX_BTC_JPY"),
+        Some((ChannelKind::BoardSnapshot, "FX_BTC_JPY"))
+    );
+    assert_eq!(
+        ChannelKind::split("lightning_board_FX_BTC_JPY"),
+        Some((ChannelKind::Board, "FX_BTC_JPY"))
+    );
+    assert_eq!(product_code("lightning_executions_BTC_JPY"), "BTC_JPY");
+    assert_eq!(ChannelKind::split("child_order_events"), None);
+}
+
+#[test]
+fn resolves_products_in_order() {
+    let registry = ProductRegistry::bitflyer();
+    let products = registry.resolve(&["ETH_JPY", "FX_BTC_JPY"]).unwrap();
+    let codes: Vec<_> = products.iter().map(|product| product.get_code()).collect();
+    assert_eq!(codes, ["ETH_JPY", "FX_BTC_JPY"]);
+    assert_eq!(
+        registry.resolve(&["ETH_JPY", "DOGE_JPY"]),
+        Err(UnknownProduct(String::from("DOGE_JPY")))
+    );
+}
+
+#[test]
+fn subscribes_channels_of_selected_products() {
+    let registry = ProductRegistry::bitflyer();
+    let mut bf = BfWebsocket::new();
+    assert_eq!(
+        bf.get_public_channels(),
+        ["lightning_executions_FX_BTC_JPY", "lightning_executions_BTC_JPY"]
+    );
+
+    bf.set_products(registry.resolve(&["ETH_JPY"]).unwrap());
+    bf.set_boards(true);
+    assert_eq!(
+        bf.get_public_channels(),
+        ["lightning_executions_ETH_JPY", "lightning_board_ETH_JPY"]
+    );
+    assert_eq!(bf.get_public_snapshot_channels(), ["lightning_board_snapshot_ETH_JPY"]);
+}
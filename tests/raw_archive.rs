@@ -0,0 +1,129 @@
+mod common;
+
+use std::fs;
+use std::time::Duration;
+
+use fetch_market_and_order_data::clock::ReceiveTime;
+use fetch_market_and_order_data::raw_archive::{repair_archive, replay, RawArchive, RawFrame, RawFrameReader};
+use fetch_market_and_order_data::stream_api::{BfWebsocket, MarketInfo};
+
+use common::temp_dir::TempDir;
+
+use serde_json::json;
+
+#[test]
+fn raw_frames_round_trip_and_replay() {
+    let dir = TempDir::new("raw_archive");
+    let path = dir.join("raw.log.gz");
+
+    let execution = json!({
+        "jsonrpc": "2.0",
+        "method": "channelMessage",
+        "params": {
+            "channel": "lightning_executions_FX_BTC_JPY",
+            "message": [
+                {"id": 2, "side": "SELL", "price": 1100001.0, "size": 0.02,
+                 "exec_date": "2020-10-01T00:00:01.5Z"},
+                {"id": 1, "side": "BUY", "price": 1100000.0, "size": 0.01,
+                 "exec_date": "2020-10-01T00:00:00.5Z"}
+            ]
+        }
+    })
+    .to_string();
+    // 改行を含むフレームやレスポンスもそのまま記録される
+    let response = String::from("{\"jsonrpc\":\"2.0\",\n\"id\":1,\"result\":true}");
+
+    // 2回に分けて書き込み、複数のgzipメンバーになっても読み込めることを確認する
+    let mut archive = RawArchive::new();
+    archive
+        .write(&path, &RawFrame::new(&response, ReceiveTime::now(), 1))
+        .unwrap();
+    archive.finish().unwrap();
+    archive
+        .write(&path, &RawFrame::new(&execution, ReceiveTime::now(), 2))
+        .unwrap();
+    archive.finish().unwrap();
+
+    let frames: Vec<RawFrame> = RawFrameReader::open(&path)
+        .unwrap()
+        .map(|frame| frame.unwrap())
+        .collect();
+    assert_eq!(frames.len(), 2);
+    assert_eq!(frames[0].get_text(), response);
+    assert_eq!(frames[0].sequence, 1);
+    assert_eq!(frames[1].get_text(), execution);
+    assert_eq!(frames[1].sequence, 2);
+
+    let mut parser = BfWebsocket::new().frame_parser();
+    let mut executions = Vec::new();
+    let mut latencies = 0;
+    let count = replay(&path, &mut parser, |message| match message {
+        MarketInfo::Executions(execution) => executions.push(execution),
+        MarketInfo::LatencyExchange(_) => latencies += 1,
+        _ => {}
+    })
+    .unwrap();
+    assert_eq!(count, 2);
+    assert_eq!(latencies, 2);
+    assert_eq!(executions.len(), 2);
+    assert_eq!(executions[0].get_price(), 1100000.0);
+    assert_eq!(executions[1].get_price(), 1100001.0);
+    assert!(executions.iter().all(|execution| execution.get_sequence() == 2));
+}
+
+#[test]
+fn appends_after_unfinished_member_and_replays() {
+    let dir = TempDir::new("raw_archive_unfinished");
+    let path = dir.join("raw.log.gz");
+    let frame = |sequence: u64| RawFrame::new(&format!("{{\"frame\":{}}}", sequence), ReceiveTime::now(), sequence);
+
+    // 1つ目のメンバーは閉じ、2つ目のメンバーは書き出しただけで異常終了する
+    let mut archive = RawArchive::new();
+    archive.write(&path, &frame(1)).unwrap();
+    archive.finish().unwrap();
+    archive.write(&path, &frame(2)).unwrap();
+    archive.write(&path, &frame(3)).unwrap();
+    archive.flush().unwrap();
+    std::mem::forget(archive);
+    // 書き込み途中で切れたデータ
+    let mut bytes = fs::read(&path).unwrap();
+    bytes.extend_from_slice(&[0x1f, 0x8b, 0x08]);
+    fs::write(&path, &bytes).unwrap();
+    assert!(RawFrameReader::open(&path).unwrap().any(|frame| frame.is_err()));
+
+    // 再起動後の追記の前に閉じられていないメンバーを閉じ直す
+    assert!(repair_archive(&path).unwrap());
+    let mut archive = RawArchive::new();
+    archive.write(&path, &frame(4)).unwrap();
+    archive.finish().unwrap();
+
+    let sequences: Vec<u64> = RawFrameReader::open(&path)
+        .unwrap()
+        .map(|frame| frame.unwrap().sequence)
+        .collect();
+    assert_eq!(sequences, vec![1, 2, 3, 4]);
+    let mut parser = BfWebsocket::new().frame_parser();
+    assert_eq!(replay(&path, &mut parser, |_| {}).unwrap(), 4);
+    assert!(!repair_archive(&path).unwrap());
+}
+
+#[test]
+fn closes_members_periodically() {
+    let dir = TempDir::new("raw_archive_member");
+    let path = dir.join("raw.log.gz");
+    let frame = RawFrame::new("{}", ReceiveTime::now(), 1);
+
+    let mut archive = RawArchive::new();
+    archive.set_member_interval(Duration::from_secs(60));
+    archive.write(&path, &frame).unwrap();
+    // 間隔が経つ前は書き出すだけ、経った後はメンバーを閉じる
+    archive.tick(frame.receive_time.wall + chrono::Duration::seconds(30)).unwrap();
+    archive.tick(frame.receive_time.wall + chrono::Duration::seconds(60)).unwrap();
+    archive.write(&path, &frame).unwrap();
+    archive.tick(frame.receive_time.wall + chrono::Duration::seconds(200)).unwrap();
+    std::mem::forget(archive);
+
+    // 異常終了しても閉じたメンバーはそのまま読み込める
+    let frames: Vec<RawFrame> = RawFrameReader::open(&path).unwrap().map(|frame| frame.unwrap()).collect();
+    assert_eq!(frames.len(), 2);
+}
@@ -0,0 +1,214 @@
+mod common;
+
+use std::cell::Cell;
+use std::env;
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use fetch_market_and_order_data::clock::ReceiveTime;
+use fetch_market_and_order_data::latency::ClockOffset;
+use fetch_market_and_order_data::product::ProductRegistry;
+use fetch_market_and_order_data::redundant::{RedundantWebsocket, StreamMerger};
+use fetch_market_and_order_data::stream_api::{BfWebsocket, FrameParser, MarketInfo};
+
+use common::mock_server::{MockServer, Script};
+
+use serde_json::json;
+
+const EXECUTIONS: &str = "lightning_executions_FX_BTC_JPY";
+
+fn fixture(name: &str) -> Script {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name);
+    Script::from_file(path).unwrap()
+}
+
+// 受信時刻を指定して約定データを解析する
+fn parse(parser: &mut FrameParser, id: u64, receive_nanos: i64) -> Vec<MarketInfo> {
+    let frame = json!({
+        "jsonrpc": "2.0",
+        "method": "channelMessage",
+        "params": {"channel": EXECUTIONS, "message": [
+            {"id": id, "side": "BUY", "price": 1100000.0, "size": 0.01, "exec_date": "2020-10-01T00:00:00.5Z"}
+        ]},
+    });
+    let receive_time = ReceiveTime::from_nanos(receive_nanos, receive_nanos as u64, receive_nanos);
+    parser.parse(&frame.to_string(), receive_time, 1)
+}
+
+#[test]
+fn merges_executions_preferring_earliest_receive_time() {
+    let mut parser = FrameParser::new(vec![EXECUTIONS.to_string()], vec![], vec![], ClockOffset::default());
+    let mut merger = StreamMerger::new(Duration::from_millis(100));
+    let now = Instant::now();
+
+    let mut forwarded = Vec::new();
+    // 優先度の低い接続が先に受信したが、受信時刻は優先度の高い接続の方が遅い
+    for message in parse(&mut parser, 1, 2_000) {
+        forwarded.extend(merger.push(message, false, now));
+    }
+    for message in parse(&mut parser, 1, 3_000) {
+        forwarded.extend(merger.push(message, true, now));
+    }
+    // 約定以外は優先度の高い接続のもののみ配信する
+    assert_eq!(forwarded.len(), 1);
+    assert!(matches!(forwarded[0], MarketInfo::LatencyExchange(_)));
+
+    assert!(merger.pop_due(now).is_empty());
+    let merged = merger.pop_due(now + Duration::from_millis(100));
+    assert_eq!(merged.len(), 1);
+    match &merged[0] {
+        MarketInfo::Executions(execution) => assert_eq!(execution.get_receive_time().monotonic_nanos(), 2_000),
+        _ => panic!("Execution was not merged"),
+    }
+
+    // 配信済みの約定は再び配信しない
+    for message in parse(&mut parser, 1, 4_000) {
+        merger.push(message, true, now);
+    }
+    assert!(merger.pop_due(now + Duration::from_secs(1)).is_empty());
+}
+
+#[test]
+fn records_once_from_two_connections() {
+    let server = MockServer::start(vec![fixture("executions.jsonl"), fixture("executions.jsonl")]);
+    let products = ProductRegistry::bitflyer().resolve(&["FX_BTC_JPY", "BTC_JPY"]).unwrap();
+    let end_point = server.end_point();
+    let connect = |products| {
+        let mut bf = BfWebsocket::new();
+        bf.set_end_point(&end_point);
+        bf.set_products(products);
+        bf
+    };
+    let mut bf = RedundantWebsocket::start(connect, products, 2, Duration::from_millis(50));
+
+    // 両方の接続が切断されるまで受信する
+    let deadline = Instant::now() + Duration::from_secs(10);
+    let mut ids = Vec::new();
+    loop {
+        assert!(Instant::now() < deadline, "Connections were not closed");
+        match bf.on_message() {
+            Ok(MarketInfo::Executions(execution)) => ids.push(execution.get_id()),
+            Ok(_) => {}
+            Err(std::sync::mpsc::TryRecvError::Empty) => sleep(Duration::from_millis(1)),
+            Err(std::sync::mpsc::TryRecvError::Disconnected) => break,
+        }
+    }
+    assert_eq!(server.connections(), 2);
+    ids.sort_unstable();
+    assert_eq!(ids, vec![1, 2, 3]);
+}
+
+#[test]
+fn reconnects_connection_dropped_without_close_frame() {
+    // 一方の接続はCloseフレームを送らずにTCP接続を切断し、再接続後に次の約定を配信する
+    let dropped = Script::parse(&format!(
+        "{}\n{}\n{}",
+        r#"{"action": "await_subscribe", "channel": "lightning_executions_FX_BTC_JPY"}"#,
+        r#"{"action": "channel_message", "channel": "lightning_executions_FX_BTC_JPY", "message": [{"id": 1, "side": "BUY", "price": 1100000.0, "size": 0.01, "exec_date": "2020-10-01T00:00:00.5Z"}]}"#,
+        r#"{"action": "drop"}"#,
+    ))
+    .unwrap();
+    let reconnected = Script::parse(&format!(
+        "{}\n{}",
+        r#"{"action": "await_subscribe", "channel": "lightning_executions_FX_BTC_JPY"}"#,
+        r#"{"action": "channel_message", "channel": "lightning_executions_FX_BTC_JPY", "message": [{"id": 2, "side": "SELL", "price": 1100001.0, "size": 0.02, "exec_date": "2020-10-01T00:00:01.5Z"}]}"#,
+    ))
+    .unwrap();
+    let server = MockServer::start(vec![dropped.clone(), dropped, reconnected]);
+    let products = ProductRegistry::bitflyer().resolve(&["FX_BTC_JPY"]).unwrap();
+    let end_point = server.end_point();
+    let connect = |products| {
+        let mut bf = BfWebsocket::new();
+        bf.set_end_point(&end_point);
+        bf.set_products(products);
+        bf
+    };
+    let mut bf = RedundantWebsocket::start(connect, products, 2, Duration::from_millis(50));
+    bf.set_reconnect_delay(Duration::from_millis(100));
+
+    let deadline = Instant::now() + Duration::from_secs(10);
+    let mut ids = Vec::new();
+    while !ids.contains(&2) {
+        assert!(Instant::now() < deadline, "Dropped connection was not reconnected");
+        match bf.on_message() {
+            Ok(MarketInfo::Executions(execution)) => ids.push(execution.get_id()),
+            Ok(_) => {}
+            Err(_) => sleep(Duration::from_millis(1)),
+        }
+    }
+    bf.close_thread();
+    assert!(3 <= server.connections());
+    assert_eq!(ids, vec![1, 2]);
+}
+
+#[test]
+fn retries_connection_that_failed_to_connect() {
+    let execution = |id: u64| {
+        Script::parse(&format!(
+            "{}\n{}",
+            r#"{"action": "await_subscribe", "channel": "lightning_executions_FX_BTC_JPY"}"#,
+            json!({
+                "action": "channel_message",
+                "channel": EXECUTIONS,
+                "message": [{"id": id, "side": "BUY", "price": 1100000.0, "size": 0.01, "exec_date": "2020-10-01T00:00:00.5Z"}],
+            }),
+        ))
+        .unwrap()
+    };
+    let server = MockServer::start(vec![execution(1), execution(2)]);
+    // 接続を受け付けないエンドポイント
+    let refused = {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        format!("ws://{}", listener.local_addr().unwrap())
+    };
+    let products = ProductRegistry::bitflyer().resolve(&["FX_BTC_JPY"]).unwrap();
+    let end_point = server.end_point();
+    // 2つ目の接続は開始時と最初の再接続に失敗する
+    let attempts = Cell::new(0);
+    let connect = |products| {
+        let attempt = attempts.get();
+        attempts.set(attempt + 1);
+        let mut bf = BfWebsocket::new();
+        bf.set_end_point(if attempt == 1 || attempt == 2 { &refused } else { &end_point });
+        bf.set_products(products);
+        bf
+    };
+    let mut bf = RedundantWebsocket::start(connect, products, 2, Duration::from_millis(50));
+    bf.set_reconnect_delay(Duration::from_millis(100));
+
+    let deadline = Instant::now() + Duration::from_secs(20);
+    let mut ids = Vec::new();
+    while !ids.contains(&2) {
+        assert!(Instant::now() < deadline, "Failed connection was not retried");
+        match bf.on_message() {
+            Ok(MarketInfo::Executions(execution)) => ids.push(execution.get_id()),
+            Ok(_) => {}
+            Err(_) => sleep(Duration::from_millis(1)),
+        }
+    }
+    bf.close_thread();
+    assert_eq!(attempts.get(), 4);
+    assert_eq!(server.connections(), 2);
+    assert_eq!(ids, vec![1, 2]);
+}
+
+#[test]
+fn reports_failed_connection_as_disconnected() {
+    let refused = {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        format!("ws://{}", listener.local_addr().unwrap())
+    };
+    let products = ProductRegistry::bitflyer().resolve(&["FX_BTC_JPY"]).unwrap();
+    let connect = |products| {
+        let mut bf = BfWebsocket::new();
+        bf.set_end_point(&refused);
+        bf.set_products(products);
+        bf
+    };
+    let mut bf = RedundantWebsocket::start(connect, products, 1, Duration::from_millis(50));
+    assert!(matches!(bf.on_message(), Err(std::sync::mpsc::TryRecvError::Disconnected)));
+}
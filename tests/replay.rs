@@ -0,0 +1,83 @@
+mod common;
+
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use chrono::{TimeZone, Utc};
+
+use fetch_market_and_order_data::clock::ReceiveTime;
+use fetch_market_and_order_data::latency::ClockOffset;
+use fetch_market_and_order_data::raw_archive::RawFrame;
+use fetch_market_and_order_data::recorder::Recorder;
+use fetch_market_and_order_data::stream_api::{FrameParser, MarketInfo};
+
+use common::temp_dir::TempDir;
+
+use serde_json::json;
+
+const EXECUTIONS: &str = "lightning_executions_FX_BTC_JPY";
+
+// 受信したフレームと解析した約定データを記録済みの日付を用意する
+fn record_day(output_dir: &Path) {
+    let mut parser = FrameParser::new(vec![EXECUTIONS.to_string()], vec![], vec![], ClockOffset::default());
+    let mut recorder = Recorder::new(&output_dir.display().to_string(), "bitFlyer");
+    let receive = Utc.with_ymd_and_hms(2020, 10, 1, 0, 0, 2).unwrap().timestamp_nanos_opt().unwrap();
+    for (sequence, id) in (1..=3).enumerate() {
+        let frame = json!({
+            "jsonrpc": "2.0",
+            "method": "channelMessage",
+            "params": {"channel": EXECUTIONS, "message": [
+                {"id": id, "side": "BUY", "price": 1100000.0 + id as f64, "size": 0.01, "exec_date": "2020-10-01T00:00:01Z"}
+            ]},
+        })
+        .to_string();
+        let receive_time = ReceiveTime::from_nanos(receive + sequence as i64, sequence as u64, receive + sequence as i64);
+        recorder.record(&MarketInfo::RawFrames(RawFrame::new(&frame, receive_time, sequence as u64)));
+        for message in parser.parse(&frame, receive_time, sequence as u64) {
+            recorder.record(&message);
+        }
+    }
+    recorder.finish();
+}
+
+fn run_replay(output_dir: &Path, raw: &Path, overwrite: bool) -> bool {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_fetch-market-and-order-data"));
+    command.arg("replay").arg("-o").arg(output_dir).arg(raw);
+    if overwrite {
+        command.arg("--overwrite");
+    }
+    command.status().unwrap().success()
+}
+
+fn executions(output_dir: &Path) -> String {
+    fs::read_to_string(output_dir.join("bitFlyer/20201001").join(format!("{}.csv", EXECUTIONS))).unwrap()
+}
+
+#[test]
+fn replays_recorded_day_into_new_directory() {
+    let dir = TempDir::new("replay_recorded_day");
+    let recorded = dir.join("recorded");
+    record_day(&recorded);
+    let raw = recorded.join("bitFlyer/20201001/raw.log.gz");
+    let expected = executions(&recorded);
+    assert_eq!(expected.lines().count(), 3);
+
+    // 記録済みの約定IDに関わらず、全ての約定を書き込む
+    let replayed = dir.join("replayed");
+    assert!(run_replay(&replayed, &raw, false));
+    assert_eq!(executions(&replayed), expected);
+
+    // 空でない出力先には書き込まない
+    assert!(!run_replay(&replayed, &raw, false));
+    assert_eq!(executions(&replayed), expected);
+
+    // 上書きする場合は作り直し、追記しない
+    assert!(run_replay(&replayed, &raw, true));
+    assert_eq!(executions(&replayed), expected);
+
+    // 再解析するファイルを含む出力先は上書きしない
+    assert!(!run_replay(&recorded, &raw, true));
+    assert!(raw.exists());
+    assert_eq!(executions(&recorded), expected);
+}
@@ -0,0 +1,76 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use fetch_market_and_order_data::rest_api::{get, get_json, RestError};
+
+use serde_json::json;
+
+// リクエストのヘッダーまで読み込み、指定したレスポンスを返すローカルのHTTPサーバーを起動する
+// keep_openの場合は、レスポンスを返した後もクライアントが切断するまで接続を閉じない
+fn spawn_server(response: &'static [u8], keep_open: bool) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream);
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line).unwrap() == 0 || line == "\r\n" {
+                break;
+            }
+        }
+        let mut stream = reader.into_inner();
+        stream.write_all(response).unwrap();
+        stream.flush().unwrap();
+        if keep_open {
+            let _ = stream.read_to_end(&mut Vec::new());
+        }
+    });
+    format!("http://{}/v1/markets", addr)
+}
+
+#[test]
+fn reads_body_of_content_length_without_waiting_for_close() {
+    let url = spawn_server(b"HTTP/1.1 200 OK\r\nContent-Length: 16\r\n\r\n{\"status\": \"ok\"}", true);
+    let started = Instant::now();
+    assert_eq!(get_json(&url).unwrap(), json!({"status": "ok"}));
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn ignores_bytes_after_content_length() {
+    let url = spawn_server(b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\nConnection: close\r\n\r\n{}trailing", false);
+    assert_eq!(get(&url).unwrap(), "{}");
+}
+
+#[test]
+fn reports_truncated_body() {
+    let url = spawn_server(b"HTTP/1.1 200 OK\r\nContent-Length: 100\r\nConnection: close\r\n\r\n{}", false);
+    match get(&url) {
+        Err(RestError::Response(message)) => assert!(message.contains("truncated"), "{}", message),
+        result => panic!("unexpected result {:?}", result),
+    }
+}
+
+#[test]
+fn decodes_chunked_body_until_close() {
+    let url = spawn_server(
+        b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\nConnection: close\r\n\r\n2\r\n{}\r\n0\r\n\r\n",
+        false,
+    );
+    assert_eq!(get(&url).unwrap(), "{}");
+}
+
+#[test]
+fn reports_status_with_body() {
+    let url = spawn_server(b"HTTP/1.1 404 Not Found\r\nContent-Length: 9\r\n\r\nnot found", true);
+    match get(&url) {
+        Err(RestError::Status { code, body }) => {
+            assert_eq!(code, 404);
+            assert_eq!(body, "not found");
+        }
+        result => panic!("unexpected result {:?}", result),
+    }
+}
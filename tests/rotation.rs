@@ -0,0 +1,87 @@
+mod common;
+
+use std::fs;
+
+use chrono::{TimeZone, Utc};
+
+use fetch_market_and_order_data::clock::ReceiveTime;
+use fetch_market_and_order_data::latency::ClockOffset;
+use fetch_market_and_order_data::recorder::Recorder;
+use fetch_market_and_order_data::rotation::{Rotation, Rotator, Segment};
+use fetch_market_and_order_data::stream_api::FrameParser;
+
+use common::temp_dir::TempDir;
+
+use serde_json::json;
+
+const EXECUTIONS: &str = "lightning_executions_FX_BTC_JPY";
+
+#[test]
+fn parses_rotation() {
+    assert_eq!("daily".parse::<Rotation>().unwrap(), Rotation::Daily);
+    assert_eq!("Hourly".parse::<Rotation>().unwrap(), Rotation::Hourly);
+    assert_eq!("1024".parse::<Rotation>().unwrap(), Rotation::Size(1024));
+    assert_eq!("100MB".parse::<Rotation>().unwrap(), Rotation::Size(100 << 20));
+    assert_eq!("2g".parse::<Rotation>().unwrap(), Rotation::Size(2 << 30));
+    assert!("0".parse::<Rotation>().is_err());
+    assert!("weekly".parse::<Rotation>().is_err());
+}
+
+#[test]
+fn rotates_by_size_and_continues_after_restart() {
+    let dir = TempDir::new("rotation_size");
+    let path = dir.join("trades.csv");
+    let day_start = Utc.with_ymd_and_hms(2020, 10, 1, 0, 0, 0).unwrap();
+    let time = |secs| day_start + chrono::Duration::seconds(secs);
+
+    let mut rotator = Rotator::new(Rotation::Size(10));
+    assert_eq!(rotator.path(&path, time(1), day_start, 6), dir.join("trades.0000.csv"));
+    assert_eq!(rotator.path(&path, time(2), day_start, 4), dir.join("trades.0000.csv"));
+    assert_eq!(rotator.path(&path, time(3), day_start, 6), dir.join("trades.0001.csv"));
+    rotator.flush().unwrap();
+
+    let index = fs::read_to_string(dir.join("trades.index.csv")).unwrap();
+    let segments: Vec<Segment> = index.lines().filter_map(Segment::from_csv).collect();
+    assert_eq!(segments.len(), 2);
+    assert_eq!(segments[0].file_name, "trades.0000.csv");
+    assert_eq!((segments[0].first, segments[0].last), (time(1), time(2)));
+    assert_eq!((segments[0].records, segments[0].bytes), (2, 10));
+
+    // 再起動後は書き込み済みの一覧から続ける
+    let mut rotator = Rotator::new(Rotation::Size(10));
+    assert_eq!(rotator.path(&path, time(4), day_start, 4), dir.join("trades.0001.csv"));
+    assert_eq!(rotator.path(&path, time(5), day_start, 4), dir.join("trades.0002.csv"));
+}
+
+#[test]
+fn records_hourly_segments_with_index() {
+    let mut parser = FrameParser::new(vec![EXECUTIONS.to_string()], vec![], vec![], ClockOffset::default());
+    let frame = json!({
+        "jsonrpc": "2.0",
+        "method": "channelMessage",
+        "params": {"channel": EXECUTIONS, "message": [
+            {"id": 1, "side": "BUY", "price": 1100000.0, "size": 0.01, "exec_date": "2020-10-01T00:59:59.5Z"},
+            {"id": 2, "side": "SELL", "price": 1100001.0, "size": 0.02, "exec_date": "2020-10-01T01:00:00.5Z"}
+        ]},
+    });
+
+    let output_dir = TempDir::new("rotation_record");
+    let mut recorder = Recorder::new(&output_dir.display().to_string(), "bitFlyer");
+    recorder.set_rotation(Rotation::Hourly);
+    for message in parser.parse(&frame.to_string(), ReceiveTime::now(), 1) {
+        recorder.record(&message);
+    }
+    recorder.finish();
+
+    let date_dir = output_dir.join("bitFlyer").join("20201001");
+    let first = fs::read_to_string(date_dir.join(format!("{}.0000.csv", EXECUTIONS))).unwrap();
+    assert!(first.starts_with("1601513999 B "));
+    let second = fs::read_to_string(date_dir.join(format!("{}.0001.csv", EXECUTIONS))).unwrap();
+    assert!(second.starts_with("1601514000 S "));
+    assert!(!date_dir.join(format!("{}.csv", EXECUTIONS)).exists());
+
+    let index = fs::read_to_string(date_dir.join(format!("{}.index.csv", EXECUTIONS))).unwrap();
+    let segments: Vec<Segment> = index.lines().filter_map(Segment::from_csv).collect();
+    assert_eq!(segments.iter().map(|segment| segment.number).collect::<Vec<_>>(), vec![0, 1]);
+    assert_eq!(segments[1].first, Utc.timestamp_millis_opt(1601514000500).unwrap());
+}
@@ -0,0 +1,109 @@
+mod common;
+
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use fetch_market_and_order_data::auth::Credentials;
+use fetch_market_and_order_data::product::ProductRegistry;
+use fetch_market_and_order_data::stream_api::{BfWebsocket, MarketInfo, StreamError};
+
+use common::mock_server::{MockServer, Script, ScriptStep};
+
+use serde_json::json;
+
+const FX_BTC_JPY: &str = "lightning_executions_FX_BTC_JPY";
+const BTC_JPY: &str = "lightning_executions_BTC_JPY";
+
+fn connect(server: &MockServer) -> BfWebsocket {
+    let mut bf = BfWebsocket::new();
+    bf.set_end_point(&server.end_point());
+    bf
+}
+
+// サーバーが承認したチャンネルが期待したチャンネルになるまで待つ
+fn wait_subscriptions(bf: &BfWebsocket, expected: &[&str]) {
+    let mut expected: Vec<String> = expected.iter().map(|channel| channel.to_string()).collect();
+    expected.sort();
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while bf.subscriptions() != expected {
+        assert!(Instant::now() < deadline, "Subscriptions were {:?}", bf.subscriptions());
+        sleep(Duration::from_millis(1));
+    }
+}
+
+// エラーを受信するまで待つ
+fn receive_error(bf: &BfWebsocket) -> StreamError {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while Instant::now() < deadline {
+        match bf.on_message() {
+            Ok(MarketInfo::Error(error)) => return error,
+            Ok(_) => {}
+            Err(_) => sleep(Duration::from_millis(1)),
+        }
+    }
+    panic!("Error was not received");
+}
+
+#[test]
+fn numbers_requests_from_one_after_auth() {
+    let server = MockServer::start(vec![Script::default()]);
+    let mut bf = connect(&server);
+    bf.set_credentials(Credentials::new("test-key", "test-secret"));
+    bf.on_connect().unwrap();
+
+    let mut channels = bf.get_public_channels();
+    channels.extend(bf.get_private_channels().iter().cloned());
+    let channels: Vec<&str> = channels.iter().map(String::as_str).collect();
+    wait_subscriptions(&bf, &channels);
+    bf.close_thread();
+
+    // 0は認証リクエストで使用し、購読のリクエストには1から順にIDを付与する
+    let requests = server.requests();
+    assert_eq!(requests[0]["method"], "auth");
+    assert_eq!(requests[0]["id"], 0);
+    let ids: Vec<u64> = requests[1..=channels.len()]
+        .iter()
+        .map(|request| {
+            assert_eq!(request["method"], "subscribe");
+            request["id"].as_u64().unwrap()
+        })
+        .collect();
+    assert_eq!(ids, (1..=channels.len() as u64).collect::<Vec<_>>());
+}
+
+#[test]
+fn reports_response_with_unknown_id() {
+    let response = json!({"jsonrpc": "2.0", "id": 999, "result": true});
+    let server = MockServer::start(vec![Script::new(vec![
+        ScriptStep::AwaitSubscribe(FX_BTC_JPY.to_string()),
+        ScriptStep::AwaitSubscribe(BTC_JPY.to_string()),
+        ScriptStep::Raw(response.to_string()),
+    ])]);
+    let bf = connect(&server);
+    bf.on_connect().unwrap();
+
+    // 対応するリクエストのないレスポンスは購読中のチャンネルを変えない
+    assert!(matches!(receive_error(&bf), StreamError::UnknownResponse { id: 999 }));
+    wait_subscriptions(&bf, &[FX_BTC_JPY, BTC_JPY]);
+    bf.close_thread();
+}
+
+#[test]
+fn tracks_confirmed_subscriptions() {
+    let server = MockServer::start_with_rejected_channels(vec![Script::default()], &[BTC_JPY]);
+    let mut bf = connect(&server);
+    bf.on_connect().unwrap();
+
+    // 拒否されたチャンネルは購読中としない
+    assert!(matches!(receive_error(&bf), StreamError::Subscribe { channel, .. } if channel == BTC_JPY));
+    wait_subscriptions(&bf, &[FX_BTC_JPY]);
+
+    // 購読の停止が承認されたチャンネルは購読中から除く
+    let registry = ProductRegistry::bitflyer();
+    bf.update_products(vec![registry.get("ETH_JPY").unwrap().clone()]);
+    wait_subscriptions(&bf, &["lightning_executions_ETH_JPY"]);
+
+    // 購読を停止して終了した接続は購読中のチャンネルを持たない
+    bf.close_thread();
+    wait_subscriptions(&bf, &[]);
+}
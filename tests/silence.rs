@@ -0,0 +1,66 @@
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use fetch_market_and_order_data::silence::{parse_channel_silence, SilenceAction, SilenceMonitor};
+
+const FX: &str = "lightning_executions_FX_BTC_JPY";
+const ETH: &str = "lightning_executions_ETH_JPY";
+
+fn monitor(start: Instant) -> SilenceMonitor {
+    let thresholds: BTreeMap<_, _> = vec![
+        parse_channel_silence(&format!("{}=10", FX)).unwrap(),
+        parse_channel_silence(&format!("{}=600", ETH)).unwrap(),
+    ]
+    .into_iter()
+    .collect();
+    SilenceMonitor::new(thresholds, start)
+}
+
+#[test]
+fn parses_channel_thresholds() {
+    assert_eq!(
+        parse_channel_silence("lightning_board_BTC_JPY=30"),
+        Ok((String::from("lightning_board_BTC_JPY"), Duration::from_secs(30)))
+    );
+    assert!(parse_channel_silence("lightning_board_BTC_JPY").is_err());
+    assert!(parse_channel_silence("=30").is_err());
+    assert!(parse_channel_silence("lightning_board_BTC_JPY=0").is_err());
+    assert!(parse_channel_silence("lightning_board_BTC_JPY=ten").is_err());
+}
+
+#[test]
+fn resubscribes_silent_channel_while_others_flow() {
+    let start = Instant::now();
+    let mut monitor = monitor(start);
+    let at = |secs| start + Duration::from_secs(secs);
+
+    monitor.on_message(FX, at(5));
+    assert!(monitor.check(at(14)).is_empty());
+    // 閾値が長いチャンネルは受信がなくても対象にならない
+    assert_eq!(monitor.check(at(15)), vec![SilenceAction::Resubscribe(FX.to_string())]);
+    assert!(monitor.check(at(16)).is_empty());
+
+    // 再購読後に受信すれば再接続しない
+    monitor.on_message(FX, at(20));
+    assert!(monitor.check(at(29)).is_empty());
+}
+
+#[test]
+fn reconnects_when_resubscribe_does_not_help() {
+    let start = Instant::now();
+    let mut monitor = monitor(start);
+    let at = |secs| start + Duration::from_secs(secs);
+
+    assert_eq!(monitor.check(at(10)), vec![SilenceAction::Resubscribe(FX.to_string())]);
+    assert!(monitor.check(at(19)).is_empty());
+    assert_eq!(monitor.check(at(20)), vec![SilenceAction::Reconnect(FX.to_string())]);
+    // 監視対象外のチャンネルの受信は影響しない
+    monitor.on_message("lightning_executions_BTC_JPY", at(20));
+    assert_eq!(
+        monitor.check(at(600)),
+        vec![
+            SilenceAction::Resubscribe(ETH.to_string()),
+            SilenceAction::Reconnect(FX.to_string())
+        ]
+    );
+}
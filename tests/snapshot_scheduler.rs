@@ -0,0 +1,47 @@
+use std::time::{Duration, Instant};
+
+use fetch_market_and_order_data::scheduler::{snapshot_channel, ResyncRequests, SnapshotScheduler};
+
+const FX_SNAPSHOT: &str = "lightning_board_snapshot_FX_BTC_JPY";
+const BTC_SNAPSHOT: &str = "lightning_board_snapshot_BTC_JPY";
+
+#[test]
+fn subscribes_on_start_and_after_each_interval() {
+    let mut scheduler = SnapshotScheduler::new(
+        vec![FX_SNAPSHOT.to_string(), BTC_SNAPSHOT.to_string()],
+        Duration::from_secs(60),
+        ResyncRequests::default(),
+    );
+    let start = Instant::now();
+
+    assert_eq!(scheduler.due(start), vec![BTC_SNAPSHOT, FX_SNAPSHOT]);
+    assert!(scheduler.due(start + Duration::from_secs(59)).is_empty());
+    assert_eq!(
+        scheduler.due(start + Duration::from_secs(60)),
+        vec![BTC_SNAPSHOT, FX_SNAPSHOT]
+    );
+}
+
+#[test]
+fn resubscribes_on_resync_request() {
+    let resync = ResyncRequests::default();
+    let mut scheduler = SnapshotScheduler::new(
+        vec![FX_SNAPSHOT.to_string(), BTC_SNAPSHOT.to_string()],
+        Duration::from_secs(60),
+        resync.clone(),
+    );
+    let start = Instant::now();
+    scheduler.due(start);
+
+    resync.request("lightning_board_FX_BTC_JPY");
+    // 購読対象外のチャンネルの要求は無視される
+    resync.request("lightning_board_ETH_JPY");
+    assert_eq!(scheduler.due(start + Duration::from_secs(1)), vec![FX_SNAPSHOT]);
+    assert!(scheduler.due(start + Duration::from_secs(2)).is_empty());
+}
+
+#[test]
+fn derives_snapshot_channel_from_board_channel() {
+    assert_eq!(snapshot_channel("lightning_board_FX_BTC_JPY"), FX_SNAPSHOT);
+    assert_eq!(snapshot_channel(FX_SNAPSHOT), FX_SNAPSHOT);
+}
@@ -0,0 +1,116 @@
+mod common;
+
+use std::fs;
+use std::time::Duration;
+
+use chrono::{NaiveDate, TimeZone, Utc};
+
+use fetch_market_and_order_data::convert::{ExecutionRow, LatencyRow};
+use fetch_market_and_order_data::recorder::Recorder;
+use fetch_market_and_order_data::stats::{daily_stats, DailyStats, Gap};
+use fetch_market_and_order_data::stream_api::Side;
+
+use common::temp_dir::TempDir;
+
+const EXECUTIONS: &str = "lightning_executions_FX_BTC_JPY";
+// 2020-10-01T00:00:00Z
+const DAY_START: i64 = 1601510400;
+
+fn execution(exec_time: i64, side: &str, price: f64, size: f64) -> ExecutionRow {
+    ExecutionRow::parse(&format!("{} {} {} {}", exec_time, side, price, size)).unwrap()
+}
+
+#[test]
+fn computes_volume_vwap_and_intervals() {
+    let date = NaiveDate::from_ymd_opt(2020, 10, 1).unwrap();
+    let executions = vec![
+        execution(DAY_START + 2, "S", 102.0, 1.5),
+        execution(DAY_START, "B", 100.0, 0.5),
+        execution(DAY_START + 3, "N", 101.0, 0.1),
+    ];
+    let latency = vec![
+        LatencyRow::parse("1601510400000000000 12.500 1 1").unwrap(),
+        LatencyRow::parse("1601510400000000000 20.000 2 2").unwrap(),
+    ];
+    let stats = DailyStats::compute(
+        date,
+        EXECUTIONS,
+        &executions,
+        &latency,
+        (DAY_START, DAY_START + 4),
+        Duration::from_secs(60),
+    );
+
+    assert_eq!(stats.trades, 3);
+    assert_eq!((stats.buy_volume, stats.sell_volume, stats.unknown_volume), (0.5, 1.5, 0.1));
+    assert!((stats.vwap.unwrap() - (100.0 * 0.5 + 102.0 * 1.5 + 101.0 * 0.1) / 2.1).abs() < 1e-9);
+    assert_eq!((stats.high, stats.low), (Some(102.0), Some(100.0)));
+    assert_eq!(stats.largest.as_ref().map(|row| row.side), Some(Side::Sell));
+    // 約定日時の順に並べた間隔(2秒, 1秒)
+    let intervals = stats.intervals.unwrap();
+    assert_eq!((intervals.count, intervals.p50, intervals.max), (2, 1, 2));
+    let latency = stats.latency.unwrap();
+    assert_eq!((latency.p50, latency.max), (12_500, 20_000));
+    assert!(stats.gaps.is_empty());
+
+    let json = stats.to_json();
+    assert_eq!(json["largest"]["side"], "S");
+    assert_eq!(json["latency_ms"]["p50"], 12.5);
+    assert!(stats.to_string().contains("largest trade"));
+}
+
+#[test]
+fn detects_gaps_including_day_edges() {
+    let date = NaiveDate::from_ymd_opt(2020, 10, 1).unwrap();
+    let executions = vec![execution(DAY_START + 120, "B", 100.0, 1.0), execution(DAY_START + 130, "S", 100.0, 1.0)];
+    let stats = DailyStats::compute(
+        date,
+        EXECUTIONS,
+        &executions,
+        &[],
+        (DAY_START, DAY_START + 300),
+        Duration::from_secs(60),
+    );
+    assert_eq!(
+        stats.gaps,
+        vec![
+            Gap {
+                start: DAY_START,
+                end: DAY_START + 120
+            },
+            Gap {
+                start: DAY_START + 130,
+                end: DAY_START + 300
+            },
+        ]
+    );
+    assert_eq!(stats.latency, None);
+
+    // 約定がない日は日付全体が途切れ
+    let stats = DailyStats::compute(date, EXECUTIONS, &[], &[], (DAY_START, DAY_START + 86400), Duration::from_secs(60));
+    assert_eq!((stats.trades, stats.vwap, stats.gaps.len()), (0, None, 1));
+}
+
+#[test]
+fn reads_recorded_files_of_date_and_channel() {
+    let output_dir = TempDir::new("stats");
+    let recorder = Recorder::new(&output_dir.display().to_string(), "bitFlyer");
+    let date = NaiveDate::from_ymd_opt(2020, 10, 1).unwrap();
+
+    let executions = recorder.channel_path(date, "", EXECUTIONS);
+    fs::create_dir_all(executions.parent().unwrap()).unwrap();
+    fs::write(&executions, "1601510400 B 100 0.5 1601510400123456789 1 1 11\nbroken\n").unwrap();
+    // 分割したファイルも読み込む
+    let segment = executions.with_file_name(format!("{}.0001.csv", EXECUTIONS));
+    fs::write(&segment, "1601510401 S 102 1.5 1601510401000000001 2 2 12\n").unwrap();
+    let latency = recorder.channel_path(date, "latency_", EXECUTIONS);
+    fs::write(&latency, "1601510400123456789 12.500 1 1\n").unwrap();
+
+    let now = Utc.timestamp_opt(DAY_START + 30, 0).unwrap();
+    let stats = daily_stats(&recorder, EXECUTIONS, date, Duration::from_secs(60), now).unwrap();
+    assert_eq!(stats.trades, 2);
+    assert_eq!(stats.skipped, 1);
+    assert_eq!(stats.latency.map(|latency| latency.count), Some(1));
+    // 記録中の日付は現在時刻までを確認する
+    assert!(stats.gaps.is_empty());
+}
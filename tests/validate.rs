@@ -0,0 +1,165 @@
+mod common;
+
+use std::fs;
+
+use chrono::NaiveDate;
+
+use fetch_market_and_order_data::clock::ReceiveTime;
+use fetch_market_and_order_data::latency::ClockOffset;
+use fetch_market_and_order_data::recorder::Recorder;
+use fetch_market_and_order_data::stream_api::FrameParser;
+use fetch_market_and_order_data::validate::{parse_date, validate_files, validate_range, AnomalyKind, DataKind};
+
+use common::temp_dir::TempDir;
+
+use serde_json::json;
+
+const EXECUTIONS: &str = "lightning_executions_FX_BTC_JPY";
+
+#[test]
+fn parses_dates() {
+    let date = NaiveDate::from_ymd_opt(2020, 10, 1).unwrap();
+    assert_eq!(parse_date("20201001").unwrap(), date);
+    assert_eq!(parse_date("2020-10-01").unwrap(), date);
+    assert!(parse_date("2020/10/01").is_err());
+}
+
+#[test]
+fn reports_anomalies_with_line_numbers() {
+    let dir = TempDir::new("validate_files");
+    let first = dir.join("executions.0000.csv");
+    let second = dir.join("executions.0001.csv");
+    fs::write(&first, "1601510401 B 100 0.01 1 1 1 11\n1601510400 S 100 0.02 2 2 2 12\n").unwrap();
+    // 分割したファイルをまたいだ重複も検出する
+    fs::write(&second, "1601510402 S 100 0.03 3 3 3 11\n1601510402 N 100 0 4 4 4 13\nbroken\n").unwrap();
+
+    let validation = validate_files(DataKind::Executions, &[first.clone(), second.clone()]).unwrap();
+    assert_eq!(validation.lines, 5);
+    let anomalies: Vec<_> = validation
+        .anomalies
+        .iter()
+        .map(|anomaly| (anomaly.path.clone(), anomaly.line, anomaly.kind.clone()))
+        .collect();
+    assert_eq!(
+        anomalies,
+        vec![
+            (first.clone(), 2, AnomalyKind::OutOfOrder),
+            (second.clone(), 1, AnomalyKind::Duplicate { path: first.clone(), line: 1 }),
+            (second.clone(), 2, AnomalyKind::UnknownSide),
+            (second.clone(), 2, AnomalyKind::ZeroSize),
+            (second.clone(), 3, AnomalyKind::Malformed),
+        ]
+    );
+    // 修復したファイルは重複等を除き時刻の順に並べる
+    assert_eq!(
+        validation.repaired,
+        vec!["1601510400 S 100 0.02 2 2 2 12", "1601510401 B 100 0.01 1 1 1 11"]
+    );
+}
+
+#[test]
+fn validates_date_range_and_writes_repaired_copies() {
+    let output_dir = TempDir::new("validate_range");
+    let recorder = Recorder::new(&output_dir.display().to_string(), "bitFlyer");
+    let from = NaiveDate::from_ymd_opt(2020, 10, 1).unwrap();
+    let to = NaiveDate::from_ymd_opt(2020, 10, 2).unwrap();
+
+    let executions = recorder.channel_path(to, "", EXECUTIONS);
+    fs::create_dir_all(executions.parent().unwrap()).unwrap();
+    fs::write(&executions, "1601596801 B 100 0.01 1 1 1 2\n1601596800 S 100 0.02 2 2 2 1\n").unwrap();
+    let latency = recorder.channel_path(to, "latency_", EXECUTIONS);
+    // 遅延データは同じ内容の行があっても重複としない
+    fs::write(
+        &latency,
+        "1601596800000000001 12.500 1 1\n1601596800000000001 12.500 1 1\n1601596800000000000 12.500 2 2\n",
+    )
+    .unwrap();
+
+    let repair_dir = output_dir.join("repaired");
+    let anomalies = validate_range(&recorder, &[EXECUTIONS.to_string()], from, to, Some(&repair_dir)).unwrap();
+    assert_eq!(anomalies.len(), 2);
+    assert_eq!((anomalies[0].line, anomalies[0].kind.clone()), (2, AnomalyKind::OutOfOrder));
+    assert_eq!((anomalies[1].path.clone(), anomalies[1].line), (latency, 3));
+    assert_eq!(anomalies[1].kind, AnomalyKind::OutOfOrder);
+
+    let repaired = fs::read_to_string(repair_dir.join(format!("bitFlyer/20201002/{}.csv", EXECUTIONS))).unwrap();
+    assert_eq!(repaired, "1601596800 S 100 0.02 2 2 2 1\n1601596801 B 100 0.01 1 1 1 2\n");
+    let repaired = fs::read_to_string(repair_dir.join(format!("bitFlyer/20201002/latency_{}.csv", EXECUTIONS))).unwrap();
+    assert_eq!(
+        repaired,
+        "1601596800000000000 12.500 2 2\n1601596800000000001 12.500 1 1\n1601596800000000001 12.500 1 1\n"
+    );
+}
+
+#[test]
+fn validates_files_in_baseline_format() {
+    let dir = TempDir::new("validate_baseline");
+    // 受信時刻・約定IDのない約定データと、受信時刻(ミリ秒)と遅延のみの遅延データ
+    let executions = dir.join("executions.csv");
+    fs::write(
+        &executions,
+        "1601510401 B 100 0.01\n1601510400 S 100 0.02\n1601510400 S 100 0.02\n1601510402 B 100 0.03 3 3\n",
+    )
+    .unwrap();
+    let latency = dir.join("latency.csv");
+    fs::write(&latency, "1601510400000 12.5\n1601510401000 10.0\n1601510402000\n").unwrap();
+
+    let validation = validate_files(DataKind::Executions, std::slice::from_ref(&executions)).unwrap();
+    let anomalies: Vec<_> = validation.anomalies.iter().map(|anomaly| (anomaly.line, anomaly.kind.clone())).collect();
+    // 約定IDがない場合は同じ内容の行を重複の可能性として報告し、修復では除かない
+    assert_eq!(
+        anomalies,
+        vec![
+            (2, AnomalyKind::OutOfOrder),
+            (3, AnomalyKind::PossibleDuplicate { path: executions.clone(), line: 2 }),
+            (3, AnomalyKind::OutOfOrder),
+        ]
+    );
+    assert_eq!(
+        validation.repaired,
+        vec![
+            "1601510400 S 100 0.02",
+            "1601510400 S 100 0.02",
+            "1601510401 B 100 0.01",
+            "1601510402 B 100 0.03 3 3"
+        ]
+    );
+
+    let validation = validate_files(DataKind::Latency, &[latency]).unwrap();
+    let anomalies: Vec<_> = validation.anomalies.iter().map(|anomaly| (anomaly.line, anomaly.kind.clone())).collect();
+    assert_eq!(anomalies, vec![(3, AnomalyKind::Malformed)]);
+    assert_eq!(validation.repaired, vec!["1601510400000 12.5", "1601510401000 10.0"]);
+}
+
+#[test]
+fn keeps_latency_of_executions_in_same_frame() {
+    // 同じ約定日時の複数の約定を含むフレームは、約定毎に同じ内容の遅延データを書き込む
+    let mut parser = FrameParser::new(vec![EXECUTIONS.to_string()], vec![], vec![], ClockOffset::default());
+    let frame = json!({
+        "jsonrpc": "2.0",
+        "method": "channelMessage",
+        "params": {"channel": EXECUTIONS, "message": [
+            {"id": 1, "side": "BUY", "price": 1100000.0, "size": 0.01, "exec_date": "2020-10-01T00:00:00.5Z"},
+            {"id": 2, "side": "BUY", "price": 1100001.0, "size": 0.01, "exec_date": "2020-10-01T00:00:00.5Z"},
+            {"id": 3, "side": "BUY", "price": 1100002.0, "size": 0.01, "exec_date": "2020-10-01T00:00:00.5Z"}
+        ]},
+    });
+    let output_dir = TempDir::new("validate_frame");
+    let mut recorder = Recorder::new(&output_dir.display().to_string(), "bitFlyer");
+    let receive_nanos = 1_601_510_400_600_000_000;
+    let receive_time = ReceiveTime::from_nanos(receive_nanos, 1, receive_nanos);
+    for message in parser.parse(&frame.to_string(), receive_time, 1) {
+        recorder.record(&message);
+    }
+    let date = NaiveDate::from_ymd_opt(2020, 10, 1).unwrap();
+    let latency = recorder.channel_path(date, "latency_", EXECUTIONS);
+    let lines = fs::read_to_string(&latency).unwrap();
+    assert_eq!(lines.lines().count(), 3);
+    assert!(lines.lines().all(|line| Some(line) == lines.lines().next()));
+
+    let repair_dir = output_dir.join("repaired");
+    let anomalies = validate_range(&recorder, &[EXECUTIONS.to_string()], date, date, Some(&repair_dir)).unwrap();
+    assert!(anomalies.is_empty(), "{:?}", anomalies);
+    let repaired = fs::read_to_string(repair_dir.join(format!("bitFlyer/20201001/latency_{}.csv", EXECUTIONS))).unwrap();
+    assert_eq!(repaired, lines);
+}